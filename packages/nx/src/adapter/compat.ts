@@ -86,6 +86,10 @@ export const allowedWorkspaceExtensions = [
   'migrate',
   'useLegacyCache',
   'maxCacheSize',
+  'cacheEviction',
+  'maxTerminalOutputSize',
+  'maxCacheFileSize',
+  'maxCacheEntrySize',
   'tui',
   'owners',
 ] as const;
@@ -38,6 +38,7 @@ describe('show target inputs', () => {
         environment: ['NX_CLOUD_ENCRYPTION_KEY'],
         depOutputs: [],
         external: [],
+        git: [],
       },
     });
 
@@ -77,6 +78,7 @@ describe('show target inputs', () => {
         environment: [],
         depOutputs: [],
         external: [],
+        git: [],
       },
     });
 
@@ -115,6 +117,7 @@ describe('show target inputs', () => {
         environment: [],
         depOutputs: [],
         external: [],
+        git: [],
       },
     });
 
@@ -175,6 +178,7 @@ describe('show target inputs', () => {
         environment: [],
         depOutputs: [],
         external: [],
+        git: [],
       },
     });
 
@@ -215,6 +219,7 @@ describe('show target inputs', () => {
         environment: ['CI', 'NX_CLOUD_ENCRYPTION_KEY'],
         depOutputs: [],
         external: [],
+        git: [],
       },
     });
 
@@ -276,6 +281,7 @@ describe('show target inputs', () => {
         environment: [],
         depOutputs: [],
         external: [],
+        git: [],
       },
     });
 
@@ -335,6 +341,7 @@ describe('show target inputs', () => {
         environment: [],
         depOutputs: [],
         external: [],
+        git: [],
       },
     });
 
@@ -383,6 +390,7 @@ describe('show target inputs', () => {
         environment: [],
         depOutputs: ['dist/libs/dep/index.d.ts'],
         external: [],
+        git: [],
       },
     });
 
@@ -425,6 +433,7 @@ describe('show target inputs', () => {
         environment: [],
         depOutputs: [],
         external: [],
+        git: [],
       },
     });
 
@@ -465,6 +474,7 @@ describe('show target inputs', () => {
         environment: [],
         depOutputs: [],
         external: [],
+        git: [],
       },
     });
 
@@ -504,6 +514,7 @@ describe('show target inputs', () => {
         environment: [],
         depOutputs: [],
         external: [],
+        git: [],
       },
     });
 
@@ -548,6 +559,7 @@ describe('show target inputs', () => {
         environment: ['CI'],
         depOutputs: [],
         external: [],
+        git: [],
       },
     });
 
@@ -593,6 +605,7 @@ describe('show target inputs', () => {
         environment: [],
         depOutputs: [],
         external: [],
+        git: [],
       },
     });
 
@@ -635,6 +648,7 @@ describe('show target inputs', () => {
         environment: [],
         depOutputs: [],
         external: [],
+        git: [],
       },
     });
 
@@ -116,6 +116,7 @@ function renderInputs(
   printList('External dependencies', [...data.external].sort());
   printList('Runtime inputs', [...data.runtime].sort());
   printList('Environment variables', [...data.environment].sort());
+  printList('Git inputs', [...data.git].sort());
   printList(
     `Files (${data.files.length})`,
     [...data.files, ...data.depOutputs].sort()
@@ -830,6 +830,40 @@ export interface NxMigrateConfiguration {
   validate?: boolean;
 }
 
+/**
+ * How entries are evicted from the local cache.
+ */
+export interface NxCacheEvictionConfiguration {
+  /**
+   * Which entries are removed first once the cache exceeds `maxCacheSize` or
+   * a project exceeds its quota. `lru` removes the least recently used
+   * entries, `lfu` the least frequently used ones. Defaults to `lru`.
+   * The `NX_CACHE_EVICTION_STRATEGY` environment variable takes precedence.
+   */
+  strategy?: 'lru' | 'lfu';
+
+  /**
+   * How long an entry is kept after it was last used, as a number of seconds
+   * or a number followed by a unit (s, m, h, d, w). Defaults to `7d`.
+   * The `NX_CACHE_MAX_AGE` environment variable takes precedence.
+   */
+  maxAge?: string;
+
+  /**
+   * How long an entry of a failed task is kept after it was last used.
+   * Accepts the same values as `maxAge`. Defaults to `1d`, or `maxAge` if
+   * that is shorter.
+   * The `NX_CACHE_FAILED_MAX_AGE` environment variable takes precedence.
+   */
+  failedMaxAge?: string;
+
+  /**
+   * Maximum size of the cache entries of individual projects, keyed by
+   * project name. Accepts the same values as `maxCacheSize`.
+   */
+  projectQuotas?: Record<string, string>;
+}
+
 /**
  * Nx.json configuration
  *
@@ -1015,6 +1049,26 @@ export interface NxJsonConfiguration<T = '*' | string[]> {
    */
   maxCacheSize?: string;
 
+  /**
+   * Configures how entries are removed from the local cache.
+   */
+  cacheEviction?: NxCacheEvictionConfiguration;
+
+  /**
+   * Sets the size above which terminal outputs stored in the local cache are truncated, keeping their beginning and end. Accepts the same values as `maxCacheSize`.
+   */
+  maxTerminalOutputSize?: string;
+
+  /**
+   * Sets the size above which output files are left out of the local cache. Accepts the same values as `maxCacheSize`.
+   */
+  maxCacheFileSize?: string;
+
+  /**
+   * Sets the size above which the outputs of a task are not stored in the local cache at all. Accepts the same values as `maxCacheSize`.
+   */
+  maxCacheEntrySize?: string;
+
   /**
    * Settings for the Nx Terminal User Interface (TUI)
    */
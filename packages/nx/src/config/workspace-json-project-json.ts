@@ -1,4 +1,4 @@
-import type { JsonInput } from '../native';
+import type { GitInput, JsonInput, TomlInput, YamlInput } from '../native';
 import type { PackageJson } from '../utils/package-json';
 import type {
   NxJsonConfiguration,
@@ -212,14 +212,41 @@ export type InputDefinition =
   | { input: string; projects: string | string[] }
   | { input: string; dependencies: true }
   | { input: string }
-  | { fileset: string }
-  | { fileset: string; dependencies: true }
-  | { runtime: string }
+  | {
+      fileset: string;
+      /**
+       * Hash a canonical form of the files, ignoring formatting and comments, including pragmas like `@ts-ignore`.
+       */
+      semantic?: boolean;
+    }
+  | { fileset: string; dependencies: true; semantic?: boolean }
+  | {
+      runtime: string;
+      /**
+       * Milliseconds the command may run before hashing fails. Defaults to 60 seconds.
+       */
+      timeout?: number;
+      /**
+       * Whether a failing command fails hashing or has its exit code hashed. Defaults to 'hashExitCode'.
+       */
+      onFailure?: 'error' | 'hashExitCode';
+      /**
+       * Run the command with only the environment variables needed to find and run it.
+       */
+      minimalEnv?: boolean;
+      /**
+       * Keep the hash of the output across runs, as long as the command and its environment stay the same.
+       */
+      cacheAcrossRuns?: boolean;
+    }
   | { externalDependencies: string[] }
   | { dependentTasksOutputFiles: string; transitive?: boolean }
   | { env: string }
   | { workingDirectory: 'relative' | 'absolute' }
-  | JsonInput;
+  | JsonInput
+  | GitInput
+  | YamlInput
+  | TomlInput;
 
 /**
  * Target's configuration
@@ -94,6 +94,7 @@ function makeHashInputs(files: string[]): HashInputs {
     environment: [],
     depOutputs: [],
     external: [],
+    git: [],
   };
 }
 
@@ -1,7 +1,7 @@
 import { NxJsonConfiguration, readNxJson } from '../config/nx-json';
 import { ProjectGraph } from '../config/project-graph';
 import { Task, TaskGraph } from '../config/task-graph';
-import { IS_WASM, TaskDetails } from '../native';
+import { HashedTask, IS_WASM, TaskDetails } from '../native';
 import { readProjectsConfigurationFromProjectGraph } from '../project-graph/project-graph';
 import { getTaskIOService } from '../tasks-runner/task-io-service';
 import { getTaskSpecificEnv } from '../tasks-runner/task-env';
@@ -72,14 +72,7 @@ export async function hashTasksThatDoNotDependOnOutputsOfOtherTasks(
     }
   }
   if (tasksDetails?.recordTaskDetails) {
-    tasksDetails.recordTaskDetails(
-      tasksToHash.map((task) => ({
-        hash: task.hash,
-        project: task.target.project,
-        target: task.target.target,
-        configuration: task.target.configuration,
-      }))
-    );
+    tasksDetails.recordTaskDetails(tasksToHash.map(toHashedTask));
   }
 
   performance.mark('hashMultipleTasks:end');
@@ -125,14 +118,7 @@ export async function hashTask(
   }
 
   if (taskDetails?.recordTaskDetails) {
-    taskDetails.recordTaskDetails([
-      {
-        hash: task.hash,
-        project: task.target.project,
-        target: task.target.target,
-        configuration: task.target.configuration,
-      },
-    ]);
+    taskDetails.recordTaskDetails([toHashedTask(task)]);
   }
 
   performance.mark('hashSingleTask:end');
@@ -239,12 +225,7 @@ export async function hashTasks(
         continue;
       }
 
-      hashedTasks.push({
-        hash: t.hash,
-        project: t.target.project,
-        target: t.target.target,
-        configuration: t.target.configuration,
-      });
+      hashedTasks.push(toHashedTask(t));
     }
     if (hashedTasks.length > 0) {
       taskDetails.recordTaskDetails(hashedTasks);
@@ -258,3 +239,18 @@ export async function hashTasks(
     'hashMultipleTasks:end'
   );
 }
+
+function toHashedTask(task: Task): HashedTask {
+  const details = task.hashDetails;
+  return {
+    hash: task.hash,
+    project: task.target.project,
+    target: task.target.target,
+    configuration: task.target.configuration,
+    // Custom hashers are untrusted, only well-formed details are recorded
+    details:
+      typeof details?.command === 'string' && details.nodes
+        ? details
+        : undefined,
+  };
+}
@@ -216,6 +216,7 @@ describe('native task hasher', () => {
               "nx.json",
               "tsconfig.base.json",
             ],
+            "git": [],
             "runtime": [
               "echo runtime123",
             ],
@@ -451,6 +452,7 @@ describe('native task hasher', () => {
             "nx.json",
             "tsconfig.base.json",
           ],
+          "git": [],
           "runtime": [],
         },
         "value": "10262178246623018030",
@@ -552,6 +554,7 @@ describe('native task hasher', () => {
             "nx.json",
             "tsconfig.base.json",
           ],
+          "git": [],
           "runtime": [],
         },
         "value": "14320402761058545796",
@@ -897,6 +900,7 @@ describe('native task hasher', () => {
               "nx.json",
               "tsconfig.base.json",
             ],
+            "git": [],
             "runtime": [],
           },
           "value": "2453961902871518313",
@@ -926,6 +930,7 @@ describe('native task hasher', () => {
               "nx.json",
               "tsconfig.base.json",
             ],
+            "git": [],
             "runtime": [],
           },
           "value": "5894031627295207190",
@@ -1049,6 +1054,7 @@ describe('native task hasher', () => {
               "nx.json",
               "tsconfig.base.json",
             ],
+            "git": [],
             "runtime": [],
           },
           "value": "12394084267697729491",
@@ -1122,6 +1128,7 @@ describe('native task hasher', () => {
             "nx.json",
             "tsconfig.base.json",
           ],
+          "git": [],
           "runtime": [],
         },
         "value": "16657264716563422624",
@@ -1223,6 +1230,7 @@ describe('native task hasher', () => {
             "nx.json",
             "tsconfig.base.json",
           ],
+          "git": [],
           "runtime": [],
         },
         "value": "1325637283470296766",
@@ -1268,6 +1276,7 @@ describe('native task hasher', () => {
             "nx.json",
             "tsconfig.base.json",
           ],
+          "git": [],
           "runtime": [],
         },
         "value": "1325637283470296766",
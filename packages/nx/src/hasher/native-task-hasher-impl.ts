@@ -102,4 +102,17 @@ export class NativeTaskHasherImpl implements TaskHasherImpl {
     );
     return tasks.map((t) => hashes[t.id]);
   }
+
+  /**
+   * Keeps expensive instruction hashes across runs. With
+   * `NX_RECORD_HASH_DETAILS=true`, also records the files of the file sets
+   * hashed from now on, so that hash changes can be explained down to the
+   * file. Recording writes every file set to the database, so it is opt-in.
+   */
+  useDbConnection(db: ExternalObject<any>) {
+    if (process.env.NX_RECORD_HASH_DETAILS === 'true') {
+      this.hasher.recordDetails(db);
+    }
+    this.hasher.cacheInstructionHashes(db);
+  }
 }
@@ -11,8 +11,9 @@ import { InputDefinition } from '../config/workspace-json-project-json';
 import { minimatch } from 'minimatch';
 import { NativeTaskHasherImpl } from './native-task-hasher-impl';
 import { workspaceRoot } from '../utils/workspace-root';
-import { HashInputs, NxWorkspaceFilesExternals } from '../native';
+import { HashInputs, IS_WASM, NxWorkspaceFilesExternals } from '../native';
 import { getTaskIOService } from '../tasks-runner/task-io-service';
+import { getDbConnection } from '../utils/db-connection';
 
 // Re-export HashInputs from native module for public API
 export { HashInputs };
@@ -188,7 +189,7 @@ export class InProcessTaskHasher implements TaskHasher {
     private readonly externalRustReferences: NxWorkspaceFilesExternals | null,
     private readonly options: any
   ) {
-    this.taskHasher = new NativeTaskHasherImpl(
+    const taskHasher = new NativeTaskHasherImpl(
       workspaceRoot,
       this.nxJson,
       this.projectGraph,
@@ -197,6 +198,11 @@ export class InProcessTaskHasher implements TaskHasher {
         selectivelyHashTsConfig: this.options?.selectivelyHashTsConfig ?? false,
       }
     );
+    // TODO: Remove the check when wasm supports sqlite
+    if (!IS_WASM) {
+      taskHasher.useDbConnection(getDbConnection());
+    }
+    this.taskHasher = taskHasher;
   }
 
   async hashTasks(
@@ -267,9 +273,16 @@ export class InProcessTaskHasher implements TaskHasher {
 }
 
 export type ExpandedSelfInput =
-  | { fileset: string }
-  | { runtime: string }
+  | { fileset: string; semantic?: boolean }
+  | {
+      runtime: string;
+      timeout?: number;
+      onFailure?: 'error' | 'hashExitCode';
+      minimalEnv?: boolean;
+      cacheAcrossRuns?: boolean;
+    }
   | { env: string }
+  | { git: string; dirty?: boolean }
   | { externalDependencies: string[] };
 export type ExpandedDepsOutput = {
   dependentTasksOutputFiles: string;
@@ -465,7 +478,10 @@ export function expandSingleProjectInputs(
         (d as any).externalDependencies ||
         (d as any).dependentTasksOutputFiles ||
         (d as any).workingDirectory ||
-        (d as any).json
+        (d as any).json ||
+        (d as any).git ||
+        (d as any).yaml ||
+        (d as any).toml
       ) {
         expanded.push(d);
       } else {
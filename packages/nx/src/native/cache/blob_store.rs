@@ -0,0 +1,832 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, trace};
+
+use crate::native::cache::file_ops::{
+    RestoreStrategy, create_dir_all_within, remove_path, restore_file, symlink,
+};
+use crate::native::cache::output_limits::SkippedCacheFile;
+use crate::native::hasher::hash_file_path;
+use crate::native::utils::Normalize;
+
+/// A single file system entry captured by `NxCache::put`.
+///
+/// Paths are relative to the cache entry directory (`<cache>/<hash>`) and use
+/// forward slashes on every platform.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ManifestEntry {
+    Directory {
+        path: String,
+    },
+    File {
+        path: String,
+        hash: String,
+        size: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<u32>,
+    },
+    Symlink {
+        path: String,
+        target: String,
+    },
+}
+
+impl ManifestEntry {
+    pub fn path(&self) -> &str {
+        match self {
+            ManifestEntry::Directory { path }
+            | ManifestEntry::File { path, .. }
+            | ManifestEntry::Symlink { path, .. } => path,
+        }
+    }
+
+    /// Whether this entry is `output` itself or nested underneath it.
+    fn is_within(&self, output: &str) -> bool {
+        let output = output.trim_end_matches('/');
+        let path = self.path();
+        output.is_empty()
+            || output == "."
+            || path == output
+            || path
+                .strip_prefix(output)
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+/// A cache entry whose contents no longer match its manifest.
+#[derive(Debug, Error)]
+#[error("Cache entry failed integrity check: {0}")]
+pub struct IntegrityError(pub String);
+
+/// Lists every entry of a cache entry directory, in the order they were
+/// written (a directory always precedes its children).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn read(path: &Path) -> anyhow::Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(content) => Ok(Some(serde_json::from_slice(&content)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    pub fn blob_hashes(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().filter_map(|entry| match entry {
+            ManifestEntry::File { hash, .. } => Some(hash.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Build a manifest for a plain directory, such as an entry extracted
+    /// from a remote cache, without storing anything.
+    pub fn scan(dir: &Path) -> anyhow::Result<Self> {
+        let mut manifest = Manifest::default();
+        if dir.exists() {
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                manifest.scan_path(&entry.path(), Path::new(&entry.file_name()))?;
+            }
+        }
+        Ok(manifest)
+    }
+
+    fn scan_path(&mut self, src: &Path, relative: &Path) -> anyhow::Result<()> {
+        let path = relative.to_normalized_string();
+        let metadata = fs::symlink_metadata(src)?;
+        if metadata.file_type().is_symlink() {
+            self.entries.push(ManifestEntry::Symlink {
+                path,
+                target: fs::read_link(src)?.to_string_lossy().into_owned(),
+            });
+        } else if metadata.is_dir() {
+            self.entries.push(ManifestEntry::Directory { path });
+            for entry in fs::read_dir(src)? {
+                let entry = entry?;
+                self.scan_path(&entry.path(), &relative.join(entry.file_name()))?;
+            }
+        } else {
+            self.entries.push(ManifestEntry::File {
+                path,
+                hash: hash_path(src)?,
+                size: metadata.len(),
+                mode: file_mode(&metadata),
+            });
+        }
+        Ok(())
+    }
+
+    /// Check that `dir` still holds every entry listed in this manifest.
+    pub fn verify(&self, dir: &Path) -> Result<(), IntegrityError> {
+        self.verify_entries(dir, |path, hash, size| {
+            verify_file(&dir.join(path), path, hash, size)
+        })
+    }
+
+    /// Files are checked in parallel by `check_file`, which is handed the
+    /// entry's relative path, hash and size.
+    fn verify_entries(
+        &self,
+        dir: &Path,
+        check_file: impl Fn(&str, &str, u64) -> Result<(), IntegrityError> + Sync,
+    ) -> Result<(), IntegrityError> {
+        self.entries.par_iter().try_for_each(|entry| match entry {
+            ManifestEntry::Directory { path } => {
+                if dir.join(path).is_dir() {
+                    Ok(())
+                } else {
+                    Err(IntegrityError(format!("{} is not a directory", path)))
+                }
+            }
+            ManifestEntry::Symlink { path, target } => match fs::read_link(dir.join(path)) {
+                Ok(actual) if actual == Path::new(target) => Ok(()),
+                _ => Err(IntegrityError(format!(
+                    "{} is not a symlink to {}",
+                    path, target
+                ))),
+            },
+            ManifestEntry::File {
+                path, hash, size, ..
+            } => check_file(path, hash, *size),
+        })
+    }
+}
+
+/// Check the file at `src` has the size and xxh3 hash recorded for `path`.
+fn verify_file(
+    src: &Path,
+    path: &str,
+    expected_hash: &str,
+    size: u64,
+) -> Result<(), IntegrityError> {
+    verify_file_size(src, path, size)?;
+    if hash_path(src).map_err(|e| unreadable(path, &e))? != expected_hash {
+        return Err(IntegrityError(format!("{} does not match its hash", path)));
+    }
+    Ok(())
+}
+
+/// Check the file at `src` has the size recorded for `path`, without reading it.
+fn verify_file_size(src: &Path, path: &str, size: u64) -> Result<(), IntegrityError> {
+    let actual_size = fs::metadata(src).map_err(|e| unreadable(path, &e))?.len();
+    if actual_size != size {
+        return Err(IntegrityError(format!(
+            "{} is {} bytes, expected {}",
+            path, actual_size, size
+        )));
+    }
+    Ok(())
+}
+
+fn unreadable(path: &str, e: &dyn std::fmt::Display) -> IntegrityError {
+    IntegrityError(format!("unable to read {}: {}", path, e))
+}
+
+fn hash_path(path: &Path) -> anyhow::Result<String> {
+    hash_file_path(path).ok_or_else(|| anyhow::anyhow!("Unable to read {}", path.display()))
+}
+
+/// Content-addressable storage for cached output files.
+///
+/// Every file is stored once under `<cache>/blobs`, keyed by the same xxh3
+/// hash `hasher::hash_file_path` produces. Cache entry directories hold hard
+/// links to the blobs, so `<cache>/<hash>` still looks like a plain copy of
+/// the outputs to remote caches while identical files share their bytes.
+pub struct BlobStore {
+    root: PathBuf,
+}
+
+/// What a `BlobStore::capture` call stored.
+#[derive(Debug, Default)]
+pub struct CaptureResult {
+    pub manifest: Manifest,
+    /// Size of the captured files, whether or not their blobs were new.
+    pub size: i64,
+    /// Bytes added to the cache directory.
+    pub new_bytes: i64,
+    /// Files left out for exceeding the size limit.
+    pub skipped: Vec<SkippedCacheFile>,
+}
+
+impl BlobStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Blobs are sharded by the last two digits of their hash; the hashes are
+    /// decimal, so the leading digits are heavily skewed.
+    pub fn blob_path(&self, hash: &str) -> PathBuf {
+        let shard = &hash[hash.len().saturating_sub(2)..];
+        self.root.join(shard).join(hash)
+    }
+
+    /// Store the file at `src`, returning its hash and whether the blob was
+    /// newly written. Files are hashed and copied in chunks, so outputs of any
+    /// size can be stored.
+    fn insert(&self, src: &Path) -> anyhow::Result<(String, bool)> {
+        let hash = hash_path(src)?;
+        if self.contains(&hash) {
+            trace!("Blob {} already stored", &hash);
+            return Ok((hash, false));
+        }
+
+        let blob = self.blob_path(&hash);
+        let shard = blob.parent().unwrap_or(&self.root);
+        fs::create_dir_all(shard)?;
+        // Copy to a unique temp file and rename it into place so concurrent
+        // writers of the same blob never observe a partially written file.
+        let tmp = shard.join(format!("{}.{}.tmp", &hash, uuid::Uuid::new_v4()));
+        let stored = self.store(src, &tmp);
+        if !matches!(stored, Ok((_, true))) {
+            fs::remove_file(&tmp).ok();
+        }
+        stored
+    }
+
+    /// Copy `src` to `tmp` and rename it into place as the blob of the copied
+    /// bytes. Hashing the copy rather than `src` means a file changing
+    /// underneath us can never end up under the wrong key.
+    fn store(&self, src: &Path, tmp: &Path) -> anyhow::Result<(String, bool)> {
+        let size = fs::copy(src, tmp)?;
+        let hash = hash_path(tmp)?;
+        if self.contains(&hash) {
+            return Ok((hash, false));
+        }
+        let blob = self.blob_path(&hash);
+        fs::create_dir_all(blob.parent().unwrap_or(&self.root))?;
+        fs::rename(tmp, &blob)?;
+        trace!("Stored blob {} ({} bytes)", &hash, size);
+        Ok((hash, true))
+    }
+
+    fn contains(&self, hash: &str) -> bool {
+        fs::symlink_metadata(self.blob_path(hash)).is_ok()
+    }
+
+    /// Copy `output` (relative to `workspace_root`) into `entry_dir`, storing
+    /// file contents as blobs and linking them into place. Files over
+    /// `max_file_size` bytes are skipped.
+    pub fn capture(
+        &self,
+        workspace_root: &Path,
+        output: &str,
+        entry_dir: &Path,
+        max_file_size: Option<u64>,
+        result: &mut CaptureResult,
+    ) -> anyhow::Result<()> {
+        self.capture_path(
+            &workspace_root.join(output),
+            Path::new(output),
+            entry_dir,
+            max_file_size,
+            result,
+        )
+    }
+
+    fn capture_path(
+        &self,
+        src: &Path,
+        relative: &Path,
+        entry_dir: &Path,
+        max_file_size: Option<u64>,
+        result: &mut CaptureResult,
+    ) -> anyhow::Result<()> {
+        let dest = entry_dir.join(relative);
+        let path = relative.to_normalized_string();
+        let metadata = fs::symlink_metadata(src)?;
+
+        if metadata.file_type().is_symlink() {
+            let target = fs::read_link(src)?;
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            remove_path(&dest)?;
+            symlink(&target, &dest)?;
+            result.manifest.entries.push(ManifestEntry::Symlink {
+                path,
+                target: target.to_string_lossy().into_owned(),
+            });
+        } else if metadata.is_dir() {
+            fs::create_dir_all(&dest)?;
+            result
+                .manifest
+                .entries
+                .push(ManifestEntry::Directory { path });
+            for entry in fs::read_dir(src)? {
+                let entry = entry?;
+                self.capture_path(
+                    &entry.path(),
+                    &relative.join(entry.file_name()),
+                    entry_dir,
+                    max_file_size,
+                    result,
+                )?;
+            }
+        } else if max_file_size.is_some_and(|max| metadata.len() > max) {
+            trace!("Skipping {} ({} bytes)", &path, metadata.len());
+            result.skipped.push(SkippedCacheFile {
+                path,
+                size: metadata.len() as i64,
+            });
+        } else {
+            let (hash, written) = self.insert(src)?;
+            let size = metadata.len();
+            result.size += size as i64;
+            if written {
+                result.new_bytes += size as i64;
+            }
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            result.new_bytes += self.link(&hash, &dest)? as i64;
+            result.manifest.entries.push(ManifestEntry::File {
+                path,
+                hash,
+                size,
+                mode: file_mode(&metadata),
+            });
+        }
+        Ok(())
+    }
+
+    /// Hard link a blob to `dest`, falling back to a copy on file systems
+    /// without hard link support. Returns the number of bytes copied.
+    fn link(&self, hash: &str, dest: &Path) -> io::Result<u64> {
+        let blob = self.blob_path(hash);
+        remove_path(dest)?;
+        match fs::hard_link(&blob, dest) {
+            Ok(()) => Ok(0),
+            Err(e) => {
+                trace!("Unable to hard link {:?}, copying instead: {}", dest, e);
+                fs::copy(&blob, dest)
+            }
+        }
+    }
+
+    /// Restore the given expanded outputs into `workspace_root` from blobs.
+    ///
+    /// Mirrors `copy_outputs_into_workspace`: only entries under the declared
+    /// outputs are written, each output replaces whatever was at its path, and
+    /// nothing is written through a symlink. A blob that has gone missing is
+    /// read from the entry directory (`outputs_path`) instead.
+    pub fn restore(
+        &self,
+        workspace_root: &Path,
+        outputs_path: &Path,
+        manifest: &Manifest,
+        expanded_outputs: &[String],
+        strategy: RestoreStrategy,
+    ) -> anyhow::Result<i64> {
+        let mut size = 0;
+        for output in expanded_outputs {
+            let entries = manifest
+                .entries
+                .iter()
+                .filter(|entry| entry.is_within(output))
+                .collect::<Vec<_>>();
+            if entries.is_empty() {
+                trace!("No cached artifact for output {}, skipping", output);
+                continue;
+            }
+
+            let to = workspace_root.join(output);
+            if let Some(parent) = to.parent() {
+                create_dir_all_within(workspace_root, parent)?;
+            }
+            // Never follow or merge into a stale entry at the destination.
+            remove_path(&to)?;
+
+            for entry in entries {
+                let dest = workspace_root.join(entry.path());
+                match entry {
+                    ManifestEntry::Directory { .. } => {
+                        create_dir_all_within(workspace_root, &dest)?;
+                    }
+                    ManifestEntry::Symlink { target, .. } => {
+                        if let Some(parent) = dest.parent() {
+                            create_dir_all_within(workspace_root, parent)?;
+                        }
+                        remove_path(&dest)?;
+                        symlink(target, &dest)?;
+                    }
+                    ManifestEntry::File {
+                        path, hash, mode, ..
+                    } => {
+                        if let Some(parent) = dest.parent() {
+                            create_dir_all_within(workspace_root, parent)?;
+                        }
+                        remove_path(&dest)?;
+                        // Blobs take the mode of whichever output stored
+                        // them first and hard links share it, so only
+                        // read-only outputs are linked, to a blob made
+                        // read-only first.
+                        let strategy = match strategy {
+                            RestoreStrategy::Hardlink if !is_read_only(*mode) => {
+                                RestoreStrategy::Auto
+                            }
+                            strategy => strategy,
+                        };
+                        let blob = self.blob_path(hash);
+                        let src = if blob.exists() {
+                            if strategy == RestoreStrategy::Hardlink {
+                                set_file_mode(&blob, *mode)?;
+                            }
+                            blob
+                        } else {
+                            debug!("Blob {} is missing, restoring {} from entry", hash, path);
+                            outputs_path.join(path)
+                        };
+                        size += restore_file(&src, &dest, strategy)? as i64;
+                        set_file_mode(&dest, *mode)?;
+                    }
+                }
+            }
+        }
+        Ok(size)
+    }
+
+    /// Check that the files `restore` would read for `manifest` exist with
+    /// the recorded sizes. Nothing is read, so this is cheap enough for every
+    /// lookup; `verify` checks the contents.
+    pub fn verify_sizes(
+        &self,
+        outputs_path: &Path,
+        manifest: &Manifest,
+    ) -> Result<(), IntegrityError> {
+        manifest.verify_entries(outputs_path, |path, hash, size| {
+            let blob = self.blob_path(hash);
+            let src = if blob.exists() {
+                blob
+            } else {
+                outputs_path.join(path)
+            };
+            verify_file_size(&src, path, size)
+        })
+    }
+
+    /// Check the files `restore` would read for `manifest` against it.
+    ///
+    /// A blob whose contents don't match its name can never be valid, so it
+    /// is deleted on the spot; otherwise `insert` would keep deduplicating
+    /// new outputs against the corrupt copy.
+    pub fn verify(&self, outputs_path: &Path, manifest: &Manifest) -> Result<(), IntegrityError> {
+        manifest.verify_entries(outputs_path, |path, hash, size| {
+            let blob = self.blob_path(hash);
+            if blob.exists() {
+                verify_file(&blob, path, hash, size).inspect_err(|_| {
+                    debug!("Removing corrupt blob {}", hash);
+                    fs::remove_file(&blob).ok();
+                })
+            } else {
+                verify_file(&outputs_path.join(path), path, hash, size)
+            }
+        })
+    }
+
+    /// Delete every blob no manifest in `manifests_dir` refers to.
+    pub fn collect_garbage(&self, manifests_dir: &Path) -> anyhow::Result<()> {
+        let mut referenced = HashSet::new();
+        if manifests_dir.exists() {
+            for entry in fs::read_dir(manifests_dir)? {
+                let entry = entry?;
+                // A manifest being rewritten concurrently is skipped rather
+                // than failing the sweep; its blobs may be collected, and
+                // restoring then falls back to the entry directory.
+                if let Ok(Some(manifest)) = Manifest::read(&entry.path()) {
+                    referenced.extend(manifest.blob_hashes().map(String::from));
+                }
+            }
+        }
+
+        if !self.root.exists() {
+            return Ok(());
+        }
+
+        let mut removed = 0;
+        for shard in fs::read_dir(&self.root)? {
+            let shard = shard?;
+            if !shard.file_type()?.is_dir() {
+                continue;
+            }
+            for blob in fs::read_dir(shard.path())? {
+                let blob = blob?;
+                let name = blob.file_name();
+                let name = name.to_string_lossy();
+                // In-flight writes belong to a concurrent `put`.
+                if name.ends_with(".tmp") || referenced.contains(name.as_ref()) {
+                    continue;
+                }
+                fs::remove_file(blob.path())?;
+                removed += 1;
+            }
+        }
+        debug!("Removed {} unreferenced blobs", removed);
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Whether a file of `mode` can't be written to. Unknown modes count as
+/// writable.
+fn is_read_only(mode: Option<u32>) -> bool {
+    mode.is_some_and(|mode| mode & 0o222 == 0)
+}
+
+#[cfg(unix)]
+fn set_file_mode(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    match mode {
+        Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_file_mode(_path: &Path, _mode: Option<u32>) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    fn capture(store: &BlobStore, workspace: &TempDir, entry_dir: &Path) -> CaptureResult {
+        let mut result = CaptureResult::default();
+        store
+            .capture(workspace.path(), "dist", entry_dir, None, &mut result)
+            .unwrap();
+        result
+    }
+
+    #[test]
+    fn should_store_identical_files_once() {
+        let workspace = TempDir::new().unwrap();
+        let cache = TempDir::new().unwrap();
+        workspace.child("dist/a.js").write_str("same").unwrap();
+        workspace.child("dist/b.js").write_str("same").unwrap();
+        let store = BlobStore::new(cache.join("blobs"));
+
+        let first = capture(&store, &workspace, &cache.join("1"));
+        let second = capture(&store, &workspace, &cache.join("2"));
+
+        assert_eq!(first.new_bytes, "same".len() as i64);
+        assert_eq!(second.new_bytes, 0);
+        assert_eq!(first.size, second.size);
+        assert_eq!(second.size, 2 * "same".len() as i64);
+        assert_eq!(first.manifest, second.manifest);
+        assert_eq!(
+            std::fs::read_to_string(cache.join("2/dist/b.js")).unwrap(),
+            "same"
+        );
+        let blobs = first.manifest.blob_hashes().collect::<HashSet<_>>();
+        assert_eq!(blobs.len(), 1);
+    }
+
+    #[test]
+    fn should_restore_declared_outputs_from_blobs() {
+        let workspace = TempDir::new().unwrap();
+        let cache = TempDir::new().unwrap();
+        workspace.child("dist/main.js").write_str("main").unwrap();
+        workspace
+            .child("dist/nested/util.js")
+            .write_str("util")
+            .unwrap();
+        let store = BlobStore::new(cache.join("blobs"));
+        let entry_dir = cache.join("123");
+        let captured = capture(&store, &workspace, &entry_dir);
+
+        let target = TempDir::new().unwrap();
+        target.child("dist/stale.js").write_str("stale").unwrap();
+        let size = store
+            .restore(
+                target.path(),
+                &entry_dir,
+                &captured.manifest,
+                &["dist".to_string()],
+                RestoreStrategy::Copy,
+            )
+            .unwrap();
+
+        assert_eq!(size, ("main".len() + "util".len()) as i64);
+        assert_eq!(
+            std::fs::read_to_string(target.join("dist/nested/util.js")).unwrap(),
+            "util"
+        );
+        assert!(!target.child("dist/stale.js").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_only_hardlink_read_only_outputs() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let workspace = TempDir::new().unwrap();
+        let cache = TempDir::new().unwrap();
+        workspace
+            .child("dist/writable.js")
+            .write_str("same")
+            .unwrap();
+        workspace
+            .child("dist/read-only.js")
+            .write_str("same")
+            .unwrap();
+        std::fs::set_permissions(
+            workspace.join("dist/read-only.js"),
+            std::fs::Permissions::from_mode(0o444),
+        )
+        .unwrap();
+        let store = BlobStore::new(cache.join("blobs"));
+        let entry_dir = cache.join("1");
+        let captured = capture(&store, &workspace, &entry_dir);
+        let other_entry_dir = cache.join("2");
+        capture(&store, &workspace, &other_entry_dir);
+        let blob = store.blob_path(captured.manifest.blob_hashes().next().unwrap());
+
+        let target = TempDir::new().unwrap();
+        store
+            .restore(
+                target.path(),
+                &entry_dir,
+                &captured.manifest,
+                &["dist".to_string()],
+                RestoreStrategy::Hardlink,
+            )
+            .unwrap();
+
+        let read_only = std::fs::metadata(target.join("dist/read-only.js")).unwrap();
+        assert_eq!(read_only.ino(), std::fs::metadata(&blob).unwrap().ino());
+        assert!(read_only.permissions().readonly());
+
+        let writable = target.join("dist/writable.js");
+        assert_ne!(std::fs::metadata(&writable).unwrap().ino(), read_only.ino());
+        std::fs::write(&writable, "changed").unwrap();
+        assert_eq!(std::fs::read_to_string(&blob).unwrap(), "same");
+        assert_eq!(
+            std::fs::read_to_string(other_entry_dir.join("dist/writable.js")).unwrap(),
+            "same"
+        );
+        assert!(store.verify(&other_entry_dir, &captured.manifest).is_ok());
+    }
+
+    #[test]
+    fn should_restore_from_entry_when_blob_is_missing() {
+        let workspace = TempDir::new().unwrap();
+        let cache = TempDir::new().unwrap();
+        workspace.child("dist/main.js").write_str("main").unwrap();
+        let store = BlobStore::new(cache.join("blobs"));
+        let entry_dir = cache.join("123");
+        let captured = capture(&store, &workspace, &entry_dir);
+        std::fs::remove_dir_all(cache.join("blobs")).unwrap();
+
+        let target = TempDir::new().unwrap();
+        store
+            .restore(
+                target.path(),
+                &entry_dir,
+                &captured.manifest,
+                &["dist".to_string()],
+                RestoreStrategy::Copy,
+            )
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(target.join("dist/main.js")).unwrap(),
+            "main"
+        );
+    }
+
+    #[test]
+    fn should_verify_intact_entries() {
+        let workspace = TempDir::new().unwrap();
+        let cache = TempDir::new().unwrap();
+        workspace.child("dist/main.js").write_str("main").unwrap();
+        workspace
+            .child("dist/nested/util.js")
+            .write_str("util")
+            .unwrap();
+        let store = BlobStore::new(cache.join("blobs"));
+        let entry_dir = cache.join("123");
+        let captured = capture(&store, &workspace, &entry_dir);
+
+        assert!(store.verify(&entry_dir, &captured.manifest).is_ok());
+        assert!(captured.manifest.verify(&entry_dir).is_ok());
+        assert_eq!(Manifest::scan(&entry_dir).unwrap().blob_hashes().count(), 2);
+    }
+
+    #[test]
+    fn should_reject_and_remove_corrupt_blobs() {
+        let workspace = TempDir::new().unwrap();
+        let cache = TempDir::new().unwrap();
+        workspace.child("dist/main.js").write_str("main").unwrap();
+        let store = BlobStore::new(cache.join("blobs"));
+        let entry_dir = cache.join("123");
+        let captured = capture(&store, &workspace, &entry_dir);
+        let blob = store.blob_path(captured.manifest.blob_hashes().next().unwrap());
+        // Same size, different bytes: only the hash can catch this.
+        std::fs::remove_file(&blob).unwrap();
+        std::fs::write(&blob, "rot!").unwrap();
+
+        assert!(store.verify(&entry_dir, &captured.manifest).is_err());
+        assert!(!blob.exists());
+    }
+
+    #[test]
+    fn should_reject_truncated_files() {
+        let entry_dir = TempDir::new().unwrap();
+        entry_dir.child("dist/main.js").write_str("main").unwrap();
+        let manifest = Manifest::scan(entry_dir.path()).unwrap();
+
+        entry_dir.child("dist/main.js").write_str("ma").unwrap();
+
+        assert!(manifest.verify(entry_dir.path()).is_err());
+    }
+
+    #[test]
+    fn should_skip_files_over_the_size_limit() {
+        let workspace = TempDir::new().unwrap();
+        let cache = TempDir::new().unwrap();
+        let store = BlobStore::new(cache.join("blobs"));
+        workspace.child("dist/main.js").write_str("main").unwrap();
+        workspace
+            .child("dist/core")
+            .write_str(&"x".repeat(100))
+            .unwrap();
+
+        let mut result = CaptureResult::default();
+        store
+            .capture(
+                workspace.path(),
+                "dist",
+                &cache.join("1"),
+                Some(10),
+                &mut result,
+            )
+            .unwrap();
+
+        assert_eq!(
+            result.skipped,
+            vec![SkippedCacheFile {
+                path: "dist/core".into(),
+                size: 100,
+            }]
+        );
+        assert!(cache.child("1/dist/main.js").exists());
+        assert!(!cache.child("1/dist/core").exists());
+        assert_eq!(result.manifest.blob_hashes().count(), 1);
+    }
+
+    #[test]
+    fn should_collect_unreferenced_blobs() {
+        let workspace = TempDir::new().unwrap();
+        let cache = TempDir::new().unwrap();
+        let store = BlobStore::new(cache.join("blobs"));
+        let manifests = cache.join("manifests");
+
+        workspace.child("dist/main.js").write_str("kept").unwrap();
+        let kept = capture(&store, &workspace, &cache.join("1"));
+        kept.manifest.write(&manifests.join("1")).unwrap();
+
+        workspace
+            .child("dist/main.js")
+            .write_str("dropped")
+            .unwrap();
+        let dropped = capture(&store, &workspace, &cache.join("2"));
+
+        store.collect_garbage(&manifests).unwrap();
+
+        let kept_hash = kept.manifest.blob_hashes().next().unwrap();
+        let dropped_hash = dropped.manifest.blob_hashes().next().unwrap();
+        assert!(store.blob_path(kept_hash).exists());
+        assert!(!store.blob_path(dropped_hash).exists());
+    }
+}
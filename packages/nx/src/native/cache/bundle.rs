@@ -0,0 +1,359 @@
+//! Portable bundles of local cache entries, for carrying artifacts between
+//! machines that can't share a remote cache.
+//!
+//! A bundle is an uncompressed tar archive holding one remote cache tarball
+//! per entry (`entries/<hash>.tar.gz`, see `tarball`) followed by
+//! `bundle.json`, which records the task details of every entry.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tar::{Archive, Builder};
+use tracing::{trace, warn};
+
+use super::blob_store::IntegrityError;
+use super::cache::CachedResult;
+use super::provenance::CacheProvenance;
+use super::tarball::{TarballEncoding, TemporaryTarball, extract_verified_tarball, write_tarball};
+
+const BUNDLE_VERSION: u32 = 1;
+const BUNDLE_INDEX: &str = "bundle.json";
+
+/// The outcome of importing a bundle.
+#[napi(object)]
+#[derive(Default, Clone, Debug)]
+pub struct CacheBundleImport {
+    /// Entries added to the cache.
+    pub imported: Vec<String>,
+    /// Entries the cache already held, which are left untouched.
+    pub skipped: Vec<String>,
+    /// Entries that failed verification and were not imported.
+    pub rejected: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleEntry {
+    pub hash: String,
+    pub code: i16,
+    pub project: Option<String>,
+    pub target: Option<String>,
+    pub configuration: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BundleIndex {
+    version: u32,
+    entries: Vec<BundleEntry>,
+}
+
+/// Writes a bundle to a temporary file next to `path`, which is renamed into
+/// place once the bundle is complete.
+pub struct BundleWriter {
+    path: PathBuf,
+    temporary: TemporaryFile,
+    archive: Builder<BufWriter<File>>,
+    entries: Vec<BundleEntry>,
+}
+
+impl BundleWriter {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let temporary = TemporaryFile(path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4())));
+        Ok(Self {
+            path: path.to_path_buf(),
+            archive: Builder::new(BufWriter::new(File::create(&temporary.0)?)),
+            temporary,
+            entries: vec![],
+        })
+    }
+
+    pub fn append(
+        &mut self,
+        entry: BundleEntry,
+        outputs_path: &Path,
+        terminal_output: &str,
+        provenance: &CacheProvenance,
+    ) -> anyhow::Result<()> {
+        let directory = self.path.parent().unwrap_or(Path::new("."));
+        let tarball = TemporaryTarball::new(directory, &entry.hash, TarballEncoding::Gzip);
+        write_tarball(
+            &tarball.path,
+            TarballEncoding::Gzip,
+            TarballEncoding::Gzip.default_level(),
+            outputs_path,
+            terminal_output,
+            entry.code as u32,
+            Some(provenance),
+        )?;
+        self.archive
+            .append_path_with_name(&tarball.path, format!("entries/{}.tar.gz", entry.hash))?;
+        trace!("Added {} to the bundle", entry.hash);
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Write the index and move the bundle into place, returning the
+    /// entries it holds.
+    pub fn finish(self) -> anyhow::Result<Vec<BundleEntry>> {
+        let Self {
+            path,
+            temporary,
+            mut archive,
+            entries,
+        } = self;
+        let index = BundleIndex {
+            version: BUNDLE_VERSION,
+            entries,
+        };
+        let index_bytes = serde_json::to_vec(&index)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(index_bytes.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive.append_data(&mut header, BUNDLE_INDEX, index_bytes.as_slice())?;
+        // The file has to be closed before it can be renamed on Windows.
+        archive.into_inner()?.into_inner()?.sync_all()?;
+        fs::rename(&temporary.0, &path)?;
+        Ok(index.entries)
+    }
+}
+
+/// Removes a file that was not moved into place.
+struct TemporaryFile(PathBuf);
+
+impl Drop for TemporaryFile {
+    fn drop(&mut self) {
+        fs::remove_file(&self.0).ok();
+    }
+}
+
+/// The verified contents of a bundle, extracted into a staging directory.
+#[derive(Default)]
+pub struct ExtractedBundle {
+    pub entries: Vec<(CachedResult, Option<BundleEntry>)>,
+    pub skipped: Vec<String>,
+    pub rejected: Vec<String>,
+}
+
+/// Extract the entries of the bundle at `path` into `<staging>/<hash>`,
+/// verifying each against its manifest. Entries for which `is_cached`
+/// returns true are not extracted.
+pub fn extract_bundle(
+    path: &Path,
+    staging: &Path,
+    is_cached: impl Fn(&str) -> anyhow::Result<bool>,
+) -> anyhow::Result<ExtractedBundle> {
+    let mut archive = Archive::new(BufReader::new(File::open(path)?));
+    let staging_directory = staging.to_string_lossy();
+    let mut extracted = ExtractedBundle::default();
+    let mut results = vec![];
+    let mut index: Option<BundleIndex> = None;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_string_lossy().into_owned();
+        if entry_path == BUNDLE_INDEX {
+            let bundle_index: BundleIndex = serde_json::from_reader(&mut entry)?;
+            if bundle_index.version > BUNDLE_VERSION {
+                anyhow::bail!(
+                    "{} was created by a newer version of Nx (bundle version {})",
+                    path.display(),
+                    bundle_index.version
+                );
+            }
+            index = Some(bundle_index);
+            continue;
+        }
+        let Some(hash) = entry_path
+            .strip_prefix("entries/")
+            .and_then(|name| name.strip_suffix(".tar.gz"))
+            .filter(|hash| is_valid_hash(hash))
+            .map(str::to_string)
+        else {
+            warn!("Ignoring unexpected bundle entry {}", entry_path);
+            continue;
+        };
+
+        if is_cached(&hash)? {
+            trace!("{} is already cached", hash);
+            extracted.skipped.push(hash);
+            continue;
+        }
+        // Bundles always carry manifests, so an entry without one is not
+        // trusted like an artifact from an older remote cache would be.
+        match extract_verified_tarball(&mut entry, TarballEncoding::Gzip, &staging_directory, &hash)
+        {
+            Ok(result) => results.push((hash, result)),
+            Err(e) if e.is::<IntegrityError>() => {
+                warn!("{} ({}), not importing it", e, hash);
+                fs::remove_dir_all(staging.join(&hash)).ok();
+                extracted.rejected.push(hash);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let index = index.ok_or_else(|| {
+        anyhow::anyhow!(
+            "{} is not a cache bundle: {} is missing",
+            path.display(),
+            BUNDLE_INDEX
+        )
+    })?;
+    let mut details = index
+        .entries
+        .into_iter()
+        .map(|entry| (entry.hash.clone(), entry))
+        .collect::<HashMap<_, _>>();
+    extracted.entries = results
+        .into_iter()
+        .map(|(hash, result)| (result, details.remove(&hash)))
+        .collect();
+    Ok(extracted)
+}
+
+/// Hashes become directory names, so only accept plain names.
+fn is_valid_hash(hash: &str) -> bool {
+    !hash.is_empty()
+        && hash
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::native::cache::blob_store::Manifest;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+    use flate2::{Compression, write::GzEncoder};
+
+    fn append<W: Write>(builder: &mut Builder<W>, path: &str, data: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+
+    fn entry(hash: &str) -> BundleEntry {
+        BundleEntry {
+            hash: hash.to_string(),
+            code: 0,
+            project: Some("app".to_string()),
+            target: Some("build".to_string()),
+            configuration: None,
+        }
+    }
+
+    #[test]
+    fn should_only_accept_plain_hashes() {
+        assert!(is_valid_hash("1234567890"));
+        assert!(is_valid_hash("abc_DEF-1"));
+        assert!(!is_valid_hash(""));
+        assert!(!is_valid_hash(".."));
+        assert!(!is_valid_hash("a/b"));
+    }
+
+    #[test]
+    fn should_round_trip_entries() {
+        let temp = TempDir::new().unwrap();
+        temp.child("cache/1/main.js")
+            .write_str("console.log(1)")
+            .unwrap();
+        let bundle = temp.path().join("bundle.tar");
+
+        let mut writer = BundleWriter::create(&bundle).unwrap();
+        writer
+            .append(
+                entry("1"),
+                &temp.path().join("cache/1"),
+                "built",
+                &CacheProvenance::default(),
+            )
+            .unwrap();
+        writer.finish().unwrap();
+        let staging = temp.path().join("staging");
+        let extracted = extract_bundle(&bundle, &staging, |hash| Ok(hash == "2")).unwrap();
+
+        assert_eq!(extracted.entries.len(), 1);
+        let (result, details) = &extracted.entries[0];
+        assert_eq!(result.terminal_output.as_deref(), Some("built"));
+        assert_eq!(details.as_ref(), Some(&entry("1")));
+        assert_eq!(
+            fs::read_to_string(staging.join("1/main.js")).unwrap(),
+            "console.log(1)"
+        );
+        // Only the bundle is left behind.
+        assert_eq!(fs::read_dir(temp.path()).unwrap().count(), 3);
+    }
+
+    /// Write a bundle holding a single entry `1` with the given members.
+    fn write_bundle(temp: &TempDir, members: &[(&str, &[u8])]) -> PathBuf {
+        let mut tarball = Builder::new(GzEncoder::new(vec![], Compression::default()));
+        for (path, data) in members {
+            append(&mut tarball, path, data);
+        }
+        let tarball = tarball.into_inner().unwrap().finish().unwrap();
+        let index = serde_json::to_vec(&BundleIndex {
+            version: BUNDLE_VERSION,
+            entries: vec![entry("1")],
+        })
+        .unwrap();
+        let mut bundle = Builder::new(vec![]);
+        append(&mut bundle, "entries/1.tar.gz", &tarball);
+        append(&mut bundle, BUNDLE_INDEX, &index);
+        let bundle_path = temp.path().join("bundle.tar");
+        fs::write(&bundle_path, bundle.into_inner().unwrap()).unwrap();
+        bundle_path
+    }
+
+    #[test]
+    fn should_reject_tampered_entries() {
+        let temp = TempDir::new().unwrap();
+        temp.child("original/main.js")
+            .write_str("console.log(1)")
+            .unwrap();
+        let manifest =
+            serde_json::to_vec(&Manifest::scan(&temp.path().join("original")).unwrap()).unwrap();
+        let bundle_path = write_bundle(
+            &temp,
+            &[
+                ("main.js", b"console.log(2)"),
+                ("terminalOutput", b"built"),
+                ("manifest", &manifest),
+                ("code", &0u32.to_be_bytes()),
+            ],
+        );
+
+        let staging = temp.path().join("staging");
+        let extracted = extract_bundle(&bundle_path, &staging, |_| Ok(false)).unwrap();
+
+        assert!(extracted.entries.is_empty());
+        assert_eq!(extracted.rejected, vec!["1"]);
+        assert!(!staging.join("1").exists());
+    }
+
+    #[test]
+    fn should_reject_entries_without_a_manifest() {
+        let temp = TempDir::new().unwrap();
+        let bundle_path = write_bundle(
+            &temp,
+            &[
+                ("main.js", b"console.log(1)"),
+                ("terminalOutput", b"built"),
+                ("code", &0u32.to_be_bytes()),
+            ],
+        );
+
+        let staging = temp.path().join("staging");
+        let extracted = extract_bundle(&bundle_path, &staging, |_| Ok(false)).unwrap();
+
+        assert!(extracted.entries.is_empty());
+        assert_eq!(extracted.rejected, vec!["1"]);
+        assert!(!staging.join("1").exists());
+    }
+}
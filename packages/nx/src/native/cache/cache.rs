@@ -1,17 +1,28 @@
-use std::fs::{create_dir_all, read_to_string, write};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs::create_dir_all;
 use std::path::{Component, Path, PathBuf};
 use std::rc::Rc;
-use std::time::Instant;
-use tracing::{debug, trace};
+use std::time::{Duration, Instant};
+use tracing::{debug, trace, warn};
 
 use fs_extra::remove_items;
 use rayon::prelude::*;
 use regex::Regex;
-use rusqlite::{params, types::Value};
+use rusqlite::{params, params_from_iter, types::Value};
 use sysinfo::Disks;
 
+use crate::native::cache::blob_store::{BlobStore, CaptureResult, Manifest};
+use crate::native::cache::bundle::{BundleEntry, BundleWriter, CacheBundleImport, extract_bundle};
+use crate::native::cache::eviction::CacheEvictionPolicy;
 use crate::native::cache::expand_outputs::_expand_outputs;
-use crate::native::cache::file_ops::{_copy, copy_outputs_into_workspace};
+use crate::native::cache::file_ops::{RestoreStrategy, copy_outputs_into_workspace};
+use crate::native::cache::fsck::{
+    CacheFsckReport, CacheSizeMismatch, IN_FLIGHT_GRACE_PERIOD, directory_size, is_hash, is_settled,
+};
+use crate::native::cache::inventory::{CacheEntry, CacheEntryPage, CacheEntryQuery};
+use crate::native::cache::output_limits::{CacheOutputLimits, CachePutResult, measure};
+use crate::native::cache::provenance::{self, CacheProvenance};
+use crate::native::cache::terminal_output::TerminalOutputStore;
 use crate::native::db::connection::NxDbConnection;
 use crate::native::utils::Normalize;
 use napi::bindgen_prelude::External;
@@ -24,6 +35,7 @@ pub struct CachedResult {
     pub terminal_output: Option<String>,
     pub outputs_path: String,
     pub size: Option<i64>,
+    pub provenance: Option<CacheProvenance>,
 }
 
 #[napi]
@@ -31,9 +43,16 @@ pub struct NxCache {
     pub cache_directory: String,
     workspace_root: PathBuf,
     cache_path: PathBuf,
+    blobs: BlobStore,
+    terminal_outputs: TerminalOutputStore,
     db: Arc<Mutex<NxDbConnection>>,
     link_task_details: bool,
     max_cache_size: i64,
+    eviction_policy: CacheEvictionPolicy,
+    restore_strategy: RestoreStrategy,
+    /// The provenance of entries produced on this machine.
+    provenance: CacheProvenance,
+    output_limits: CacheOutputLimits,
 }
 
 #[napi]
@@ -48,11 +67,16 @@ impl NxCache {
         // TODO: this is unused by Nx but still required by Nx Cloud
         link_task_details: Option<bool>,
         max_cache_size: Option<i64>,
+        eviction_policy: Option<CacheEvictionPolicy>,
+        max_terminal_output_size: Option<i64>,
+        provenance: Option<CacheProvenance>,
+        output_limits: Option<CacheOutputLimits>,
     ) -> anyhow::Result<Self> {
         let cache_path = PathBuf::from(&cache_path);
 
         create_dir_all(&cache_path)?;
         create_dir_all(cache_path.join("terminalOutputs"))?;
+        create_dir_all(cache_path.join("manifests"))?;
 
         let max_cache_size = max_cache_size.unwrap_or(0);
 
@@ -60,9 +84,18 @@ impl NxCache {
             db: Arc::clone(db_connection),
             workspace_root: PathBuf::from(workspace_root),
             cache_directory: cache_path.to_normalized_string(),
+            blobs: BlobStore::new(cache_path.join("blobs")),
+            terminal_outputs: TerminalOutputStore::new(
+                cache_path.join("terminalOutputs"),
+                max_terminal_output_size,
+            ),
             cache_path,
             link_task_details: link_task_details.unwrap_or(true),
             max_cache_size,
+            eviction_policy: eviction_policy.unwrap_or_default(),
+            restore_strategy: RestoreStrategy::from_env(),
+            provenance: CacheProvenance::local(provenance),
+            output_limits: output_limits.unwrap_or_default(),
         };
 
         r.setup()?;
@@ -78,6 +111,13 @@ impl NxCache {
                 size   INTEGER NOT NULL,
                 created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
                 accessed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                hits   INTEGER NOT NULL DEFAULT 0,
+                machine_id TEXT,
+                git_commit TEXT,
+                git_branch TEXT,
+                nx_version TEXT,
+                ci_run_id TEXT,
+                origin TEXT,
                 FOREIGN KEY (hash) REFERENCES task_details (hash)
             );
             "
@@ -87,7 +127,14 @@ impl NxCache {
                 code   INTEGER NOT NULL,
                 size   INTEGER NOT NULL,
                 created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-                accessed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+                accessed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                hits   INTEGER NOT NULL DEFAULT 0,
+                machine_id TEXT,
+                git_commit TEXT,
+                git_branch TEXT,
+                nx_version TEXT,
+                ci_run_id TEXT,
+                origin TEXT
             );
             "
         };
@@ -107,22 +154,38 @@ impl NxCache {
 
         // Direct primary-key lookup — cheaper per call than routing through
         // fetch_cache_rows() + rarray for a single hash.
-        let row_data: Option<(i16, i64)> = self
+        let row_data: Option<(i16, i64, CacheProvenance)> = self
             .db
             .lock()
             .unwrap()
             .query_row(
-                "UPDATE cache_outputs
-                    SET accessed_at = CURRENT_TIMESTAMP
-                    WHERE hash = ?1
-                    RETURNING code, size",
+                &format!(
+                    "SELECT code, size, {} FROM cache_outputs WHERE hash = ?1",
+                    provenance::COLUMNS
+                ),
                 params![hash],
-                |row| Ok((row.get::<_, i16>(0)?, row.get::<_, i64>(1)?)),
+                |row| {
+                    Ok((
+                        row.get::<_, i16>(0)?,
+                        row.get::<_, i64>(1)?,
+                        CacheProvenance::from_row(row, 2)?,
+                    ))
+                },
             )
             .map_err(|e| anyhow::anyhow!("Unable to get {}: {:?}", &hash, e))?;
 
         // Terminal output file read happens AFTER the lock is released.
-        let result = row_data.map(|(code, size)| self.build_cached_result(&hash, code, size));
+        let result = match row_data {
+            Some(_) if !self.verify_entry_sizes(&hash) => {
+                self.evict_corrupt_entry(&hash);
+                None
+            }
+            Some((code, size, provenance)) => {
+                self.record_hits(std::slice::from_ref(&hash))?;
+                Some(self.build_cached_result(&hash, code, size, provenance))
+            }
+            None => None,
+        };
 
         trace!("GET {} {:?}", &hash, start.elapsed());
         Ok(result)
@@ -137,25 +200,41 @@ impl NxCache {
             return Ok(vec![]);
         }
 
-        // 1. One SQL round-trip: look up every hash and bump accessed_at.
+        // 1. One SQL round-trip: look up every hash.
         let rows = self.fetch_cache_rows(&hashes)?;
 
-        // 2. For each requested hash, read its terminal output file in
-        //    parallel. Misses stay as None so callers can correlate by index.
-        let results = hashes
+        // 2. For each requested hash, check the entry and read its terminal
+        //    output file in parallel. Misses stay as None so callers can
+        //    correlate by index.
+        let results: Vec<Option<CachedResult>> = hashes
             .par_iter()
             .map(|hash| {
                 rows.get(hash)
-                    .map(|&(code, size)| self.build_cached_result(hash, code, size))
+                    .filter(|_| self.verify_entry_sizes(hash))
+                    .map(|(code, size, provenance)| {
+                        self.build_cached_result(hash, *code, *size, provenance.clone())
+                    })
             })
             .collect();
 
+        // 3. Entries that failed the check are evicted and reported as
+        //    misses, the others have accessed_at and hits bumped.
+        let mut hits = vec![];
+        for (hash, result) in hashes.iter().zip(&results) {
+            if result.is_some() {
+                hits.push(hash.clone());
+            } else if rows.contains_key(hash) {
+                self.evict_corrupt_entry(hash);
+            }
+        }
+        self.record_hits(&hits)?;
+
         trace!("GET_BATCH {} hashes {:?}", hashes.len(), start.elapsed());
         Ok(results)
     }
 
-    /// Runs one `UPDATE ... RETURNING` across every requested hash and
-    /// returns the matching rows keyed by hash.
+    /// Runs one `SELECT` across every requested hash and returns the
+    /// matching rows keyed by hash.
     ///
     /// Uses `rarray` to bind the whole Vec as a single parameter so the SQL
     /// text is constant regardless of batch size — the prepared-statement
@@ -164,7 +243,7 @@ impl NxCache {
     fn fetch_cache_rows(
         &self,
         hashes: &[String],
-    ) -> anyhow::Result<std::collections::HashMap<String, (i16, i64)>> {
+    ) -> anyhow::Result<std::collections::HashMap<String, (i16, i64, CacheProvenance)>> {
         let values = Rc::new(
             hashes
                 .iter()
@@ -182,15 +261,17 @@ impl NxCache {
             .lock()
             .unwrap()
             .query_map(
-                "UPDATE cache_outputs SET accessed_at = CURRENT_TIMESTAMP
-                 WHERE hash IN rarray(?1)
-                 RETURNING hash, code, size",
+                &format!(
+                    "SELECT hash, code, size, {} FROM cache_outputs WHERE hash IN rarray(?1)",
+                    provenance::COLUMNS
+                ),
                 [values],
                 |row| {
                     let hash: String = row.get(0)?;
                     let code: i16 = row.get(1)?;
                     let size: i64 = row.get(2)?;
-                    Ok((hash, (code, size)))
+                    let provenance = CacheProvenance::from_row(row, 3)?;
+                    Ok((hash, (code, size, provenance)))
                 },
             )?
             .into_iter()
@@ -198,20 +279,98 @@ impl NxCache {
         Ok(rows)
     }
 
+    /// Bump accessed_at and hits of the entries that were hit.
+    fn record_hits(&self, hashes: &[String]) -> anyhow::Result<()> {
+        if hashes.is_empty() {
+            return Ok(());
+        }
+        let values = Rc::new(
+            hashes
+                .iter()
+                .map(|h| Value::from(h.clone()))
+                .collect::<Vec<Value>>(),
+        );
+        self.db.lock().unwrap().execute(
+            "UPDATE cache_outputs SET accessed_at = CURRENT_TIMESTAMP, hits = hits + 1
+             WHERE hash IN rarray(?1)",
+            [values],
+        )?;
+        Ok(())
+    }
+
+    /// Check a hit against the manifest written when it was stored, so a
+    /// half-written entry is never reported as a hit. Only the sizes are
+    /// compared, so that lookups don't read outputs they may never restore;
+    /// `copy_files_from_cache` checks the contents. Only entries stored by
+    /// versions of Nx that didn't write manifests lack one.
+    fn verify_entry_sizes(&self, hash: &str) -> bool {
+        let manifest = match Manifest::read(&self.get_manifest_path_internal(hash)) {
+            Ok(Some(manifest)) => manifest,
+            Ok(None) => return true,
+            Err(e) => {
+                warn!("Unable to read the cache manifest for {}: {}", hash, e);
+                return false;
+            }
+        };
+        match self
+            .blobs
+            .verify_sizes(&self.cache_path.join(hash), &manifest)
+        {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("{} ({}), evicting it", e, hash);
+                false
+            }
+        }
+    }
+
+    /// Evict an entry that failed verification. The hit is reported as a
+    /// miss either way, so failing to evict it is only logged.
+    fn evict_corrupt_entry(&self, hash: &str) {
+        if let Err(e) = self.evict(hash) {
+            warn!("Unable to evict corrupt cache entry {}: {}", hash, e);
+        }
+    }
+
+    /// Remove an entry from the database and the file system.
+    fn evict(&self, hash: &str) -> anyhow::Result<()> {
+        self.db
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM cache_outputs WHERE hash = ?1", params![hash])?;
+        let [compressed_output, output] = self.terminal_outputs.paths(hash);
+        remove_items(&[
+            self.cache_path.join(hash),
+            compressed_output,
+            output,
+            self.get_manifest_path_internal(hash),
+        ])?;
+        Ok(())
+    }
+
     /// Assemble a `CachedResult` for a confirmed hit by reading its
     /// terminal output file. Safe to call concurrently — Rayon invokes
     /// this from multiple threads during `get_batch`.
-    fn build_cached_result(&self, hash: &str, code: i16, size: i64) -> CachedResult {
-        let terminal_output =
-            read_to_string(self.get_task_outputs_path_internal(hash)).unwrap_or_default();
+    fn build_cached_result(
+        &self,
+        hash: &str,
+        code: i16,
+        size: i64,
+        provenance: CacheProvenance,
+    ) -> CachedResult {
+        let terminal_output = self.terminal_outputs.read(hash).unwrap_or_default();
         CachedResult {
             code,
             terminal_output: Some(terminal_output),
             outputs_path: self.cache_path.join(hash).to_normalized_string(),
             size: Some(size),
+            provenance: Some(provenance),
         }
     }
 
+    /// Store the outputs of a task. Files over the `max_file_size` limit are
+    /// left out, and an entry over the `max_entry_size` limit is refused so
+    /// that a single misconfigured target cannot evict the rest of the cache.
     #[napi]
     pub fn put(
         &mut self,
@@ -219,15 +378,16 @@ impl NxCache {
         terminal_output: String,
         outputs: Vec<String>,
         code: i16,
-    ) -> anyhow::Result<Vec<String>> {
+    ) -> anyhow::Result<CachePutResult> {
         let start = Instant::now();
         trace!("PUT {}", &hash);
         let task_dir = self.cache_path.join(&hash);
+        let manifest_path = self.get_manifest_path_internal(&hash);
 
         // Remove the task directory
         //
         trace!("Removing task directory: {:?}", &task_dir);
-        remove_items(&[&task_dir])?;
+        remove_items(&[&task_dir, &manifest_path])?;
         trace!("Successfully removed task directory: {:?}", &task_dir);
 
         // Create the task directory again
@@ -236,10 +396,8 @@ impl NxCache {
         trace!("Successfully created task directory: {:?}", &task_dir);
 
         // Write the terminal outputs into a file
-        let task_outputs_path = self.get_task_outputs_path_internal(&hash);
-        trace!("Writing terminal outputs to: {:?}", &task_outputs_path);
-        let mut total_size: i64 = terminal_output.len() as i64;
-        write(task_outputs_path, terminal_output)?;
+        trace!("Writing terminal outputs of {}", &hash);
+        let mut total_size = self.terminal_outputs.write(&hash, &terminal_output)? as i64;
         trace!("Successfully wrote terminal outputs ({} bytes)", total_size);
 
         // Expand the outputs
@@ -247,30 +405,69 @@ impl NxCache {
         let expanded_outputs = _expand_outputs(&self.workspace_root, outputs)?;
         trace!("Successfully expanded {} outputs", expanded_outputs.len());
 
-        // Copy the outputs to the cache
+        // Measure the outputs before copying anything, so an oversized entry
+        // costs a walk of its files rather than a copy of them.
+        let max_file_size = self.output_limits.max_file_size();
+        if let Some(max_entry_size) = self.output_limits.max_entry_size() {
+            let mut entry_size = total_size as u64;
+            for expanded_output in expanded_outputs.iter() {
+                let p = self.workspace_root.join(expanded_output);
+                if p.exists() {
+                    entry_size += measure(&p, max_file_size)?;
+                }
+            }
+            if entry_size > max_entry_size {
+                debug!(
+                    "Refusing to cache {}: {} bytes exceed the limit of {} bytes",
+                    &hash, entry_size, max_entry_size
+                );
+                self.evict(&hash)?;
+                return Ok(CachePutResult {
+                    expanded_outputs,
+                    skipped_files: vec![],
+                    refused_size: Some(entry_size as i64),
+                });
+            }
+        }
+
+        // Copy the outputs to the cache. File contents go into the blob
+        // store and are linked into the task directory. The entry is charged
+        // the full size of its files, including those it shares with other
+        // entries, so its size doesn't depend on which entry happened to
+        // store a blob first and the total never undercounts the disk usage.
         let mut copied_files = 0;
+        let mut captured = CaptureResult::default();
         for expanded_output in expanded_outputs.iter() {
             let p = self.workspace_root.join(expanded_output);
             if p.exists() {
-                let cached_outputs_dir = task_dir.join(expanded_output);
-                trace!("Copying {:?} -> {:?}", &p, &cached_outputs_dir);
-                let copied_size = _copy(p, cached_outputs_dir)?;
-                total_size += copied_size;
+                trace!("Copying {:?} -> {:?}", &p, task_dir.join(expanded_output));
+                self.blobs.capture(
+                    &self.workspace_root,
+                    expanded_output,
+                    &task_dir,
+                    max_file_size,
+                    &mut captured,
+                )?;
                 copied_files += 1;
-                trace!(
-                    "Successfully copied {} ({} bytes)",
-                    expanded_output, copied_size
-                );
+                trace!("Successfully copied {}", expanded_output);
             }
         }
+        total_size += captured.size;
         trace!(
-            "Successfully copied {} files, total cache size: {} bytes",
-            copied_files, total_size
+            "Successfully copied {} files ({} new bytes), total entry size: {} bytes",
+            copied_files, captured.new_bytes, total_size
         );
 
-        self.record_to_cache(hash.clone(), code, total_size)?;
+        trace!("Writing manifest to: {:?}", &manifest_path);
+        captured.manifest.write(&manifest_path)?;
+
+        self.record_to_cache(hash.clone(), code, total_size, &self.provenance)?;
         debug!("PUT {} {:?}", &hash, start.elapsed());
-        Ok(expanded_outputs)
+        Ok(CachePutResult {
+            expanded_outputs,
+            skipped_files: captured.skipped,
+            refused_size: None,
+        })
     }
 
     #[napi]
@@ -285,39 +482,89 @@ impl NxCache {
             &hash, &result.outputs_path
         );
         let terminal_output = result.terminal_output.clone().unwrap_or(String::from(""));
-        let mut size = terminal_output.len() as i64;
+        let mut size = 0;
+        // Remote results are extracted as plain directories, already checked
+        // against the manifest they were uploaded with. Record one for the
+        // extracted files so later restores of the entry are verified too.
+        remove_items(&[self.get_manifest_path_internal(&hash)])?;
+        if Path::new(&result.outputs_path) == self.cache_path.join(&hash) {
+            self.write_scanned_manifest(&hash)?;
+        }
         if let Some(outputs) = outputs {
             if outputs.len() > 0 && result.code == 0 {
                 size +=
                     try_and_retry(|| self.copy_files_from_cache(result.clone(), outputs.clone()))?;
             };
         }
-        write(self.get_task_outputs_path(hash.clone()), terminal_output)?;
+        size += self.terminal_outputs.write(&hash, &terminal_output)? as i64;
 
         let code: i16 = result.code;
-        self.record_to_cache(hash, code, size)?;
+        let provenance = CacheProvenance::remote(result.provenance);
+        self.record_to_cache(hash, code, size, &provenance)?;
         Ok(())
     }
 
-    fn get_task_outputs_path_internal(&self, hash: &str) -> PathBuf {
-        self.cache_path.join("terminalOutputs").join(hash)
+    /// Write a manifest for an entry directory that was extracted rather than
+    /// captured by `put`.
+    fn write_scanned_manifest(&self, hash: &str) -> anyhow::Result<()> {
+        Manifest::scan(&self.cache_path.join(hash))?.write(&self.get_manifest_path_internal(hash))
+    }
+
+    fn get_manifest_path_internal(&self, hash: &str) -> PathBuf {
+        self.cache_path.join("manifests").join(hash)
+    }
+
+    /// Whether `outputs_path` is an entry directory of this cache.
+    fn is_entry_path(&self, outputs_path: &Path) -> bool {
+        outputs_path.file_name().is_some()
+            && outputs_path.parent() == Some(self.cache_path.as_path())
+    }
+
+    /// Reads the manifest for an entry directory of this cache, if `put`
+    /// wrote one for it.
+    fn read_manifest(&self, outputs_path: &Path) -> anyhow::Result<Option<Manifest>> {
+        match outputs_path.file_name() {
+            Some(hash) if self.is_entry_path(outputs_path) => {
+                Manifest::read(&self.get_manifest_path_internal(&hash.to_string_lossy()))
+            }
+            _ => Ok(None),
+        }
     }
 
     #[napi]
     pub fn get_task_outputs_path(&self, hash: String) -> String {
-        self.get_task_outputs_path_internal(&hash)
-            .to_normalized_string()
+        self.terminal_outputs.path(&hash).to_normalized_string()
     }
 
-    fn record_to_cache(&self, hash: String, code: i16, size: i64) -> anyhow::Result<()> {
+    fn record_to_cache(
+        &self,
+        hash: String,
+        code: i16,
+        size: i64,
+        provenance: &CacheProvenance,
+    ) -> anyhow::Result<()> {
         trace!("Recording to cache: {}, {}, {}", &hash, code, size);
+        let [
+            machine_id,
+            git_commit,
+            git_branch,
+            nx_version,
+            ci_run_id,
+            origin,
+        ] = provenance.values();
         self.db.lock().unwrap().execute(
-            "INSERT INTO cache_outputs (hash, code, size) VALUES (?1, ?2, ?3)
-             ON CONFLICT(hash) DO UPDATE SET code = excluded.code, size = excluded.size, created_at = CURRENT_TIMESTAMP, accessed_at = CURRENT_TIMESTAMP",
-            params![hash, code, size],
+            "INSERT INTO cache_outputs (hash, code, size, machine_id, git_commit, git_branch, nx_version, ci_run_id, origin)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+             ON CONFLICT(hash) DO UPDATE SET code = excluded.code, size = excluded.size, created_at = CURRENT_TIMESTAMP, accessed_at = CURRENT_TIMESTAMP,
+                machine_id = excluded.machine_id, git_commit = excluded.git_commit, git_branch = excluded.git_branch,
+                nx_version = excluded.nx_version, ci_run_id = excluded.ci_run_id, origin = excluded.origin",
+            params![hash, code, size, machine_id, git_commit, git_branch, nx_version, ci_run_id, origin],
         )?;
         if self.max_cache_size != 0 {
-            self.ensure_cache_size_within_limit()?
+            self.ensure_cache_size_within_limit(&hash)?
+        }
+        if self.eviction_policy.has_project_quotas() {
+            self.ensure_project_within_quota(&hash)?
         }
         Ok(())
     }
@@ -343,42 +590,302 @@ impl NxCache {
             .unwrap_or(Ok(0))
     }
 
-    fn ensure_cache_size_within_limit(&self) -> anyhow::Result<()> {
+    /// List the entries of the cache along with the details of their tasks,
+    /// filtered, sorted and paginated according to `query`.
+    #[napi]
+    pub fn list_entries(&self, query: Option<CacheEntryQuery>) -> anyhow::Result<CacheEntryPage> {
+        let query = query.unwrap_or_default();
+        let db = self.db.lock().unwrap();
+
+        let (sql, params) = query.entries_sql();
+        let entries = db.query_map(&sql, params_from_iter(params), |row| {
+            Ok(CacheEntry {
+                hash: row.get(0)?,
+                project: row.get(1)?,
+                target: row.get(2)?,
+                configuration: row.get(3)?,
+                code: row.get(4)?,
+                size: row.get(5)?,
+                created_at: row.get(6)?,
+                accessed_at: row.get(7)?,
+                hits: row.get(8)?,
+                provenance: CacheProvenance::from_row(row, 9)?,
+            })
+        })?;
+
+        let (sql, params) = query.totals_sql();
+        let (total, total_size) = db
+            .query_row(&sql, params_from_iter(params), |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .unwrap_or((0, 0));
+
+        Ok(CacheEntryPage {
+            entries,
+            total,
+            total_size,
+        })
+    }
+
+    /// Remove every entry matching `query`, e.g. all entries produced by a
+    /// misbehaving CI agent, regardless of `limit` and `offset`. Returns the
+    /// hashes of the removed entries.
+    #[napi]
+    pub fn remove_entries(&self, query: CacheEntryQuery) -> anyhow::Result<Vec<String>> {
+        let query = CacheEntryQuery {
+            limit: None,
+            offset: None,
+            ..query
+        };
+        let hashes = self
+            .list_entries(Some(query))?
+            .entries
+            .into_iter()
+            .map(|entry| entry.hash)
+            .collect::<Vec<_>>();
+        for hash in &hashes {
+            self.evict(hash)?;
+        }
+        self.blobs
+            .collect_garbage(&self.cache_path.join("manifests"))?;
+        debug!("Removed {} cache entries", hashes.len());
+        Ok(hashes)
+    }
+
+    /// Write the entries matching `query`, limited to `hashes` when given,
+    /// into a single bundle at `path` that `import_bundle` can merge into
+    /// the cache of another machine. Returns the hashes of the entries
+    /// written.
+    #[napi]
+    pub fn export_bundle(
+        &self,
+        path: String,
+        hashes: Option<Vec<String>>,
+        query: Option<CacheEntryQuery>,
+    ) -> anyhow::Result<Vec<String>> {
+        let query = CacheEntryQuery {
+            limit: None,
+            offset: None,
+            ..query.unwrap_or_default()
+        };
+        let hashes = hashes.map(|hashes| hashes.into_iter().collect::<HashSet<_>>());
+        let entries = self
+            .list_entries(Some(query))?
+            .entries
+            .into_iter()
+            .filter(|entry| {
+                hashes
+                    .as_ref()
+                    .is_none_or(|hashes| hashes.contains(&entry.hash))
+            });
+
+        let mut bundle = BundleWriter::create(Path::new(&path))?;
+        for entry in entries {
+            let outputs_path = self.cache_path.join(&entry.hash);
+            if !outputs_path.exists() {
+                warn!(
+                    "Outputs of {} are missing, leaving it out of the bundle",
+                    entry.hash
+                );
+                continue;
+            }
+            let terminal_output = self.terminal_outputs.read(&entry.hash).unwrap_or_default();
+            let provenance = entry.provenance;
+            bundle.append(
+                BundleEntry {
+                    hash: entry.hash,
+                    code: entry.code,
+                    project: entry.project,
+                    target: entry.target,
+                    configuration: entry.configuration,
+                },
+                &outputs_path,
+                &terminal_output,
+                &provenance,
+            )?;
+        }
+        let exported = bundle.finish()?;
+        debug!("Exported {} cache entries to {}", exported.len(), path);
+        Ok(exported.into_iter().map(|entry| entry.hash).collect())
+    }
+
+    /// Verify the entries of the bundle at `path` and merge them into the
+    /// cache. Entries the cache already holds are kept as they are.
+    #[napi]
+    pub fn import_bundle(&self, path: String) -> anyhow::Result<CacheBundleImport> {
+        let staging = self
+            .cache_path
+            .join(format!(".import-{}", uuid::Uuid::new_v4()));
+        let extracted = extract_bundle(Path::new(&path), &staging, |hash| {
+            Ok(self
+                .db
+                .lock()
+                .unwrap()
+                .query_row(
+                    "SELECT 1 FROM cache_outputs WHERE hash = ?1",
+                    params![hash],
+                    |_| Ok(()),
+                )?
+                .is_some())
+        });
+        let imported = extracted.and_then(|extracted| {
+            let mut imported = vec![];
+            for (result, details) in extracted.entries {
+                let hash = Path::new(&result.outputs_path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Invalid outputs path {}", result.outputs_path)
+                    })?;
+                if let Some(details) = details {
+                    self.db.lock().unwrap().execute(
+                        "INSERT OR IGNORE INTO task_details (hash, project, target, configuration)
+                         VALUES (?1, ?2, ?3, ?4)",
+                        params![hash, details.project, details.target, details.configuration],
+                    )?;
+                }
+                let task_dir = self.cache_path.join(&hash);
+                remove_items(&[&task_dir, &self.get_manifest_path_internal(&hash)])?;
+                std::fs::rename(&result.outputs_path, &task_dir)?;
+                self.write_scanned_manifest(&hash)?;
+                // The size of the extracted entry counts the uncompressed
+                // terminal output.
+                let terminal_output = result.terminal_output.unwrap_or_default();
+                let size = result.size.unwrap_or(0) - terminal_output.len() as i64
+                    + self.terminal_outputs.write(&hash, &terminal_output)? as i64;
+                let provenance = CacheProvenance::remote(result.provenance);
+                self.record_to_cache(hash.clone(), result.code, size, &provenance)?;
+                imported.push(hash);
+            }
+            Ok(CacheBundleImport {
+                imported,
+                skipped: extracted.skipped,
+                rejected: extracted.rejected,
+            })
+        });
+        remove_items(&[&staging])?;
+        let imported = imported?;
+        debug!(
+            "Imported {} cache entries from {} ({} already cached, {} rejected)",
+            imported.imported.len(),
+            path,
+            imported.skipped.len(),
+            imported.rejected.len()
+        );
+        Ok(imported)
+    }
+
+    fn ensure_cache_size_within_limit(&self, stored_hash: &str) -> anyhow::Result<()> {
         // 0 is equivalent to being unlimited.
         if self.max_cache_size == 0 {
             return Ok(());
         }
-        let user_specified_max_cache_size = self.max_cache_size;
-        let buffer_amount = (0.1 * user_specified_max_cache_size as f64) as i64;
-        let target_cache_size = user_specified_max_cache_size - buffer_amount;
+        let cache_size = self.get_cache_size()?;
+        if self.max_cache_size < cache_size {
+            self.evict_down_to(
+                "SELECT hash, size FROM cache_outputs",
+                [],
+                cache_size,
+                self.eviction_policy.target_size(self.max_cache_size),
+                stored_hash,
+            )?;
+        }
+        Ok(())
+    }
 
-        let full_cache_size = self.get_cache_size()?;
-        if user_specified_max_cache_size < full_cache_size {
-            let mut cache_size = full_cache_size;
-            let db = self.db.lock().unwrap();
-            let mut stmt = db.prepare(
-                "SELECT hash, size FROM cache_outputs ORDER BY accessed_at ASC LIMIT 100",
+    /// Keep the project `stored_hash` belongs to within its quota, as found
+    /// by joining `cache_outputs` with `task_details`.
+    fn ensure_project_within_quota(&self, stored_hash: &str) -> anyhow::Result<()> {
+        let project = self.db.lock().unwrap().query_row(
+            "SELECT project FROM task_details WHERE hash = ?1",
+            params![stored_hash],
+            |row| row.get::<_, String>(0),
+        )?;
+        let Some((project, quota)) = project.and_then(|project| {
+            let quota = self.eviction_policy.project_quota(&project)?;
+            Some((project, quota))
+        }) else {
+            return Ok(());
+        };
+
+        let project_size = self
+            .db
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT SUM(size) FROM cache_outputs
+                 JOIN task_details ON cache_outputs.hash = task_details.hash
+                 WHERE task_details.project = ?1",
+                params![project],
+                |row| row.get::<_, Option<i64>>(0),
+            )?
+            .flatten()
+            .unwrap_or(0);
+        if quota < project_size {
+            trace!(
+                "{} uses {} bytes of its {} byte quota, evicting entries",
+                project, project_size, quota
+            );
+            self.evict_down_to(
+                "SELECT cache_outputs.hash, size FROM cache_outputs
+                 JOIN task_details ON cache_outputs.hash = task_details.hash
+                 WHERE task_details.project = ?1",
+                params![project],
+                project_size,
+                self.eviction_policy.target_size(quota),
+                stored_hash,
             )?;
-            'outer: while cache_size > target_cache_size {
-                let rows = stmt.query_map([], |r| {
+        }
+        Ok(())
+    }
+
+    /// Evict the entries selected by `candidates` (a `SELECT hash, size`),
+    /// in the order of the eviction strategy, until `size` is below `target`.
+    /// `stored_hash` was just stored and is never evicted, since under LFU it
+    /// would otherwise always go first.
+    fn evict_down_to<P: rusqlite::Params + Clone>(
+        &self,
+        candidates: &str,
+        params: P,
+        mut size: i64,
+        target: i64,
+        stored_hash: &str,
+    ) -> anyhow::Result<()> {
+        let query = format!(
+            "{} ORDER BY {} LIMIT 100",
+            candidates,
+            self.eviction_policy.strategy().order_by()
+        );
+        'outer: while size > target {
+            let rows = self
+                .db
+                .lock()
+                .unwrap()
+                .query_map(&query, params.clone(), |r| {
                     let hash: String = r.get(0)?;
                     let size: i64 = r.get(1)?;
                     Ok((hash, size))
                 })?;
-                for row in rows {
-                    if let Ok((hash, size)) = row {
-                        cache_size -= size;
-                        db.execute("DELETE FROM cache_outputs WHERE hash = ?1", params![hash])?;
-                        remove_items(&[self.cache_path.join(&hash)])?;
-                    }
-                    // We've deleted enough cache entries to be under the
-                    // target cache size, stop looking for more.
-                    if cache_size < target_cache_size {
-                        break 'outer;
-                    }
+            let mut evicted_any = false;
+            for (hash, entry_size) in rows {
+                if hash == stored_hash {
+                    continue;
                 }
+                size -= entry_size;
+                self.evict(&hash)?;
+                evicted_any = true;
+                // We've deleted enough cache entries to be under the
+                // target size, stop looking for more.
+                if size < target {
+                    break 'outer;
+                }
+            }
+            if !evicted_any {
+                break;
             }
         }
+        self.blobs
+            .collect_garbage(&self.cache_path.join("manifests"))?;
         Ok(())
     }
 
@@ -399,31 +906,78 @@ impl NxCache {
             &outputs_path,
             &self.workspace_root
         );
-        copy_outputs_into_workspace(&self.workspace_root, outputs_path, &expanded_outputs)
+        match self.read_manifest(outputs_path)? {
+            Some(manifest) => {
+                // Lookups only compare sizes, the contents are checked here.
+                if let Err(e) = self.blobs.verify(outputs_path, &manifest) {
+                    let hash = outputs_path.file_name().unwrap().to_string_lossy();
+                    self.evict_corrupt_entry(&hash);
+                    anyhow::bail!(
+                        "The cached outputs of {} are corrupt ({}) and were evicted, run the task again",
+                        hash,
+                        e
+                    );
+                }
+                self.blobs.restore(
+                    &self.workspace_root,
+                    outputs_path,
+                    &manifest,
+                    &expanded_outputs,
+                    self.restore_strategy,
+                )
+            }
+            // The entry is gone, e.g. evicted by an earlier attempt that
+            // found it corrupt, so there is nothing left to restore.
+            None if self.is_entry_path(outputs_path) && !outputs_path.exists() => {
+                anyhow::bail!("{} is no longer in the cache", outputs_path.display())
+            }
+            None => copy_outputs_into_workspace(
+                &self.workspace_root,
+                outputs_path,
+                &expanded_outputs,
+                self.restore_strategy,
+            ),
+        }
     }
 
     #[napi]
     pub fn remove_old_cache_records(&self) -> anyhow::Result<()> {
+        // Entries of failed tasks are usually rerun soon after, so they can
+        // be given a shorter lifetime than successful ones.
         let outdated_cache = self
             .db
             .lock()
             .unwrap()
             .prepare(
-                "DELETE FROM cache_outputs WHERE accessed_at < datetime('now', '-7 days') RETURNING hash",
+                "DELETE FROM cache_outputs
+                 WHERE accessed_at < datetime('now', ?1)
+                    OR (code != 0 AND accessed_at < datetime('now', ?2))
+                 RETURNING hash",
             )?
-            .query_map(params![], |row| {
-                let hash: String = row.get(0)?;
+            .query_map(
+                params![
+                    format!("-{} seconds", self.eviction_policy.max_age()),
+                    format!("-{} seconds", self.eviction_policy.failed_max_age())
+                ],
+                |row| {
+                    let hash: String = row.get(0)?;
+                    let [compressed_output, output] = self.terminal_outputs.paths(&hash);
 
-                Ok(vec![
-                    self.cache_path.join(&hash),
-                    self.get_task_outputs_path_internal(&hash),
-                ])
-            })?
+                    Ok(vec![
+                        self.cache_path.join(&hash),
+                        compressed_output,
+                        output,
+                        self.get_manifest_path_internal(&hash),
+                    ])
+                },
+            )?
             .filter_map(anyhow::Result::ok)
             .flatten()
             .collect::<Vec<_>>();
 
         remove_items(&outdated_cache)?;
+        self.blobs
+            .collect_garbage(&self.cache_path.join("manifests"))?;
 
         Ok(())
     }
@@ -465,6 +1019,158 @@ impl NxCache {
             Ok(true)
         }
     }
+
+    /// Check that the database and the files of the cache agree: every
+    /// entry has its outputs and terminal output, every file belongs to an
+    /// entry, and recorded sizes match what is on disk. With `repair`,
+    /// dangling entries are dropped, orphaned files deleted and sizes
+    /// recomputed.
+    #[napi]
+    pub fn fsck(&self, repair: Option<bool>) -> anyhow::Result<CacheFsckReport> {
+        self.check_consistency(repair.unwrap_or(false), IN_FLIGHT_GRACE_PERIOD)
+    }
+
+    fn check_consistency(
+        &self,
+        repair: bool,
+        grace_period: Duration,
+    ) -> anyhow::Result<CacheFsckReport> {
+        let entries: BTreeMap<String, i64> = self
+            .db
+            .lock()
+            .unwrap()
+            .query_map("SELECT hash, size FROM cache_outputs", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .into_iter()
+            .collect();
+        let mut report = CacheFsckReport::default();
+
+        for (hash, &recorded) in &entries {
+            let outputs_path = self.cache_path.join(hash);
+            let terminal_output_size = self
+                .terminal_outputs
+                .paths(hash)
+                .iter()
+                .find_map(|path| std::fs::metadata(path).ok())
+                .map(|metadata| metadata.len() as i64);
+            if !outputs_path.is_dir() {
+                report.missing_outputs.push(hash.clone());
+            }
+            if terminal_output_size.is_none() {
+                report.missing_terminal_outputs.push(hash.clone());
+            }
+            let Some(terminal_output_size) = terminal_output_size.filter(|_| outputs_path.is_dir())
+            else {
+                continue;
+            };
+
+            // Entries are charged the full size of their files, shared or not.
+            let actual = terminal_output_size + directory_size(&outputs_path)?;
+            if recorded != actual {
+                report.size_mismatches.push(CacheSizeMismatch {
+                    hash: hash.clone(),
+                    recorded,
+                    actual,
+                });
+            }
+        }
+
+        report.orphaned_outputs =
+            self.find_orphans(&self.cache_path, &entries, grace_period, |name, path| {
+                path.is_dir().then_some(name)
+            })?;
+        report.orphaned_terminal_outputs = self.find_orphans(
+            &self.cache_path.join("terminalOutputs"),
+            &entries,
+            grace_period,
+            |name, _| Some(name.strip_suffix(".zst").unwrap_or(name)),
+        )?;
+        report.orphaned_manifests = self.find_orphans(
+            &self.cache_path.join("manifests"),
+            &entries,
+            grace_period,
+            |name, _| Some(name),
+        )?;
+
+        if report.is_consistent() {
+            trace!("The cache is consistent");
+        } else {
+            warn!("Found inconsistencies in the cache: {:?}", report);
+        }
+        if repair && !report.is_consistent() {
+            self.repair(&report)?;
+            report.repaired = true;
+        }
+        Ok(report)
+    }
+
+    /// The hashes of files in `directory` that belong to no entry. `hash_of`
+    /// maps a file name to the hash it belongs to, if any.
+    fn find_orphans(
+        &self,
+        directory: &Path,
+        entries: &BTreeMap<String, i64>,
+        grace_period: Duration,
+        hash_of: impl for<'a> Fn(&'a str, &Path) -> Option<&'a str>,
+    ) -> anyhow::Result<Vec<String>> {
+        if !directory.exists() {
+            return Ok(vec![]);
+        }
+        let mut orphans = BTreeSet::new();
+        for entry in std::fs::read_dir(directory)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = entry.path();
+            let Some(hash) = hash_of(&name, &path).filter(|hash| is_hash(hash)) else {
+                continue;
+            };
+            if !entries.contains_key(hash) && is_settled(&path, grace_period)? {
+                orphans.insert(hash.to_string());
+            }
+        }
+        Ok(orphans.into_iter().collect())
+    }
+
+    fn repair(&self, report: &CacheFsckReport) -> anyhow::Result<()> {
+        let dangling = report
+            .missing_outputs
+            .iter()
+            .chain(&report.missing_terminal_outputs)
+            .collect::<BTreeSet<_>>();
+        for hash in &dangling {
+            self.evict(hash)?;
+        }
+
+        let mut orphaned_files = vec![];
+        for hash in &report.orphaned_outputs {
+            orphaned_files.push(self.cache_path.join(hash));
+        }
+        for hash in &report.orphaned_terminal_outputs {
+            orphaned_files.extend(self.terminal_outputs.paths(hash));
+        }
+        for hash in &report.orphaned_manifests {
+            orphaned_files.push(self.get_manifest_path_internal(hash));
+        }
+        remove_items(&orphaned_files)?;
+
+        for mismatch in &report.size_mismatches {
+            self.db.lock().unwrap().execute(
+                "UPDATE cache_outputs SET size = ?2 WHERE hash = ?1",
+                params![mismatch.hash, mismatch.actual],
+            )?;
+        }
+
+        self.blobs
+            .collect_garbage(&self.cache_path.join("manifests"))?;
+        debug!(
+            "Repaired the cache: dropped {} entries, removed {} orphaned files, fixed {} sizes",
+            dangling.len(),
+            orphaned_files.len(),
+            report.size_mismatches.len()
+        );
+        Ok(())
+    }
 }
 
 #[napi]
@@ -557,6 +1263,605 @@ fn escapes_workspace(path: &Path) -> bool {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::native::cache::eviction::EvictionStrategy;
+    use crate::native::cache::inventory::CacheEntrySortKey;
+    use crate::native::cache::provenance::CacheEntryOrigin;
+    use crate::native::db::initialize::initialize_db;
+    use std::collections::HashMap;
+    use std::fs::{read_to_string, write};
+
+    fn test_cache(
+        dir: &Path,
+        max_cache_size: i64,
+        eviction_policy: CacheEvictionPolicy,
+    ) -> NxCache {
+        let cache_path = dir.join("cache");
+        create_dir_all(cache_path.join("terminalOutputs")).unwrap();
+        create_dir_all(cache_path.join("manifests")).unwrap();
+        let cache = NxCache {
+            cache_directory: cache_path.to_normalized_string(),
+            workspace_root: dir.to_path_buf(),
+            blobs: BlobStore::new(cache_path.join("blobs")),
+            terminal_outputs: TerminalOutputStore::new(cache_path.join("terminalOutputs"), None),
+            cache_path,
+            db: Arc::new(Mutex::new(initialize_db(&dir.join("test.db")).unwrap())),
+            link_task_details: true,
+            max_cache_size,
+            eviction_policy,
+            restore_strategy: RestoreStrategy::default(),
+            provenance: CacheProvenance::local(Some(CacheProvenance {
+                machine_id: Some("machine".to_string()),
+                ..Default::default()
+            })),
+            output_limits: CacheOutputLimits::default(),
+        };
+        cache.setup().unwrap();
+        cache
+    }
+
+    fn record(cache: &NxCache, hash: &str, project: &str, code: i16, size: i64) {
+        cache
+            .db
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO task_details (hash, project, target) VALUES (?1, ?2, 'build')",
+                params![hash, project],
+            )
+            .unwrap();
+        cache
+            .record_to_cache(hash.to_string(), code, size, &cache.provenance)
+            .unwrap();
+    }
+
+    fn age(cache: &NxCache, hash: &str, modifier: &str, hits: i64) {
+        cache
+            .db
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE cache_outputs SET accessed_at = datetime('now', ?2), hits = ?3 WHERE hash = ?1",
+                params![hash, modifier, hits],
+            )
+            .unwrap();
+    }
+
+    fn cached_hashes(cache: &NxCache) -> Vec<String> {
+        cache
+            .db
+            .lock()
+            .unwrap()
+            .query_map("SELECT hash FROM cache_outputs ORDER BY hash", [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn list_entries_filters_sorts_and_paginates() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = test_cache(dir.path(), 0, CacheEvictionPolicy::default());
+        record(&cache, "1", "app", 0, 300);
+        record(&cache, "2", "app", 1, 100);
+        record(&cache, "3", "lib", 0, 200);
+        let hashes = |page: CacheEntryPage| {
+            page.entries
+                .into_iter()
+                .map(|entry| entry.hash)
+                .collect::<Vec<_>>()
+        };
+
+        let page = cache
+            .list_entries(Some(CacheEntryQuery {
+                sort_by: Some(CacheEntrySortKey::size),
+                limit: Some(2),
+                ..Default::default()
+            }))
+            .unwrap();
+        assert_eq!((page.total, page.total_size), (3, 600));
+        assert_eq!(page.entries[0].project.as_deref(), Some("app"));
+        assert_eq!(page.entries[0].target.as_deref(), Some("build"));
+        assert_eq!(hashes(page), vec!["1", "3"]);
+
+        let page = cache
+            .list_entries(Some(CacheEntryQuery {
+                sort_by: Some(CacheEntrySortKey::size),
+                limit: Some(2),
+                offset: Some(2),
+                ..Default::default()
+            }))
+            .unwrap();
+        assert_eq!(hashes(page), vec!["2"]);
+
+        let page = cache
+            .list_entries(Some(CacheEntryQuery {
+                project: Some("app".to_string()),
+                failed: Some(false),
+                ..Default::default()
+            }))
+            .unwrap();
+        assert_eq!((page.total, page.total_size), (1, 300));
+        assert_eq!(hashes(page), vec!["1"]);
+
+        let page = cache
+            .list_entries(Some(CacheEntryQuery {
+                min_size: Some(150),
+                sort_by: Some(CacheEntrySortKey::size),
+                descending: Some(false),
+                ..Default::default()
+            }))
+            .unwrap();
+        assert_eq!(hashes(page), vec!["3", "1"]);
+    }
+
+    #[test]
+    fn fsck_finds_and_repairs_inconsistencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = test_cache(dir.path(), 0, CacheEvictionPolicy::default());
+        create_dir_all(dir.path().join("dist")).unwrap();
+        write(dir.path().join("dist/main.js"), "console.log(1)").unwrap();
+        for hash in ["1", "2", "3", "4"] {
+            cache
+                .db
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT INTO task_details (hash, project, target) VALUES (?1, 'app', 'build')",
+                    params![hash],
+                )
+                .unwrap();
+            cache
+                .put(
+                    hash.to_string(),
+                    "built".to_string(),
+                    vec!["dist".to_string()],
+                    0,
+                )
+                .unwrap();
+        }
+        remove_items(&[cache.cache_path.join("2")]).unwrap();
+        remove_items(&cache.terminal_outputs.paths("3")).unwrap();
+        cache
+            .db
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE cache_outputs SET size = 1000000 WHERE hash = '4'",
+                [],
+            )
+            .unwrap();
+        // Left behind by a `put` that never got to record its entry.
+        create_dir_all(cache.cache_path.join("5/dist")).unwrap();
+        write(cache.cache_path.join("terminalOutputs/6.zst"), "").unwrap();
+        write(cache.cache_path.join("manifests/7"), "{}").unwrap();
+
+        let report = cache.check_consistency(false, Duration::ZERO).unwrap();
+
+        assert_eq!(report.missing_outputs, vec!["2"]);
+        assert_eq!(report.missing_terminal_outputs, vec!["3"]);
+        assert_eq!(report.orphaned_outputs, vec!["5"]);
+        assert_eq!(report.orphaned_terminal_outputs, vec!["6"]);
+        assert_eq!(report.orphaned_manifests, vec!["7"]);
+        assert_eq!(report.size_mismatches.len(), 1);
+        assert_eq!(report.size_mismatches[0].hash, "4");
+        assert!(!report.repaired);
+        // Recent files may belong to a `put` in progress.
+        assert!(
+            cache
+                .check_consistency(false, Duration::from_secs(60))
+                .unwrap()
+                .orphaned_outputs
+                .is_empty()
+        );
+
+        let repaired = cache.check_consistency(true, Duration::ZERO).unwrap();
+
+        assert!(repaired.repaired);
+        assert!(
+            cache
+                .check_consistency(false, Duration::ZERO)
+                .unwrap()
+                .is_consistent()
+        );
+        assert_eq!(cached_hashes(&cache), vec!["1", "4"]);
+        assert!(!cache.cache_path.join("5").exists());
+        assert_eq!(
+            cache.get("4".to_string()).unwrap().unwrap().size,
+            Some(repaired.size_mismatches[0].actual)
+        );
+    }
+
+    #[test]
+    fn fsck_repairs_entries_recording_less_than_they_hold() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = test_cache(dir.path(), 0, CacheEvictionPolicy::default());
+        create_dir_all(dir.path().join("dist")).unwrap();
+        write(dir.path().join("dist/main.js"), "console.log(1)").unwrap();
+        cache
+            .put(
+                "1".to_string(),
+                "built".to_string(),
+                vec!["dist".to_string()],
+                0,
+            )
+            .unwrap();
+        assert!(cache.get_manifest_path_internal("1").exists());
+        cache
+            .db
+            .lock()
+            .unwrap()
+            .execute("UPDATE cache_outputs SET size = 1 WHERE hash = '1'", [])
+            .unwrap();
+
+        let report = cache.check_consistency(true, Duration::ZERO).unwrap();
+
+        assert_eq!(report.size_mismatches.len(), 1);
+        assert_eq!(report.size_mismatches[0].recorded, 1);
+        assert!(report.size_mismatches[0].actual > 1);
+        assert_eq!(
+            cache.get("1".to_string()).unwrap().unwrap().size,
+            Some(report.size_mismatches[0].actual)
+        );
+    }
+
+    #[test]
+    fn entries_record_their_provenance() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = test_cache(dir.path(), 0, CacheEvictionPolicy::default());
+        record(&cache, "1", "app", 0, 100);
+        cache
+            .db
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO task_details (hash, project, target) VALUES ('2', 'app', 'build')",
+                [],
+            )
+            .unwrap();
+        cache
+            .apply_remote_cache_results(
+                "2".to_string(),
+                CachedResult {
+                    terminal_output: Some("built elsewhere".to_string()),
+                    provenance: Some(CacheProvenance {
+                        machine_id: Some("agent-2".to_string()),
+                        ci_run_id: Some("42".to_string()),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                None,
+            )
+            .unwrap();
+
+        let local = cache.get("1".to_string()).unwrap().unwrap();
+        assert_eq!(
+            local.provenance.unwrap(),
+            CacheProvenance {
+                machine_id: Some("machine".to_string()),
+                origin: Some(CacheEntryOrigin::local),
+                ..Default::default()
+            }
+        );
+        let page = cache
+            .list_entries(Some(CacheEntryQuery {
+                machine_id: Some("agent-2".to_string()),
+                ..Default::default()
+            }))
+            .unwrap();
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.entries[0].provenance.ci_run_id.as_deref(), Some("42"));
+        assert_eq!(
+            page.entries[0].provenance.origin,
+            Some(CacheEntryOrigin::remote)
+        );
+
+        let removed = cache
+            .remove_entries(CacheEntryQuery {
+                machine_id: Some("agent-2".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(removed, vec!["2"]);
+        assert_eq!(cached_hashes(&cache), vec!["1"]);
+    }
+
+    #[test]
+    fn oversized_outputs_are_skipped_or_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = test_cache(dir.path(), 0, CacheEvictionPolicy::default());
+        cache.output_limits = CacheOutputLimits {
+            max_file_size: Some(1000),
+            max_entry_size: Some(5000),
+        };
+        create_dir_all(dir.path().join("dist")).unwrap();
+        write(dir.path().join("dist/main.js"), "console.log(1)").unwrap();
+        write(dir.path().join("dist/core"), "x".repeat(2000)).unwrap();
+        for hash in ["1", "2"] {
+            cache
+                .db
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT INTO task_details (hash, project, target) VALUES (?1, 'app', 'build')",
+                    params![hash],
+                )
+                .unwrap();
+        }
+
+        let result = cache
+            .put(
+                "1".to_string(),
+                "built".to_string(),
+                vec!["dist".to_string()],
+                0,
+            )
+            .unwrap();
+
+        assert_eq!(result.refused_size, None);
+        assert_eq!(result.skipped_files.len(), 1);
+        assert_eq!(result.skipped_files[0].path, "dist/core");
+        assert!(cache.cache_path.join("1/dist/main.js").exists());
+        assert!(!cache.cache_path.join("1/dist/core").exists());
+
+        for i in 0..10 {
+            write(dir.path().join(format!("dist/{}.js", i)), "x".repeat(800)).unwrap();
+        }
+        let result = cache
+            .put(
+                "2".to_string(),
+                "built".to_string(),
+                vec!["dist".to_string()],
+                0,
+            )
+            .unwrap();
+
+        assert!(result.refused_size.unwrap() > 5000);
+        assert!(!cache.cache_path.join("2").exists());
+        assert!(cache.get("2".to_string()).unwrap().is_none());
+        assert_eq!(cached_hashes(&cache), vec!["1"]);
+    }
+
+    #[test]
+    fn remote_entries_are_verified_on_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = test_cache(dir.path(), 0, CacheEvictionPolicy::default());
+        let outputs_path = cache.cache_path.join("1");
+        create_dir_all(outputs_path.join("dist")).unwrap();
+        write(outputs_path.join("dist/main.js"), "console.log(1)").unwrap();
+
+        cache
+            .apply_remote_cache_results(
+                "1".to_string(),
+                CachedResult {
+                    code: 0,
+                    terminal_output: Some("built".to_string()),
+                    outputs_path: outputs_path.to_normalized_string(),
+                    size: None,
+                    provenance: None,
+                },
+                Some(vec!["dist".to_string()]),
+            )
+            .unwrap();
+        assert!(cache.get("1".to_string()).unwrap().is_some());
+
+        // Same size, different contents: only restoring reads the files.
+        write(outputs_path.join("dist/main.js"), "console.log(2)").unwrap();
+        let cached_result = cache.get("1".to_string()).unwrap().unwrap();
+        assert!(
+            cache
+                .copy_files_from_cache(cached_result.clone(), vec!["dist".to_string()])
+                .is_err()
+        );
+        assert!(!dir.path().join("dist/main.js").exists());
+        assert!(cached_hashes(&cache).is_empty());
+        // Retrying doesn't mistake the evicted entry for empty outputs.
+        assert!(
+            cache
+                .copy_files_from_cache(cached_result, vec!["dist".to_string()])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn incomplete_entries_are_misses_without_hits() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = test_cache(dir.path(), 0, CacheEvictionPolicy::default());
+        create_dir_all(dir.path().join("dist")).unwrap();
+        write(dir.path().join("dist/main.js"), "console.log(1)").unwrap();
+        for hash in ["1", "2"] {
+            cache
+                .put(
+                    hash.to_string(),
+                    "built".to_string(),
+                    vec!["dist".to_string()],
+                    0,
+                )
+                .unwrap();
+        }
+        // Left half-written, as by an interrupted copy.
+        remove_items(&[cache.cache_path.join("2/dist")]).unwrap();
+        let hits = |cache: &NxCache| -> Vec<i64> {
+            cache
+                .db
+                .lock()
+                .unwrap()
+                .query_map("SELECT hits FROM cache_outputs ORDER BY hash", [], |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        };
+
+        let results = cache
+            .get_batch(vec!["1".to_string(), "2".to_string()])
+            .unwrap();
+
+        assert!(results[0].is_some());
+        assert!(results[1].is_none());
+        assert_eq!(cached_hashes(&cache), vec!["1"]);
+        assert_eq!(hits(&cache), vec![1]);
+    }
+
+    #[test]
+    fn entries_are_charged_for_the_files_they_share() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = test_cache(dir.path(), 0, CacheEvictionPolicy::default());
+        create_dir_all(dir.path().join("dist")).unwrap();
+        write(dir.path().join("dist/main.js"), "x".repeat(1000)).unwrap();
+
+        for hash in ["1", "2"] {
+            cache
+                .put(
+                    hash.to_string(),
+                    "built".to_string(),
+                    vec!["dist".to_string()],
+                    0,
+                )
+                .unwrap();
+        }
+
+        let sizes = ["1", "2"].map(|hash| cache.get(hash.to_string()).unwrap().unwrap().size);
+        assert_eq!(sizes[0], sizes[1]);
+        assert!(sizes[1].unwrap() >= 1000);
+        assert_eq!(cache.get_cache_size().unwrap(), 2 * sizes[0].unwrap());
+    }
+
+    #[test]
+    fn bundles_carry_entries_between_caches() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let target_dir = tempfile::tempdir().unwrap();
+        let mut source = test_cache(source_dir.path(), 0, CacheEvictionPolicy::default());
+        let target = test_cache(target_dir.path(), 0, CacheEvictionPolicy::default());
+        create_dir_all(source_dir.path().join("dist")).unwrap();
+        write(source_dir.path().join("dist/main.js"), "console.log(1)").unwrap();
+        source
+            .db
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO task_details (hash, project, target) VALUES ('1', 'app', 'build')",
+                [],
+            )
+            .unwrap();
+        source
+            .put(
+                "1".to_string(),
+                "built".to_string(),
+                vec!["dist".to_string()],
+                0,
+            )
+            .unwrap();
+        record(&source, "2", "lib", 0, 100);
+        let bundle = source_dir.path().join("cache.bundle");
+
+        let exported = source
+            .export_bundle(
+                bundle.to_string_lossy().into_owned(),
+                Some(vec!["1".to_string(), "2".to_string()]),
+                None,
+            )
+            .unwrap();
+        let imported = target
+            .import_bundle(bundle.to_string_lossy().into_owned())
+            .unwrap();
+        let reimported = target
+            .import_bundle(bundle.to_string_lossy().into_owned())
+            .unwrap();
+
+        // "2" has no outputs on disk, so it is left out.
+        assert_eq!(exported, vec!["1"]);
+        assert_eq!(imported.imported, vec!["1"]);
+        assert_eq!(reimported.skipped, vec!["1"]);
+        let entries = target.list_entries(None).unwrap().entries;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].project.as_deref(), Some("app"));
+        assert_eq!(
+            read_to_string(target.cache_path.join("1/dist/main.js")).unwrap(),
+            "console.log(1)"
+        );
+        assert_eq!(target.terminal_outputs.read("1").unwrap(), "built");
+    }
+
+    #[test]
+    fn lfu_evicts_the_least_used_entries_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = CacheEvictionPolicy {
+            strategy: Some(EvictionStrategy::lfu),
+            buffer: Some(0.0),
+            ..Default::default()
+        };
+        let cache = test_cache(dir.path(), 250, policy);
+
+        record(&cache, "1", "app", 0, 100);
+        record(&cache, "2", "app", 0, 100);
+        // "1" is used less recently but more often than "2".
+        age(&cache, "1", "-1 hour", 5);
+        age(&cache, "2", "-1 minute", 0);
+        record(&cache, "3", "app", 0, 100);
+
+        assert_eq!(cached_hashes(&cache), vec!["1", "3"]);
+    }
+
+    #[test]
+    fn hits_are_counted_on_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = test_cache(dir.path(), 0, CacheEvictionPolicy::default());
+        record(&cache, "1", "app", 0, 100);
+
+        cache.get("1".to_string()).unwrap();
+        cache.get_batch(vec!["1".to_string()]).unwrap();
+
+        let hits = cache
+            .db
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT hits FROM cache_outputs WHERE hash = '1'",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap();
+        assert_eq!(hits, Some(2));
+    }
+
+    #[test]
+    fn failed_entries_expire_sooner() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = CacheEvictionPolicy {
+            failed_max_age: Some(60 * 60),
+            ..Default::default()
+        };
+        let cache = test_cache(dir.path(), 0, policy);
+
+        record(&cache, "1", "app", 0, 100);
+        record(&cache, "2", "app", 1, 100);
+        record(&cache, "3", "app", 1, 100);
+        age(&cache, "1", "-2 hours", 0);
+        age(&cache, "2", "-2 hours", 0);
+        age(&cache, "3", "-1 minute", 0);
+        cache.remove_old_cache_records().unwrap();
+
+        assert_eq!(cached_hashes(&cache), vec!["1", "3"]);
+    }
+
+    #[test]
+    fn project_quotas_only_evict_entries_of_that_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = CacheEvictionPolicy {
+            buffer: Some(0.0),
+            project_quotas: Some(HashMap::from([("app".to_string(), 150)])),
+            ..Default::default()
+        };
+        let cache = test_cache(dir.path(), 0, policy);
+
+        record(&cache, "1", "app", 0, 100);
+        record(&cache, "2", "lib", 0, 100);
+        age(&cache, "2", "-1 hour", 0);
+        record(&cache, "3", "app", 0, 100);
+
+        assert_eq!(cached_hashes(&cache), vec!["2", "3"]);
+    }
 
     #[cfg(unix)]
     #[test]
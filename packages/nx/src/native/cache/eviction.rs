@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+/// Entries not used for this long are removed: 7 days.
+const DEFAULT_MAX_AGE: i64 = 7 * 24 * 60 * 60;
+/// Entries of failed tasks not used for this long are removed: 1 day. They
+/// are rarely worth restoring once the failure has been fixed.
+const DEFAULT_FAILED_MAX_AGE: i64 = 24 * 60 * 60;
+/// Share of a limit freed up once it is exceeded, so that the next few
+/// entries don't trigger another eviction straight away.
+const DEFAULT_BUFFER: f64 = 0.1;
+
+/// The order in which entries are evicted once the cache, or a project,
+/// is over its size limit.
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvictionStrategy {
+    /// Least recently used entries go first.
+    #[allow(non_camel_case_types)]
+    lru,
+    /// Least frequently used entries go first, the least recently used among
+    /// entries with as many hits.
+    #[allow(non_camel_case_types)]
+    lfu,
+}
+
+impl EvictionStrategy {
+    /// The `ORDER BY` clause over `cache_outputs` listing entries to evict first.
+    pub fn order_by(&self) -> &'static str {
+        match self {
+            EvictionStrategy::lru => "accessed_at ASC",
+            EvictionStrategy::lfu => "hits ASC, accessed_at ASC",
+        }
+    }
+}
+
+/// How the local cache decides which entries to remove.
+#[napi(object)]
+#[derive(Default, Clone, Debug)]
+pub struct CacheEvictionPolicy {
+    /// Which entries are evicted first when a size limit is exceeded.
+    /// Defaults to `lru`.
+    pub strategy: Option<EvictionStrategy>,
+    /// Seconds an entry is kept after it was last used. Defaults to 7 days.
+    pub max_age: Option<i64>,
+    /// Seconds an entry of a failed task is kept after it was last used.
+    /// Defaults to 1 day, or `max_age` if that is shorter.
+    pub failed_max_age: Option<i64>,
+    /// Share of a size limit freed up once it is exceeded, between 0 and 1.
+    /// Defaults to 0.1.
+    pub buffer: Option<f64>,
+    /// The maximum size in bytes of the entries of each listed project.
+    pub project_quotas: Option<HashMap<String, i64>>,
+}
+
+impl CacheEvictionPolicy {
+    pub fn strategy(&self) -> EvictionStrategy {
+        self.strategy.unwrap_or(EvictionStrategy::lru)
+    }
+
+    pub fn max_age(&self) -> i64 {
+        self.max_age.unwrap_or(DEFAULT_MAX_AGE).max(0)
+    }
+
+    pub fn failed_max_age(&self) -> i64 {
+        self.failed_max_age
+            .unwrap_or_else(|| DEFAULT_FAILED_MAX_AGE.min(self.max_age()))
+            .max(0)
+    }
+
+    /// The size to shrink down to once `limit` is exceeded.
+    pub fn target_size(&self, limit: i64) -> i64 {
+        let buffer = self.buffer.unwrap_or(DEFAULT_BUFFER).clamp(0.0, 1.0);
+        limit - (buffer * limit as f64) as i64
+    }
+
+    pub fn project_quota(&self, project: &str) -> Option<i64> {
+        self.project_quotas
+            .as_ref()?
+            .get(project)
+            .copied()
+            .filter(|quota| *quota > 0)
+    }
+
+    pub fn has_project_quotas(&self) -> bool {
+        self.project_quotas
+            .as_ref()
+            .is_some_and(|quotas| !quotas.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn defaults_keep_failed_entries_for_a_day() {
+        let policy = CacheEvictionPolicy::default();
+
+        assert_eq!(policy.strategy(), EvictionStrategy::lru);
+        assert_eq!(policy.max_age(), 7 * 24 * 60 * 60);
+        assert_eq!(policy.failed_max_age(), 24 * 60 * 60);
+        assert_eq!(policy.target_size(1000), 900);
+    }
+
+    #[test]
+    fn failed_max_age_defaults_to_a_day_capped_at_max_age() {
+        let short = CacheEvictionPolicy {
+            max_age: Some(60 * 60),
+            ..Default::default()
+        };
+        let explicit = CacheEvictionPolicy {
+            failed_max_age: Some(3 * 24 * 60 * 60),
+            ..Default::default()
+        };
+
+        assert_eq!(short.failed_max_age(), 60 * 60);
+        assert_eq!(explicit.failed_max_age(), 3 * 24 * 60 * 60);
+    }
+
+    #[test]
+    fn buffer_and_quotas_are_sanitized() {
+        let policy = CacheEvictionPolicy {
+            buffer: Some(2.0),
+            project_quotas: Some(HashMap::from([
+                ("app".to_string(), 100),
+                ("lib".to_string(), 0),
+            ])),
+            ..Default::default()
+        };
+
+        assert_eq!(policy.target_size(1000), 0);
+        assert_eq!(policy.project_quota("app"), Some(100));
+        assert_eq!(policy.project_quota("lib"), None);
+        assert_eq!(policy.project_quota("other"), None);
+    }
+}
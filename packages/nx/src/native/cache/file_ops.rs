@@ -2,7 +2,7 @@ use std::path::{Path, PathBuf};
 use std::{fs, io};
 
 use fs_extra::error::ErrorKind;
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
 
 #[napi]
 pub fn remove(src: String) -> anyhow::Result<()> {
@@ -21,15 +21,120 @@ pub fn _copy<P>(src: P, dest: P) -> anyhow::Result<i64>
 where
     P: AsRef<Path>,
 {
-    _copy_impl(src.as_ref(), dest.as_ref(), None)
+    _copy_impl(src.as_ref(), dest.as_ref(), None, RestoreStrategy::Copy)
 }
 
-/// Copy `src` to `dest`.
+/// How restored files are materialized in the workspace, set with
+/// `NX_CACHE_RESTORE_STRATEGY`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestoreStrategy {
+    /// Clone files copy-on-write where the file system supports it (btrfs,
+    /// XFS with reflink), which is near-instant and takes no extra space, and
+    /// copy them otherwise.
+    #[default]
+    Auto,
+    /// Hard link read-only files to the cache. Writable files are restored
+    /// like `Auto`, since modifying a linked file in place would modify the
+    /// cached copy too. Also falls back to `Auto` where hard links aren't
+    /// possible, e.g. across devices.
+    Hardlink,
+    /// Always copy the bytes.
+    Copy,
+}
+
+impl RestoreStrategy {
+    pub fn from_env() -> Self {
+        match std::env::var("NX_CACHE_RESTORE_STRATEGY") {
+            Ok(value) => Self::parse(&value).unwrap_or_else(|| {
+                warn!("Ignoring invalid NX_CACHE_RESTORE_STRATEGY {:?}", value);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "auto" | "reflink" => Some(RestoreStrategy::Auto),
+            "hardlink" => Some(RestoreStrategy::Hardlink),
+            "copy" => Some(RestoreStrategy::Copy),
+            _ => None,
+        }
+    }
+}
+
+/// Materialize the file `src` at `dest` according to `strategy`, returning
+/// its size in bytes.
+pub fn restore_file(src: &Path, dest: &Path, strategy: RestoreStrategy) -> io::Result<u64> {
+    match strategy {
+        RestoreStrategy::Copy => fs::copy(src, dest),
+        RestoreStrategy::Hardlink if !fs::metadata(src)?.permissions().readonly() => {
+            trace!("{:?} is writable, cloning it instead of linking", src);
+            restore_file(src, dest, RestoreStrategy::Auto)
+        }
+        RestoreStrategy::Hardlink => match fs::hard_link(src, dest) {
+            Ok(()) => Ok(fs::metadata(dest)?.len()),
+            Err(e) => {
+                trace!("Unable to hard link {:?}, cloning instead: {}", dest, e);
+                restore_file(src, dest, RestoreStrategy::Auto)
+            }
+        },
+        RestoreStrategy::Auto => match reflink(src, dest) {
+            Ok(size) => Ok(size),
+            Err(e) => {
+                trace!("Unable to clone {:?}, copying instead: {}", dest, e);
+                fs::copy(src, dest)
+            }
+        },
+    }
+}
+
+/// Clone `src` to a new file at `dest` with `FICLONE`. Fails without leaving
+/// `dest` behind when the file system can't share extents between the two,
+/// e.g. because they are on different devices.
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dest: &Path) -> io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+    // `_IOW(0x94, 9, int)` from `linux/fs.h`
+    const FICLONE: u32 = 0x4004_9409;
+
+    let src_file = fs::File::open(src)?;
+    let metadata = src_file.metadata()?;
+    let dest_file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dest)?;
+    // SAFETY: both descriptors are open for the duration of the call.
+    let result = unsafe { libc::ioctl(dest_file.as_raw_fd(), FICLONE as _, src_file.as_raw_fd()) };
+    if result != 0 {
+        let error = io::Error::last_os_error();
+        drop(dest_file);
+        fs::remove_file(dest).ok();
+        return Err(error);
+    }
+    dest_file.set_permissions(metadata.permissions())?;
+    Ok(metadata.len())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_src: &Path, _dest: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "cloning files is only supported on Linux",
+    ))
+}
+
+/// Copy `src` to `dest`, materializing files according to `strategy`.
 ///
 /// With `boundary = Some(root)` the copy is confined to `root` (cache restore):
 /// parents are realized as real dirs (any symlink under `root` is replaced) and
 /// existing entries are removed, not written through. `None` keeps the original.
-pub fn _copy_impl(src: &Path, dest: &Path, boundary: Option<&Path>) -> anyhow::Result<i64> {
+pub fn _copy_impl(
+    src: &Path,
+    dest: &Path,
+    boundary: Option<&Path>,
+    strategy: RestoreStrategy,
+) -> anyhow::Result<i64> {
     let dest: PathBuf = remove_trailing_single_dot(dest);
     let dest_parent = dest.parent().unwrap_or(&dest);
     let src: PathBuf = src.into();
@@ -62,10 +167,10 @@ pub fn _copy_impl(src: &Path, dest: &Path, boundary: Option<&Path>) -> anyhow::R
         0
     } else if src.is_dir() {
         trace!("Copying directory: {:?}", &src);
-        copy_dir_all(&src, &dest, boundary).map_err(anyhow::Error::new)?
+        copy_dir_all(&src, &dest, boundary, strategy).map_err(anyhow::Error::new)?
     } else {
         trace!("Copying file: {:?}", &src);
-        fs::copy(&src, &dest)?
+        restore_file(&src, &dest, strategy)?
     };
 
     debug!("Copy completed: {:?} -> {:?} ({} bytes)", &src, &dest, size);
@@ -75,7 +180,7 @@ pub fn _copy_impl(src: &Path, dest: &Path, boundary: Option<&Path>) -> anyhow::R
 /// Create `dir` and missing ancestors without traversing a symlink at or below
 /// `boundary`: such a symlink/file is replaced with a real directory. Paths at
 /// or above `boundary` are trusted and untouched (e.g. a `/tmp` system symlink).
-fn create_dir_all_within(boundary: &Path, dir: &Path) -> io::Result<()> {
+pub(crate) fn create_dir_all_within(boundary: &Path, dir: &Path) -> io::Result<()> {
     // At or above the boundary: trust the existing tree, only create if missing.
     if dir == boundary || !dir.starts_with(boundary) {
         return match fs::symlink_metadata(dir) {
@@ -105,7 +210,7 @@ fn create_dir_all_within(boundary: &Path, dir: &Path) -> io::Result<()> {
 
 /// Remove the entry at `path` without following a final symlink (the link is
 /// unlinked, not its target); directories recurse, a missing path is a no-op.
-fn remove_path(path: &Path) -> io::Result<()> {
+pub(crate) fn remove_path(path: &Path) -> io::Result<()> {
     match fs::symlink_metadata(path) {
         Ok(meta) if meta.file_type().is_dir() => fs::remove_dir_all(path),
         Ok(_) => fs::remove_file(path),
@@ -122,6 +227,7 @@ pub fn copy_outputs_into_workspace(
     workspace_root: &Path,
     outputs_path: &Path,
     expanded_outputs: &[String],
+    strategy: RestoreStrategy,
 ) -> anyhow::Result<i64> {
     let mut size = 0;
     for output in expanded_outputs {
@@ -132,7 +238,7 @@ pub fn copy_outputs_into_workspace(
             continue;
         }
         let to = workspace_root.join(output);
-        size += _copy_impl(&from, &to, Some(workspace_root))?;
+        size += _copy_impl(&from, &to, Some(workspace_root), strategy)?;
     }
     Ok(size)
 }
@@ -150,17 +256,17 @@ fn remove_trailing_single_dot(path: impl AsRef<Path>) -> PathBuf {
 }
 
 #[cfg(windows)]
-fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(original: P, link: Q) -> io::Result<()> {
+pub(crate) fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(original: P, link: Q) -> io::Result<()> {
     std::os::windows::fs::symlink_file(original, link)
 }
 
 #[cfg(unix)]
-fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(original: P, link: Q) -> io::Result<()> {
+pub(crate) fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(original: P, link: Q) -> io::Result<()> {
     std::os::unix::fs::symlink(original, link)
 }
 
 #[cfg(target_os = "wasi")]
-fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(original: P, link: Q) -> io::Result<()> {
+pub(crate) fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(original: P, link: Q) -> io::Result<()> {
     std::os::wasi::fs::symlink_path(original, link)
 }
 
@@ -182,6 +288,7 @@ fn copy_dir_all(
     src: impl AsRef<Path>,
     dst: impl AsRef<Path>,
     boundary: Option<&Path>,
+    strategy: RestoreStrategy,
 ) -> io::Result<u64> {
     trace!("Creating directory: {:?}", dst.as_ref());
     match boundary {
@@ -203,7 +310,7 @@ fn copy_dir_all(
 
         let size: u64 = if ty.is_dir() {
             trace!("Copying subdirectory: {:?}", entry.path());
-            let subdir_size = copy_dir_all(entry.path(), dest_path, boundary)?;
+            let subdir_size = copy_dir_all(entry.path(), dest_path, boundary, strategy)?;
             dirs_copied += 1;
             subdir_size
         } else if ty.is_symlink() {
@@ -218,7 +325,7 @@ fn copy_dir_all(
             if boundary.is_some() {
                 remove_existing_symlink(&dest_path)?;
             }
-            let file_size = fs::copy(entry.path(), dest_path)?;
+            let file_size = restore_file(&entry.path(), &dest_path, strategy)?;
             files_copied += 1;
             file_size
         };
@@ -318,6 +425,154 @@ mod test {
         );
     }
 
+    #[test]
+    fn should_parse_restore_strategies() {
+        assert_eq!(RestoreStrategy::parse("auto"), Some(RestoreStrategy::Auto));
+        assert_eq!(
+            RestoreStrategy::parse("reflink"),
+            Some(RestoreStrategy::Auto)
+        );
+        assert_eq!(
+            RestoreStrategy::parse(" Hardlink "),
+            Some(RestoreStrategy::Hardlink)
+        );
+        assert_eq!(RestoreStrategy::parse("copy"), Some(RestoreStrategy::Copy));
+        assert_eq!(RestoreStrategy::parse("symlink"), None);
+    }
+
+    /// Restore `dist/main.js` from a cache entry with `strategy`.
+    fn restore_with(strategy: RestoreStrategy) -> (TempDir, TempDir) {
+        let cache = TempDir::new().unwrap();
+        let workspace = TempDir::new().unwrap();
+        cache.child("dist/main.js").write_str("cached").unwrap();
+
+        let size = copy_outputs_into_workspace(
+            workspace.path(),
+            cache.path(),
+            &["dist".to_string()],
+            strategy,
+        )
+        .unwrap();
+
+        assert_eq!(size, 6);
+        assert_eq!(
+            fs::read_to_string(workspace.join("dist/main.js")).unwrap(),
+            "cached"
+        );
+        (cache, workspace)
+    }
+
+    #[test]
+    fn restore_with_copy_leaves_the_cache_untouched() {
+        let (cache, workspace) = restore_with(RestoreStrategy::Copy);
+
+        fs::write(workspace.join("dist/main.js"), "changed").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(cache.join("dist/main.js")).unwrap(),
+            "cached"
+        );
+    }
+
+    #[test]
+    fn restore_with_auto_clones_or_falls_back_to_copy() {
+        // Whether the temp dir supports reflinks depends on the machine; either
+        // way the restored file must be independent from the cached one.
+        let (cache, workspace) = restore_with(RestoreStrategy::Auto);
+
+        fs::write(workspace.join("dist/main.js"), "changed").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(cache.join("dist/main.js")).unwrap(),
+            "cached"
+        );
+    }
+
+    #[test]
+    fn restore_with_auto_replaces_existing_files() {
+        let cache = TempDir::new().unwrap();
+        let workspace = TempDir::new().unwrap();
+        cache.child("main.js").write_str("cached").unwrap();
+        workspace.child("main.js").write_str("stale").unwrap();
+
+        restore_file(
+            &cache.join("main.js"),
+            &workspace.join("main.js"),
+            RestoreStrategy::Auto,
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(workspace.join("main.js")).unwrap(),
+            "cached"
+        );
+    }
+
+    fn set_read_only(path: &Path) {
+        let mut permissions = fs::metadata(path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(path, permissions).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restore_with_hardlink_shares_read_only_files() {
+        use std::os::unix::fs::MetadataExt;
+
+        let cache = TempDir::new().unwrap();
+        let workspace = TempDir::new().unwrap();
+        cache.child("main.js").write_str("cached").unwrap();
+        set_read_only(&cache.join("main.js"));
+
+        restore_file(
+            &cache.join("main.js"),
+            &workspace.join("main.js"),
+            RestoreStrategy::Hardlink,
+        )
+        .unwrap();
+
+        let restored = fs::metadata(workspace.join("main.js")).unwrap();
+        assert_eq!(
+            restored.ino(),
+            fs::metadata(cache.join("main.js")).unwrap().ino()
+        );
+        assert!(restored.permissions().readonly());
+    }
+
+    #[test]
+    fn restore_with_hardlink_copies_writable_files() {
+        let (cache, workspace) = restore_with(RestoreStrategy::Hardlink);
+
+        fs::write(workspace.join("dist/main.js"), "changed").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(cache.join("dist/main.js")).unwrap(),
+            "cached"
+        );
+    }
+
+    #[test]
+    fn restore_with_hardlink_falls_back_when_linking_fails() {
+        let cache = TempDir::new().unwrap();
+        let workspace = TempDir::new().unwrap();
+        cache.child("main.js").write_str("cached").unwrap();
+        set_read_only(&cache.join("main.js"));
+        // Linking onto an existing file fails, so the file is copied instead.
+        workspace.child("main.js").write_str("stale").unwrap();
+
+        restore_file(
+            &cache.join("main.js"),
+            &workspace.join("main.js"),
+            RestoreStrategy::Hardlink,
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(workspace.join("main.js")).unwrap(),
+            "cached"
+        );
+    }
+
     #[test]
     fn restore_copies_only_declared_outputs() {
         // A malicious artifact ships files beyond the declared outputs; the
@@ -333,7 +588,13 @@ mod test {
         cache.child("package.json").write_str("{evil}").unwrap();
 
         let expanded = vec!["dist/main.js".to_string()];
-        copy_outputs_into_workspace(workspace.path(), cache.path(), &expanded).unwrap();
+        copy_outputs_into_workspace(
+            workspace.path(),
+            cache.path(),
+            &expanded,
+            RestoreStrategy::Copy,
+        )
+        .unwrap();
 
         assert!(workspace.child("dist/main.js").exists());
         assert!(!workspace.child(".git/hooks/pre-commit").exists());
@@ -357,7 +618,13 @@ mod test {
         symlink(outside.path(), workspace.join("dist")).unwrap();
 
         let expanded = vec!["dist/payload.js".to_string()];
-        copy_outputs_into_workspace(workspace.path(), cache.path(), &expanded).unwrap();
+        copy_outputs_into_workspace(
+            workspace.path(),
+            cache.path(),
+            &expanded,
+            RestoreStrategy::Copy,
+        )
+        .unwrap();
 
         // The write must land in the workspace, not through the symlink.
         assert!(!outside.child("payload.js").exists());
@@ -407,7 +674,13 @@ mod test {
 
         let workspace = TempDir::new().unwrap();
         let expanded = vec!["dist".to_string()];
-        copy_outputs_into_workspace(workspace.path(), cache.path(), &expanded).unwrap();
+        copy_outputs_into_workspace(
+            workspace.path(),
+            cache.path(),
+            &expanded,
+            RestoreStrategy::Copy,
+        )
+        .unwrap();
 
         // The symlink is recreated verbatim — a pointer outside the workspace is
         // allowed; nothing was written *through* it.
@@ -438,7 +711,13 @@ mod test {
 
         let workspace = TempDir::new().unwrap();
         let expanded = vec!["dist/real.js".to_string(), "dist/link.js".to_string()];
-        copy_outputs_into_workspace(workspace.path(), cache.path(), &expanded).unwrap();
+        copy_outputs_into_workspace(
+            workspace.path(),
+            cache.path(),
+            &expanded,
+            RestoreStrategy::Copy,
+        )
+        .unwrap();
 
         let link = workspace.join("dist/link.js");
         assert!(
@@ -0,0 +1,104 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Files and directories this recent are left alone when looking for orphans,
+/// as they may belong to a task that is still running or being stored.
+pub const IN_FLIGHT_GRACE_PERIOD: Duration = Duration::from_secs(10 * 60);
+
+/// An entry whose recorded size disagrees with the files it holds.
+#[napi(object)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheSizeMismatch {
+    pub hash: String,
+    /// Size in bytes recorded in the database
+    pub recorded: i64,
+    /// Size in bytes of the terminal output and outputs on disk
+    pub actual: i64,
+}
+
+/// The inconsistencies found between the cache database and its files.
+#[napi(object)]
+#[derive(Default, Clone, Debug)]
+pub struct CacheFsckReport {
+    /// Entries whose outputs directory is missing.
+    pub missing_outputs: Vec<String>,
+    /// Entries whose terminal output is missing.
+    pub missing_terminal_outputs: Vec<String>,
+    /// Outputs directories without an entry.
+    pub orphaned_outputs: Vec<String>,
+    /// Terminal outputs without an entry.
+    pub orphaned_terminal_outputs: Vec<String>,
+    /// Manifests without an entry.
+    pub orphaned_manifests: Vec<String>,
+    pub size_mismatches: Vec<CacheSizeMismatch>,
+    /// Whether the inconsistencies were repaired.
+    pub repaired: bool,
+}
+
+impl CacheFsckReport {
+    pub fn is_consistent(&self) -> bool {
+        self.missing_outputs.is_empty()
+            && self.missing_terminal_outputs.is_empty()
+            && self.orphaned_outputs.is_empty()
+            && self.orphaned_terminal_outputs.is_empty()
+            && self.orphaned_manifests.is_empty()
+            && self.size_mismatches.is_empty()
+    }
+}
+
+/// Task hashes are numeric, which tells entries apart from the other
+/// contents of the cache directory.
+pub fn is_hash(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Whether `path` was last modified longer than `grace_period` ago.
+pub fn is_settled(path: &Path, grace_period: Duration) -> io::Result<bool> {
+    let modified = fs::symlink_metadata(path)?.modified()?;
+    Ok(SystemTime::now()
+        .duration_since(modified)
+        .is_ok_and(|age| age >= grace_period))
+}
+
+/// The combined size in bytes of the files under `dir`.
+pub fn directory_size(dir: &Path) -> io::Result<i64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += directory_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len() as i64;
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn should_only_treat_numeric_names_as_hashes() {
+        assert!(is_hash("1234567890"));
+        assert!(!is_hash(""));
+        assert!(!is_hash("terminalOutputs"));
+        assert!(!is_hash(".import-1234"));
+    }
+
+    #[test]
+    fn should_sum_nested_file_sizes() {
+        let temp = TempDir::new().unwrap();
+        temp.child("a.txt").write_str("12345").unwrap();
+        temp.child("nested/b.txt").write_str("123").unwrap();
+
+        assert_eq!(directory_size(temp.path()).unwrap(), 8);
+        assert!(!is_settled(temp.path(), Duration::from_secs(60)).unwrap());
+        assert!(is_settled(temp.path(), Duration::ZERO).unwrap());
+    }
+}
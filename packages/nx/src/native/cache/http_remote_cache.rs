@@ -1,29 +1,140 @@
 use std::{
     env,
-    fs::{self},
-    io::Read,
-    path::Path,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use super::{
+    blob_store::IntegrityError,
     cache::CachedResult,
     errors::{HttpRemoteCacheErrors, convert_response_to_error, report_request_error},
+    provenance::CacheProvenance,
+    remote_access::{
+        HttpRemoteCacheOptions, NegativeCache, RemoteCacheMissTimings, RemoteCacheMode,
+    },
+    retry::{CircuitBreaker, RetryPolicy, is_retryable_error, is_retryable_status, retry_after},
+    signing::{ArtifactSigner, SIGNATURE_HEADER, digest_file},
+    tarball::{TarballEncoding, TemporaryTarball, extract_tarball, write_tarball},
 };
-use flate2::Compression;
-use reqwest::{Client, ClientBuilder, StatusCode, header};
-use tar::{Archive, Builder};
-use tracing::trace;
+use bytes::Bytes;
+use futures::{StreamExt, stream};
+use http_body::{Frame, SizeHint};
+use reqwest::{Body, Client, ClientBuilder, StatusCode, header};
+use ring::digest::{Context as DigestContext, Digest, SHA256};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{debug, trace, warn};
+
+/// Size of the chunks tarballs are streamed in, to and from the socket.
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+/// Number of chunks buffered between the socket and the tarball on disk.
+const STREAM_CHANNEL_CAPACITY: usize = 16;
+/// Artifacts `retrieve_batch` downloads at once, unless
+/// `NX_SELF_HOSTED_REMOTE_CACHE_CONCURRENCY` says otherwise.
+const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Body of `POST /v1/cache/exists` and of its response: the hashes to look
+/// up, and the ones the server holds.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ExistsBatch {
+    hashes: Vec<String>,
+}
+
+/// Compression settings, read from `NX_SELF_HOSTED_REMOTE_CACHE_COMPRESSION`
+/// (`zstd` or `gzip`) and `NX_SELF_HOSTED_REMOTE_CACHE_COMPRESSION_LEVEL`.
+#[derive(Debug, Clone, Copy)]
+struct CompressionOptions {
+    /// Codec used when the server supports it. zstd is only offered and
+    /// uploaded once the server has advertised it; gzip always works.
+    preferred: TarballEncoding,
+    level: Option<i32>,
+}
+
+impl CompressionOptions {
+    fn from_env() -> Self {
+        let preferred = match env::var("NX_SELF_HOSTED_REMOTE_CACHE_COMPRESSION")
+            .map(|v| v.to_ascii_lowercase())
+            .as_deref()
+        {
+            Ok("gzip") => TarballEncoding::Gzip,
+            Ok("zstd") | Err(_) => TarballEncoding::Zstd,
+            Ok(other) => {
+                warn!(
+                    "Ignoring unknown NX_SELF_HOSTED_REMOTE_CACHE_COMPRESSION {:?}, expected zstd or gzip",
+                    other
+                );
+                TarballEncoding::Zstd
+            }
+        };
+        let level = env::var("NX_SELF_HOSTED_REMOTE_CACHE_COMPRESSION_LEVEL")
+            .ok()
+            .and_then(|v| match v.trim().parse::<i32>() {
+                Ok(level) => Some(level),
+                Err(_) => {
+                    warn!(
+                        "Ignoring invalid NX_SELF_HOSTED_REMOTE_CACHE_COMPRESSION_LEVEL {:?}",
+                        v
+                    );
+                    None
+                }
+            });
+        Self { preferred, level }
+    }
+
+    /// The level to use for `encoding`, clamped to the range it supports.
+    fn level_for(&self, encoding: TarballEncoding) -> i32 {
+        match (encoding, self.level) {
+            (TarballEncoding::Gzip, Some(level)) => level.clamp(0, 9),
+            (encoding, None) => encoding.default_level(),
+            (TarballEncoding::Zstd, Some(level)) => {
+                let range = zstd::compression_level_range();
+                level.clamp(*range.start(), *range.end())
+            }
+        }
+    }
+
+    fn accept_encoding(&self) -> &'static str {
+        match self.preferred {
+            TarballEncoding::Zstd => "zstd, gzip",
+            TarballEncoding::Gzip => "gzip",
+        }
+    }
+}
 
 #[napi]
 pub struct HttpRemoteCache {
     client: Client,
     url: String,
+    compression: CompressionOptions,
+    /// Set once the server advertises zstd through `Accept-Encoding`, or
+    /// serves a zstd artifact.
+    server_accepts_zstd: AtomicBool,
+    signer: ArtifactSigner,
+    retries: RetryPolicy,
+    circuit_breaker: CircuitBreaker,
+    /// Cleared once the server turns out not to implement
+    /// `POST /v1/cache/exists`.
+    server_supports_exists: AtomicBool,
+    concurrency: usize,
+    mode: RemoteCacheMode,
+    /// Hashes recently reported missing, not looked up again for a while.
+    misses: NegativeCache,
+    miss_timings: Mutex<RemoteCacheMissTimings>,
 }
 
 #[napi]
 impl HttpRemoteCache {
     #[napi(constructor)]
-    pub fn new() -> Self {
+    pub fn new(options: Option<HttpRemoteCacheOptions>) -> napi::Result<Self> {
+        let options = options.unwrap_or_default();
         let mut headers = header::HeaderMap::new();
         let auth_token = env::var("NX_SELF_HOSTED_REMOTE_CACHE_ACCESS_TOKEN");
         if let Ok(token) = auth_token {
@@ -56,13 +167,30 @@ impl HttpRemoteCache {
             }
         }
 
-        HttpRemoteCache {
+        Ok(HttpRemoteCache {
             client: client_builder
                 .build()
                 .expect("Failed to create HTTP client"),
             url: env::var("NX_SELF_HOSTED_REMOTE_CACHE_SERVER")
                 .expect("NX_REMOTE_CACHE_URL must be set"),
-        }
+            compression: CompressionOptions::from_env(),
+            server_accepts_zstd: AtomicBool::new(false),
+            signer: ArtifactSigner::from_env()?,
+            retries: RetryPolicy::from_env(),
+            circuit_breaker: CircuitBreaker::from_env(),
+            server_supports_exists: AtomicBool::new(true),
+            concurrency: env::var("NX_SELF_HOSTED_REMOTE_CACHE_CONCURRENCY")
+                .ok()
+                .and_then(|v| v.trim().parse::<usize>().ok())
+                .filter(|&concurrency| concurrency > 0)
+                .unwrap_or(DEFAULT_CONCURRENCY),
+            mode: options.mode.unwrap_or_else(RemoteCacheMode::from_env),
+            misses: match options.negative_cache_ttl {
+                Some(ttl) => NegativeCache::new(Duration::from_secs(ttl.into())),
+                None => NegativeCache::from_env(),
+            },
+            miss_timings: Mutex::new(RemoteCacheMissTimings::default()),
+        })
     }
 
     #[napi]
@@ -74,31 +202,237 @@ impl HttpRemoteCache {
         let span = tracing::trace_span!("retrieve", hash = %hash);
         let _guard = span.enter();
 
+        if !self.mode.reads() {
+            trace!("Remote cache is write-only, skipping lookup");
+            return Ok(None);
+        }
+        if self.circuit_breaker.is_open() {
+            trace!("Remote cache circuit is open, skipping lookup");
+            return Ok(None);
+        }
+        if self.misses.contains(&hash) {
+            trace!(
+                "{} recently missed the remote cache, skipping lookup",
+                &hash
+            );
+            return Ok(None);
+        }
+        let start = Instant::now();
+
         let url: String = format!("{}/v1/cache/{}", self.url, hash);
         let response = self
-            .client
-            .get(&url)
-            .header("Accept", "application/octet-stream")
-            .send()
+            .send(|| {
+                self.client
+                    .get(&url)
+                    .header("Accept", "application/octet-stream")
+                    .header(header::ACCEPT_ENCODING, self.compression.accept_encoding())
+            })
             .await;
-        if let Ok(resp) = response {
+        if let Some(resp) = self.settle(response)? {
             trace!("HTTP response status: {}", resp.status());
             let status = resp.status();
+            self.record_server_encodings(&resp);
 
             match status {
                 StatusCode::OK => {
-                    Ok(Some(
-                        // response is an application/octet-stream containing a tarball
-                        // we need to extract the tarball and return the path to the extracted files
-                        Self::download_and_extract_from_result(resp, cache_directory, hash).await?,
-                    ))
+                    let encoding = TarballEncoding::from_content_encoding(
+                        resp.headers().get(header::CONTENT_ENCODING),
+                    )?;
+                    let signature = resp
+                        .headers()
+                        .get(SIGNATURE_HEADER)
+                        .and_then(|value| value.to_str().ok())
+                        .map(String::from);
+                    // Don't bother downloading what can't be trusted.
+                    if self.signer.verifies() && signature.is_none() {
+                        return Err(HttpRemoteCacheErrors::InvalidSignature(format!(
+                            "{} is not signed",
+                            hash
+                        ))
+                        .into());
+                    }
+                    // response is an application/octet-stream containing a tarball
+                    let downloaded = if self.signer.verifies() {
+                        self.download_verify_and_extract(
+                            resp,
+                            encoding,
+                            cache_directory.clone(),
+                            hash.clone(),
+                            signature.as_deref(),
+                        )
+                        .await
+                    } else {
+                        Self::download_and_extract_from_result(
+                            resp,
+                            encoding,
+                            cache_directory.clone(),
+                            hash.clone(),
+                        )
+                        .await
+                    };
+                    match downloaded {
+                        Ok(result) => {
+                            debug!("Remote cache hit for {} in {:?}", &hash, start.elapsed());
+                            Ok(Some(result))
+                        }
+                        Err(e) if e.is::<IntegrityError>() => {
+                            warn!("{} ({}), treating it as a cache miss", e, hash);
+                            fs::remove_dir_all(Path::new(&cache_directory).join(&hash)).ok();
+                            Ok(None)
+                        }
+                        Err(e) => match e.downcast::<HttpRemoteCacheErrors>() {
+                            Ok(e) => Err(e.into()),
+                            Err(e) => Err(e.into()),
+                        },
+                    }
+                }
+                StatusCode::NOT_FOUND => {
+                    let elapsed = start.elapsed();
+                    debug!("Remote cache miss for {} in {:?}", &hash, elapsed);
+                    self.miss_timings.lock().unwrap().record(elapsed);
+                    self.misses.insert(&hash);
+                    Ok(None)
                 }
-                StatusCode::NOT_FOUND => Ok(None),
                 _ => Err(convert_response_to_error(resp).await.into()),
             }
         } else {
-            Err(HttpRemoteCacheErrors::RequestError(response.unwrap_err().to_string()).into())
+            Ok(None)
+        }
+    }
+
+    /// Look up which of `hashes` the server holds with a single request.
+    /// The result is index-aligned with `hashes`, or `null` when the server
+    /// doesn't implement `POST /v1/cache/exists`. Hashes that recently
+    /// missed are not looked up again.
+    #[napi]
+    pub async fn exists_batch(&self, hashes: Vec<String>) -> napi::Result<Option<Vec<bool>>> {
+        if hashes.is_empty() {
+            return Ok(Some(vec![]));
+        }
+        if !self.mode.reads() {
+            trace!("Remote cache is write-only, skipping lookup");
+            return Ok(Some(vec![false; hashes.len()]));
+        }
+        if self.circuit_breaker.is_open() {
+            trace!("Remote cache circuit is open, skipping lookup");
+            return Ok(Some(vec![false; hashes.len()]));
+        }
+        if !self.server_supports_exists.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let unknown = hashes
+            .iter()
+            .filter(|hash| !self.misses.contains(hash))
+            .cloned()
+            .collect::<Vec<_>>();
+        if unknown.is_empty() {
+            trace!(
+                "All {} hashes recently missed the remote cache",
+                hashes.len()
+            );
+            return Ok(Some(vec![false; hashes.len()]));
+        }
+        let start = Instant::now();
+
+        let url: String = format!("{}/v1/cache/exists", self.url);
+        let body = serde_json::to_vec(&ExistsBatch {
+            hashes: unknown.clone(),
+        })
+        .map_err(anyhow::Error::from)?;
+        let response = self
+            .send(|| {
+                self.client
+                    .post(&url)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header("Accept", "application/json")
+                    .body(body.clone())
+            })
+            .await;
+        let Some(response) = self.settle(response)? else {
+            return Ok(None);
+        };
+
+        match response.status() {
+            StatusCode::OK => {
+                let bytes = response
+                    .bytes()
+                    .await
+                    .map_err(|e| HttpRemoteCacheErrors::RequestError(report_request_error(&e)))?;
+                let found: ExistsBatch = serde_json::from_slice(&bytes).map_err(|e| {
+                    HttpRemoteCacheErrors::Misconfigured(format!(
+                        "Invalid response to {}: {}",
+                        url, e
+                    ))
+                })?;
+                let found = found
+                    .hashes
+                    .into_iter()
+                    .collect::<std::collections::HashSet<_>>();
+                for hash in unknown.iter().filter(|hash| !found.contains(*hash)) {
+                    self.misses.insert(hash);
+                }
+                debug!(
+                    "Remote cache holds {} of {} hashes, looked up in {:?}",
+                    found.len(),
+                    unknown.len(),
+                    start.elapsed()
+                );
+                Ok(Some(
+                    hashes.iter().map(|hash| found.contains(hash)).collect(),
+                ))
+            }
+            // Servers that predate the endpoint.
+            StatusCode::NOT_FOUND
+            | StatusCode::METHOD_NOT_ALLOWED
+            | StatusCode::NOT_IMPLEMENTED => {
+                debug!("Remote cache does not support batch lookups");
+                self.server_supports_exists.store(false, Ordering::Relaxed);
+                Ok(None)
+            }
+            _ => Err(convert_response_to_error(response).await.into()),
+        }
+    }
+
+    /// Retrieve many artifacts, index-aligned with `hashes`. A single
+    /// `exists_batch` lookup decides which hashes are worth downloading; the
+    /// hits are then fetched concurrently, a bounded number at a time.
+    #[napi]
+    pub async fn retrieve_batch(
+        &self,
+        hashes: Vec<String>,
+        cache_directory: String,
+    ) -> napi::Result<Vec<Option<CachedResult>>> {
+        let exists = self.exists_batch(hashes.clone()).await?;
+        let candidates = hashes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| exists.as_ref().is_none_or(|exists| exists[*i]))
+            .collect::<Vec<_>>();
+        trace!(
+            "Retrieving {} of {} hashes from remote cache",
+            candidates.len(),
+            hashes.len()
+        );
+
+        let mut results = vec![None; hashes.len()];
+        let mut retrieved = stream::iter(candidates)
+            .map(|(i, hash)| {
+                let cache_directory = cache_directory.clone();
+                async move { (i, self.retrieve(hash.clone(), cache_directory).await) }
+            })
+            .buffer_unordered(self.concurrency);
+        while let Some((i, result)) = retrieved.next().await {
+            results[i] = result?;
         }
+        Ok(results)
+    }
+
+    /// Time spent on lookups that missed since this cache was created.
+    /// Hashes ruled out by `exists_batch`, or skipped as recent misses,
+    /// are not counted.
+    #[napi]
+    pub fn miss_timings(&self) -> RemoteCacheMissTimings {
+        self.miss_timings.lock().unwrap().clone()
     }
 
     #[napi]
@@ -108,77 +442,117 @@ impl HttpRemoteCache {
         cache_directory: String,
         terminal_output: String,
         code: u32,
+        provenance: Option<CacheProvenance>,
     ) -> napi::Result<bool> {
         let span = tracing::trace_span!("store", hash = %hash);
         let _guard = span.enter();
 
+        if !self.mode.writes() {
+            trace!("Remote cache is read-only, not storing {}", &hash);
+            return Ok(false);
+        }
+        // Readers that verify signatures can't produce them; anything they
+        // uploaded would be rejected by every other verifying reader.
+        if self.signer.verifies() && !self.signer.signs() {
+            trace!("Not storing {} without a signing key", &hash);
+            return Ok(false);
+        }
+        if self.circuit_breaker.is_open() {
+            trace!("Remote cache circuit is open, skipping upload");
+            return Ok(false);
+        }
+
         // We can change the creation of the tar in a future version without
         // worrying about breaking existing user cache's, because when the
         // user updates their task's hashes will be changed... so users
         // retrieving old hashes will not be affected, and new entries
         // will have distinct hashes.
 
-        // create a tarball in memory from the cache dir
-        let tar_gz: Vec<u8> = Vec::new();
-        let enc = flate2::write::GzEncoder::new(tar_gz, Compression::default());
-        let mut archive = Builder::new(enc);
-        archive.follow_symlinks(false);
-        trace!("Created tar file for writing");
-
-        let cache_path = Path::new(&cache_directory);
-        let outputs_path = cache_path.join(&hash);
-
-        trace!("Adding cache artifacts to tarball");
-        archive.append_dir_all("", &outputs_path)?;
-        trace!("Added cache directory to tarball");
-
-        trace!("Adding terminal output to tarball");
-        let mut terminal_output_header = tar::Header::new_old();
-        let terminal_output_bytes = terminal_output.as_bytes();
-        terminal_output_header.set_size(terminal_output_bytes.len() as u64);
-        terminal_output_header.set_cksum(); // Ensure the checksum is set correctly
-        archive.append_data(
-            &mut terminal_output_header,
-            "terminalOutput",
-            terminal_output_bytes,
-        )?;
-        trace!("Added terminal output to tarball");
-
-        trace!("Adding code to tarball");
-        let mut code_header = tar::Header::new_old();
-        code_header.set_size(4);
-        code_header.set_cksum(); // Ensure the checksum is set correctly
-        archive.append_data(&mut code_header, "code", &code.to_be_bytes()[..])?;
-        trace!("Added code to tarball");
-
-        trace!("Finishing tarball");
-        archive
-            .finish() // Finish the archive to get the inner bytes
-            .map_err(|e| anyhow::anyhow!(format!("Failed to finish tarball: {}", e)))?;
-        trace!("Finished tarball");
-
-        trace!("Reading tarball into memory");
-        let archive_bytes = archive.into_inner()?;
-        let buffer = archive_bytes.finish()?;
-        trace!("read tarball into memory");
-
-        let url: String = format!("{}/v1/cache/{}", self.url, hash);
-        let response = self
-            .client
-            .put(&url)
-            .body(buffer) // Convert the bytes to a Vec<u8> for the request body
-            .send()
+        let mut encoding = self.upload_encoding();
+        let response = loop {
+            // The tarball is written to disk next to the cache entry rather than
+            // kept in memory: outputs can be gigabytes, and the protocol requires
+            // a Content-Length up front, so it can't be streamed straight out.
+            let cache_path = PathBuf::from(&cache_directory);
+            let tarball = TemporaryTarball::new(&cache_path, &hash, encoding);
+            let outputs_path = cache_path.join(&hash);
+            let tarball_path = tarball.path.clone();
+            let terminal_output = terminal_output.clone();
+            let provenance = provenance.clone();
+            let level = self.compression.level_for(encoding);
+            let signs = self.signer.signs();
+            let (tarball_size, digest) = tokio::task::spawn_blocking(move || {
+                let size = write_tarball(
+                    &tarball_path,
+                    encoding,
+                    level,
+                    &outputs_path,
+                    &terminal_output,
+                    code,
+                    provenance.as_ref(),
+                )?;
+                let digest = if signs {
+                    Some(digest_file(&tarball_path)?)
+                } else {
+                    None
+                };
+                anyhow::Ok((size, digest))
+            })
             .await
-            .map_err(|e| {
-                napi::Error::from(HttpRemoteCacheErrors::RequestError(report_request_error(
-                    &e,
-                )))
-            })?;
+            .map_err(|e| anyhow::anyhow!("Failed to create tarball: {}", e))??;
+            trace!(
+                "Created {} byte {} tarball at {:?}",
+                tarball_size,
+                encoding.content_encoding(),
+                &tarball.path
+            );
+
+            let url: String = format!("{}/v1/cache/{}", self.url, hash);
+            let signature = digest.and_then(|digest| self.signer.sign(&hash, &digest));
+            let response = self
+                .send(|| {
+                    let mut request = self
+                        .client
+                        .put(&url)
+                        .header(header::CONTENT_LENGTH, tarball_size);
+                    // Gzip uploads stay header-less, exactly as servers that
+                    // predate negotiation expect them.
+                    if encoding == TarballEncoding::Zstd {
+                        request =
+                            request.header(header::CONTENT_ENCODING, encoding.content_encoding());
+                    }
+                    if let Some(signature) = &signature {
+                        request = request.header(SIGNATURE_HEADER, signature.as_str());
+                    }
+                    request.body(stream_file(tarball.path.clone(), tarball_size))
+                })
+                .await;
+            let Some(response) = self.settle(response)? else {
+                return Ok(false);
+            };
+            self.record_server_encodings(&response);
+
+            if response.status() == StatusCode::UNSUPPORTED_MEDIA_TYPE
+                && encoding == TarballEncoding::Zstd
+            {
+                debug!("Remote cache rejected a zstd upload, falling back to gzip");
+                self.server_accepts_zstd.store(false, Ordering::Relaxed);
+                encoding = TarballEncoding::Gzip;
+                continue;
+            }
+            break response;
+        };
 
         match response.status() {
-            StatusCode::OK => Ok(true),
+            StatusCode::OK => {
+                self.misses.remove(&hash);
+                Ok(true)
+            }
             // Cache entry already exists, silently do not store new data
-            StatusCode::CONFLICT => Ok(false),
+            StatusCode::CONFLICT => {
+                self.misses.remove(&hash);
+                Ok(false)
+            }
             // User is authorized but server does not allow
             // cache storage for whatever reason (e.g. read-only token.)
             StatusCode::FORBIDDEN => Ok(false),
@@ -186,106 +560,283 @@ impl HttpRemoteCache {
         }
     }
 
+    /// Send the request `build` creates, retrying connection errors and
+    /// retryable statuses with backoff. Once retries run out the last
+    /// outcome is returned as is.
+    async fn send(
+        &self,
+        build: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let mut attempt = 0;
+        loop {
+            let result = build().send().await;
+            let retry_after = match &result {
+                Ok(response) if is_retryable_status(response.status()) => {
+                    retry_after(response.headers())
+                }
+                Err(e) if is_retryable_error(e) => None,
+                _ => return result,
+            };
+            if attempt >= self.retries.max_retries {
+                return result;
+            }
+            attempt += 1;
+            let delay = self.retries.delay(attempt, retry_after);
+            debug!(
+                "Remote cache request failed, retrying in {:?} ({}/{})",
+                delay, attempt, self.retries.max_retries
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Report the outcome of a request to the circuit breaker. A transient
+    /// failure that outlived its retries becomes `None`, so a flaky server
+    /// degrades to cache misses rather than failing the task.
+    fn settle(
+        &self,
+        result: Result<reqwest::Response, reqwest::Error>,
+    ) -> Result<Option<reqwest::Response>, HttpRemoteCacheErrors> {
+        match result {
+            Ok(response) if is_retryable_status(response.status()) => {
+                self.record_failure(&format!("the server responded with {}", response.status()));
+                Ok(None)
+            }
+            Ok(response) => {
+                self.circuit_breaker.record_success();
+                Ok(Some(response))
+            }
+            Err(e) if is_retryable_error(&e) => {
+                self.record_failure(&report_request_error(&e));
+                Ok(None)
+            }
+            Err(e) => Err(HttpRemoteCacheErrors::RequestError(report_request_error(
+                &e,
+            ))),
+        }
+    }
+
+    fn record_failure(&self, reason: &str) {
+        warn!("Remote cache request failed: {}", reason);
+        if self.circuit_breaker.record_failure() {
+            warn!("The remote cache keeps failing, skipping it for the rest of this process");
+        }
+    }
+
+    /// The codec for the next upload: zstd only when preferred and the server
+    /// is known to accept it.
+    fn upload_encoding(&self) -> TarballEncoding {
+        match self.compression.preferred {
+            TarballEncoding::Zstd if self.server_accepts_zstd.load(Ordering::Relaxed) => {
+                TarballEncoding::Zstd
+            }
+            _ => TarballEncoding::Gzip,
+        }
+    }
+
+    /// Remember whether the server has advertised zstd support, either in an
+    /// `Accept-Encoding` response header or by serving a zstd artifact.
+    fn record_server_encodings(&self, response: &reqwest::Response) {
+        let headers = response.headers();
+        let advertises_zstd = headers
+            .get_all(header::ACCEPT_ENCODING)
+            .iter()
+            .chain(headers.get_all(header::CONTENT_ENCODING).iter())
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|coding| {
+                coding
+                    .split(';')
+                    .next()
+                    .is_some_and(|name| name.trim().eq_ignore_ascii_case("zstd"))
+            });
+        if advertises_zstd {
+            self.server_accepts_zstd.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Extract the response body while it downloads: chunks are handed to a
+    /// blocking extraction task as they arrive instead of being buffered.
     async fn download_and_extract_from_result(
         response: reqwest::Response,
+        encoding: TarballEncoding,
         cache_directory: String,
         hash: String,
     ) -> anyhow::Result<CachedResult> {
-        let content = response
-            .bytes()
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to read remote cache response body: {}", e))?;
-        trace!("Downloaded {} bytes from remote cache", content.len());
-        Self::extract_tarball(content.as_ref(), &cache_directory, &hash)
-    }
-
-    /// Extract a gzipped cache tarball into `<cache_directory>/<hash>`.
-    ///
-    /// Uses `tar`'s `unpack_in` so a malicious cache server can't escape
-    /// `output_dir` via `..`, absolute paths, or symlinks.
-    fn extract_tarball(
-        content: &[u8],
-        cache_directory: &str,
-        hash: &str,
+        let (result, _) = Self::download(response, move |body| {
+            extract_tarball(body, encoding, &cache_directory, &hash)
+        })
+        .await?;
+        Ok(result)
+    }
+
+    /// Download the response body to a temporary tarball and extract it only
+    /// once its signature checks out, so an artifact that can't be trusted
+    /// never writes a byte into the cache directory.
+    async fn download_verify_and_extract(
+        &self,
+        response: reqwest::Response,
+        encoding: TarballEncoding,
+        cache_directory: String,
+        hash: String,
+        signature: Option<&str>,
     ) -> anyhow::Result<CachedResult> {
-        let tar = flate2::read::GzDecoder::new(content);
-        let mut archive = Archive::new(tar);
-        let entries = archive
-            .entries() // Get the entries in the archive
-            .map_err(|_| anyhow::anyhow!("Failed to read entries from tarball"))?;
-
-        let mut code: Option<i16> = None;
-        let mut terminal_output: Option<String> = None;
-        let mut size: i64 = 0;
-
-        let output_dir = Path::new(cache_directory).join(hash);
-        // `unpack_in` canonicalizes `output_dir`, so it must exist beforehand.
-        fs::create_dir_all(&output_dir)?;
-
-        // Extract the archive to the specified cache directory
-        for entry in entries {
-            let mut entry =
-                entry.map_err(|_| anyhow::anyhow!("Failed to read entry from tarball"))?;
-
-            let entry_path = entry
-                .path()
-                .map_err(|e| anyhow::anyhow!("Invalid entry path in cache artifact: {}", e))?
-                .to_string_lossy()
-                .into_owned();
-
-            if entry_path == "code" {
-                let code_file_bytes = entry.bytes().collect::<Result<Vec<u8>, _>>()?;
-                // The exit code is stored as a 4-byte big-endian integer (see `store`).
-                let code_bytes: [u8; 4] = code_file_bytes
-                    .as_slice()
-                    .try_into()
-                    .map_err(|_| anyhow::anyhow!("Invalid exit code in cache artifact"))?;
-                code = Some(u32::from_be_bytes(code_bytes) as i16);
-                trace!("Retrieved exit code from cache: {}", code.unwrap());
-            } else if entry_path == "terminalOutput" {
-                let terminal_output_bytes = entry.bytes().collect::<Result<Vec<u8>, _>>()?;
-                let terminal_output_size = terminal_output_bytes.len();
-
-                terminal_output = Some(String::from_utf8(terminal_output_bytes)?);
-                size += terminal_output_size as i64;
-
-                trace!(
-                    "Retrieved terminal output from cache: {} bytes",
-                    terminal_output_size
-                );
-            } else {
-                trace!(
-                    "Extracting entry {} into {}",
-                    entry_path,
-                    output_dir.display()
-                );
-                let is_file = entry.header().entry_type().is_file();
-                let entry_size = entry.size();
-                // Reject entries `unpack_in` skips (`..`) or refuses (symlink escape).
-                let unpacked = entry
-                    .unpack_in(&output_dir)
-                    .map_err(|e| anyhow::anyhow!("Failed to unpack entry: {}", e))?;
-                if !unpacked {
-                    return Err(anyhow::anyhow!(
-                        "Refusing to extract cache entry with unsafe path: {}",
-                        entry_path
-                    ));
-                }
-                if is_file {
-                    size += entry_size as i64;
+        let tarball = TemporaryTarball::new(Path::new(&cache_directory), &hash, encoding);
+        let tarball_path = tarball.path.clone();
+        let (_, digest) = Self::download(response, move |mut body| {
+            if let Some(parent) = tarball_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            io::copy(&mut body, &mut File::create(&tarball_path)?)?;
+            Ok(())
+        })
+        .await?;
+        self.signer.verify(&hash, &digest, signature)?;
+
+        tokio::task::spawn_blocking(move || {
+            // Removed once extracted.
+            let tarball = tarball;
+            extract_tarball(
+                File::open(&tarball.path)?,
+                encoding,
+                &cache_directory,
+                &hash,
+            )
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to extract remote cache artifact: {}", e))?
+    }
+
+    /// Hand the response body to `consume`, which reads it on a blocking task
+    /// as it arrives. Also returns the SHA-256 of the body, which signatures
+    /// cover.
+    async fn download<T: Send + 'static>(
+        mut response: reqwest::Response,
+        consume: impl FnOnce(ChannelReader) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<(T, Digest)> {
+        let (tx, rx) = mpsc::channel(STREAM_CHANNEL_CAPACITY);
+        let consumer = tokio::task::spawn_blocking(move || consume(ChannelReader::new(rx)));
+
+        let mut downloaded = 0;
+        let mut digest = DigestContext::new(&SHA256);
+        loop {
+            let chunk = match response.chunk().await {
+                Ok(Some(chunk)) => Ok(chunk),
+                Ok(None) => break,
+                Err(e) => Err(io::Error::other(format!(
+                    "Failed to read remote cache response body: {}",
+                    e
+                ))),
+            };
+            let failed = chunk.is_err();
+            if let Ok(chunk) = &chunk {
+                downloaded += chunk.len();
+                digest.update(chunk);
+            }
+            // The consumer stops reading once it fails; its error is the one
+            // worth reporting.
+            if tx.send(chunk).await.is_err() || failed {
+                break;
+            }
+        }
+        drop(tx);
+        trace!("Downloaded {} bytes from remote cache", downloaded);
+
+        let result = consumer
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read remote cache artifact: {}", e))??;
+        Ok((result, digest.finish()))
+    }
+}
+
+/// Stream the file at `path` as a request body of `len` bytes, reading it on
+/// a blocking task so the upload never holds more than a few chunks.
+fn stream_file(path: PathBuf, len: u64) -> Body {
+    let (tx, rx) = mpsc::channel(STREAM_CHANNEL_CAPACITY);
+    tokio::task::spawn_blocking(move || {
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                tx.blocking_send(Err(e)).ok();
+                return;
+            }
+        };
+        loop {
+            let mut chunk = vec![0; STREAM_CHUNK_SIZE];
+            let chunk = match file.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => {
+                    chunk.truncate(read);
+                    Ok(Bytes::from(chunk))
                 }
+                Err(e) => Err(e),
+            };
+            let failed = chunk.is_err();
+            // The receiver is gone once the request has failed.
+            if tx.blocking_send(chunk).is_err() || failed {
+                break;
             }
         }
+    });
+    Body::wrap(ChannelBody { rx, remaining: len })
+}
 
-        trace!("Extracted tarball to {}", output_dir.display());
+/// A request body fed with chunks from a blocking task.
+struct ChannelBody {
+    rx: mpsc::Receiver<io::Result<Bytes>>,
+    remaining: u64,
+}
 
-        let code = code.ok_or_else(|| anyhow::anyhow!("Exit code not found in cache artifact"))?;
-        Ok(CachedResult {
-            terminal_output,
-            code,
-            outputs_path: output_dir.to_string_lossy().into_owned(),
-            size: Some(size),
-        })
+impl http_body::Body for ChannelBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let chunk = match self.rx.poll_recv(cx) {
+            Poll::Ready(chunk) => chunk,
+            Poll::Pending => return Poll::Pending,
+        };
+        if let Some(Ok(data)) = &chunk {
+            self.remaining = self.remaining.saturating_sub(data.len() as u64);
+        }
+        Poll::Ready(chunk.map(|chunk| chunk.map(Frame::data)))
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
+
+/// Reads the chunks of a streamed response body as they are received, for
+/// extraction on a blocking task.
+struct ChannelReader {
+    rx: mpsc::Receiver<io::Result<Bytes>>,
+    chunk: Bytes,
+}
+
+impl ChannelReader {
+    fn new(rx: mpsc::Receiver<io::Result<Bytes>>) -> Self {
+        Self {
+            rx,
+            chunk: Bytes::new(),
+        }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => self.chunk = chunk?,
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk.split_to(len));
+        Ok(len)
     }
 }
 
@@ -293,224 +844,363 @@ impl HttpRemoteCache {
 mod test {
     use super::*;
     use assert_fs::TempDir;
-    use flate2::Compression;
-    use flate2::write::GzEncoder;
-    use tar::{Builder, EntryType, Header};
-
-    /// Forge a tar entry, writing `name` straight into the header to bypass the
-    /// `tar` Builder's `..`/absolute-path validation, like a hostile server.
-    fn raw_entry(name: &str, ty: EntryType, link: Option<&str>, data: &[u8]) -> (Header, Vec<u8>) {
-        let mut header = Header::new_ustar();
-        header.set_size(data.len() as u64);
-        header.set_entry_type(ty);
-        header.set_mode(0o644);
-        header.set_mtime(0);
-        if let Some(target) = link {
-            header.set_link_name(target).unwrap();
-        }
-        let name_bytes = name.as_bytes();
-        header.as_mut_bytes()[..name_bytes.len()].copy_from_slice(name_bytes);
-        header.set_cksum();
-        (header, data.to_vec())
-    }
-
-    fn code_entry(code: u32) -> (Header, Vec<u8>) {
-        raw_entry("code", EntryType::Regular, None, &code.to_be_bytes())
-    }
-
-    fn terminal_output_entry(output: &str) -> (Header, Vec<u8>) {
-        raw_entry(
-            "terminalOutput",
-            EntryType::Regular,
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn extract_streams_tarball_across_chunks() {
+        let temp = TempDir::new().unwrap();
+        let outputs = temp.join("outputs");
+        std::fs::create_dir_all(outputs.join("dist")).unwrap();
+        std::fs::write(outputs.join("dist").join("main.js"), "console.log(1)").unwrap();
+        let tarball = temp.join(format!("artifact.{}", TarballEncoding::Gzip.extension()));
+        write_tarball(
+            &tarball,
+            TarballEncoding::Gzip,
+            TarballEncoding::Gzip.default_level(),
+            &outputs,
+            "build complete",
+            0,
             None,
-            output.as_bytes(),
         )
+        .unwrap();
+        let tar = std::fs::read(&tarball).unwrap();
+        let cache_dir = temp.join("cache");
+
+        // Feed the tarball in small chunks, the way a response body arrives.
+        let (tx, rx) = mpsc::channel(STREAM_CHANNEL_CAPACITY);
+        let sender = std::thread::spawn(move || {
+            for chunk in tar.chunks(7) {
+                // The extraction may stop before the gzip trailer.
+                if tx.blocking_send(Ok(Bytes::copy_from_slice(chunk))).is_err() {
+                    break;
+                }
+            }
+        });
+        let result = extract_tarball(
+            ChannelReader::new(rx),
+            TarballEncoding::Gzip,
+            cache_dir.to_str().unwrap(),
+            "123",
+        )
+        .expect("a chunked tarball should extract");
+        sender.join().unwrap();
+
+        assert_eq!(result.terminal_output.as_deref(), Some("build complete"));
+        assert_eq!(
+            std::fs::read_to_string(cache_dir.join("123").join("dist").join("main.js")).unwrap(),
+            "console.log(1)"
+        );
     }
 
-    fn build_tar_gz(entries: Vec<(Header, Vec<u8>)>) -> Vec<u8> {
-        let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
-        for (header, data) in &entries {
-            builder.append(header, data.as_slice()).unwrap();
+    #[test]
+    fn compression_level_is_clamped_per_codec() {
+        let options = CompressionOptions {
+            preferred: TarballEncoding::Zstd,
+            level: Some(50),
+        };
+        assert_eq!(options.level_for(TarballEncoding::Gzip), 9);
+        assert_eq!(
+            options.level_for(TarballEncoding::Zstd),
+            *zstd::compression_level_range().end()
+        );
+    }
+
+    /// Serve `responses` in order, one per connection, on a local port.
+    /// Returns the server URL and the number of requests served so far;
+    /// once the responses run out the port stops accepting connections.
+    fn mock_server(responses: Vec<Vec<u8>>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let served = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&served);
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                read_request(&mut stream);
+                counter.fetch_add(1, Ordering::SeqCst);
+                stream.write_all(&response).unwrap();
+            }
+        });
+        (url, served)
+    }
+
+    /// Read a request's head and, if it has one, its body.
+    fn read_request(stream: &mut TcpStream) {
+        let mut request = vec![];
+        let mut byte = [0; 1];
+        while !request.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).unwrap();
+            request.push(byte[0]);
         }
-        builder.into_inner().unwrap().finish().unwrap()
+        let head = String::from_utf8_lossy(&request).to_ascii_lowercase();
+        let content_length = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .map_or(0, |len| len.trim().parse().unwrap());
+        let mut body = vec![0; content_length];
+        stream.read_exact(&mut body).unwrap();
     }
 
-    #[test]
-    fn extract_rejects_parent_dir_traversal() {
+    fn remote_cache(url: String, max_retries: u32, threshold: u32) -> HttpRemoteCache {
+        HttpRemoteCache {
+            client: Client::new(),
+            url,
+            compression: CompressionOptions {
+                preferred: TarballEncoding::Gzip,
+                level: None,
+            },
+            server_accepts_zstd: AtomicBool::new(false),
+            signer: ArtifactSigner::default(),
+            retries: RetryPolicy {
+                max_retries,
+                base_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(10),
+            },
+            circuit_breaker: CircuitBreaker::new(threshold),
+            server_supports_exists: AtomicBool::new(true),
+            concurrency: 2,
+            mode: RemoteCacheMode::readWrite,
+            misses: NegativeCache::new(Duration::from_secs(60)),
+            miss_timings: Mutex::new(RemoteCacheMissTimings::default()),
+        }
+    }
+
+    fn json_response(body: &str) -> Vec<u8> {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .into_bytes()
+    }
+
+    const SERVICE_UNAVAILABLE: &str = "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const NOT_FOUND: &str =
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+    #[tokio::test]
+    async fn badly_signed_artifacts_are_never_extracted() {
         let temp = TempDir::new().unwrap();
-        let cache_dir = temp.join("cache");
-        // `../../escape.txt` from <cache>/123 resolves to <temp>/escape.txt.
-        let tar = build_tar_gz(vec![raw_entry(
-            "../../escape.txt",
-            EntryType::Regular,
+        let outputs = temp.join("outputs");
+        std::fs::create_dir_all(outputs.join("dist")).unwrap();
+        std::fs::write(outputs.join("dist/main.js"), "console.log(1)").unwrap();
+        let tarball = temp.join(format!("artifact.{}", TarballEncoding::Gzip.extension()));
+        write_tarball(
+            &tarball,
+            TarballEncoding::Gzip,
+            TarballEncoding::Gzip.default_level(),
+            &outputs,
+            "build complete",
+            0,
             None,
-            b"PWNED",
-        )]);
+        )
+        .unwrap();
+        let tar = std::fs::read(&tarball).unwrap();
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\n{}: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            SIGNATURE_HEADER,
+            "00".repeat(64),
+            tar.len()
+        )
+        .into_bytes();
+        response.extend(tar);
+        let (url, _) = mock_server(vec![response]);
+        let mut cache = remote_cache(url, 0, 5);
+        cache.signer = ArtifactSigner::with_seed([7; 32]);
+        let cache_dir = temp.join("cache");
+        std::fs::create_dir_all(&cache_dir).unwrap();
 
-        let result = HttpRemoteCache::extract_tarball(&tar, cache_dir.to_str().unwrap(), "123");
+        let result = cache
+            .retrieve("123".into(), cache_dir.to_str().unwrap().into())
+            .await;
 
-        assert!(result.is_err(), "a `..` entry must be rejected");
-        assert!(
-            !temp.join("escape.txt").exists(),
-            "extraction must not write outside the cache directory"
-        );
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 0);
     }
 
-    #[test]
-    fn extract_contains_absolute_paths() {
+    #[tokio::test]
+    async fn retrieve_retries_server_errors() {
         let temp = TempDir::new().unwrap();
-        let cache_dir = temp.join("cache");
-        let abs_target = temp.join("outside").join("pwned.txt");
-        let tar = build_tar_gz(vec![
-            raw_entry(
-                abs_target.to_str().unwrap(),
-                EntryType::Regular,
-                None,
-                b"PWNED",
-            ),
-            code_entry(0),
-            terminal_output_entry("done"),
+        let (url, served) = mock_server(vec![
+            SERVICE_UNAVAILABLE.into(),
+            SERVICE_UNAVAILABLE.into(),
+            NOT_FOUND.into(),
         ]);
+        let cache = remote_cache(url, 3, 5);
 
-        let result = HttpRemoteCache::extract_tarball(&tar, cache_dir.to_str().unwrap(), "123");
+        let result = cache
+            .retrieve("123".into(), temp.to_str().unwrap().into())
+            .await
+            .unwrap();
 
-        // Absolute paths are stripped to relative, so extraction is contained, not rejected.
-        assert!(
-            result.is_ok(),
-            "absolute paths should be contained, not error: {:?}",
-            result.err()
-        );
-        assert!(
-            !abs_target.exists(),
-            "an absolute entry must not escape the cache directory"
-        );
+        assert!(result.is_none());
+        assert_eq!(served.load(Ordering::SeqCst), 3);
+        assert!(!cache.circuit_breaker.is_open());
     }
 
-    #[cfg(unix)]
-    #[test]
-    fn extract_rejects_symlink_escape() {
+    #[tokio::test]
+    async fn store_retries_server_errors() {
         let temp = TempDir::new().unwrap();
-        let cache_dir = temp.join("cache");
-        let outside = temp.join("outside");
-        std::fs::create_dir_all(&outside).unwrap();
-
-        // `evil` -> <temp>/outside, then `evil/pwned.txt` would escape if followed.
-        let tar = build_tar_gz(vec![
-            raw_entry(
-                "evil",
-                EntryType::Symlink,
-                Some(outside.to_str().unwrap()),
-                b"",
-            ),
-            raw_entry("evil/pwned.txt", EntryType::Regular, None, b"PWNED"),
-        ]);
+        std::fs::create_dir_all(temp.join("123")).unwrap();
+        let (url, served) = mock_server(vec![SERVICE_UNAVAILABLE.into(), OK.into()]);
+        let cache = remote_cache(url, 3, 5);
 
-        let result = HttpRemoteCache::extract_tarball(&tar, cache_dir.to_str().unwrap(), "123");
+        let stored = cache
+            .store(
+                "123".into(),
+                temp.to_str().unwrap().into(),
+                "".into(),
+                0,
+                None,
+            )
+            .await
+            .unwrap();
 
-        assert!(
-            result.is_err(),
-            "writing through a symlink must be rejected"
-        );
-        assert!(
-            !outside.join("pwned.txt").exists(),
-            "extraction must not write through a symlink out of the cache directory"
-        );
+        assert!(stored);
+        assert_eq!(served.load(Ordering::SeqCst), 2);
     }
 
-    #[test]
-    fn extract_unpacks_legitimate_tarball() {
+    #[tokio::test]
+    async fn failing_server_is_a_miss_and_opens_the_circuit() {
         let temp = TempDir::new().unwrap();
-        let cache_dir = temp.join("cache");
-        let tar = build_tar_gz(vec![
-            raw_entry("dist/main.js", EntryType::Regular, None, b"console.log(1)"),
-            code_entry(0),
-            terminal_output_entry("build complete"),
-        ]);
+        let (url, served) =
+            mock_server(vec![SERVICE_UNAVAILABLE.into(), SERVICE_UNAVAILABLE.into()]);
+        let cache = remote_cache(url, 1, 1);
 
-        let result = HttpRemoteCache::extract_tarball(&tar, cache_dir.to_str().unwrap(), "123")
-            .expect("legitimate tarball should extract");
+        let result = cache
+            .retrieve("123".into(), temp.to_str().unwrap().into())
+            .await
+            .unwrap();
+        assert!(result.is_none());
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+        assert!(cache.circuit_breaker.is_open());
 
-        assert_eq!(result.code, 0);
-        assert_eq!(result.terminal_output.as_deref(), Some("build complete"));
-        let extracted = cache_dir.join("123").join("dist").join("main.js");
-        assert!(extracted.exists(), "expected extracted output file");
-        assert_eq!(
-            std::fs::read_to_string(&extracted).unwrap(),
-            "console.log(1)"
-        );
-        assert_eq!(
-            result.size,
-            Some(("build complete".len() + "console.log(1)".len()) as i64)
-        );
+        // The server is no longer contacted.
+        let result = cache
+            .retrieve("456".into(), temp.to_str().unwrap().into())
+            .await
+            .unwrap();
+        assert!(result.is_none());
+        assert_eq!(served.load(Ordering::SeqCst), 2);
     }
 
-    #[cfg(unix)]
-    #[test]
-    fn extract_rejects_hardlink_escape() {
+    #[tokio::test]
+    async fn unreachable_server_is_a_miss() {
         let temp = TempDir::new().unwrap();
-        let cache_dir = temp.join("cache");
-        // A file outside the cache dir the hardlink entry tries to reach.
-        let outside = temp.join("outside.txt");
-        std::fs::write(&outside, b"secret").unwrap();
-
-        // A hard link entry whose target points outside output_dir.
-        let tar = build_tar_gz(vec![raw_entry(
-            "evil",
-            EntryType::Link,
-            Some(outside.to_str().unwrap()),
-            b"",
-        )]);
-
-        let result = HttpRemoteCache::extract_tarball(&tar, cache_dir.to_str().unwrap(), "123");
-
-        assert!(
-            result.is_err(),
-            "a hardlink escaping the cache dir must be rejected"
+        // Bind and drop a listener to get a port nothing listens on.
+        let url = format!(
+            "http://{}",
+            TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
         );
+        let cache = remote_cache(url, 1, 5);
+
+        let result = cache
+            .retrieve("123".into(), temp.to_str().unwrap().into())
+            .await
+            .unwrap();
+
+        assert!(result.is_none());
     }
 
-    #[test]
-    fn extract_rejects_short_code_entry() {
+    #[tokio::test]
+    async fn retrieve_batch_only_downloads_existing_hashes() {
         let temp = TempDir::new().unwrap();
-        let cache_dir = temp.join("cache");
-        let tar = build_tar_gz(vec![raw_entry("code", EntryType::Regular, None, b"\0")]);
+        let (url, served) =
+            mock_server(vec![json_response(r#"{"hashes":["2"]}"#), NOT_FOUND.into()]);
+        let cache = remote_cache(url, 0, 5);
 
-        let result = HttpRemoteCache::extract_tarball(&tar, cache_dir.to_str().unwrap(), "123");
+        let results = cache
+            .retrieve_batch(
+                vec!["1".into(), "2".into(), "3".into()],
+                temp.to_str().unwrap().into(),
+            )
+            .await
+            .unwrap();
 
-        assert!(
-            result.is_err(),
-            "a short code entry must be rejected, not panic"
-        );
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(Option::is_none));
+        // One lookup plus a single download.
+        assert_eq!(served.load(Ordering::SeqCst), 2);
     }
 
-    #[test]
-    fn extract_rejects_missing_code_entry() {
+    #[tokio::test]
+    async fn retrieve_batch_falls_back_without_exists_endpoint() {
         let temp = TempDir::new().unwrap();
-        let cache_dir = temp.join("cache");
-        let tar = build_tar_gz(vec![terminal_output_entry("done")]);
+        let (url, served) = mock_server(vec![NOT_FOUND.into(), NOT_FOUND.into(), NOT_FOUND.into()]);
+        let cache = remote_cache(url, 0, 5);
 
-        let result = HttpRemoteCache::extract_tarball(&tar, cache_dir.to_str().unwrap(), "123");
+        let results = cache
+            .retrieve_batch(vec!["1".into(), "2".into()], temp.to_str().unwrap().into())
+            .await
+            .unwrap();
 
-        assert!(
-            result.is_err(),
-            "a missing code entry must be rejected, not panic"
-        );
+        assert_eq!(results.len(), 2);
+        assert_eq!(served.load(Ordering::SeqCst), 3);
+        assert!(!cache.server_supports_exists.load(Ordering::Relaxed));
+        assert_eq!(cache.exists_batch(vec!["1".into()]).await.unwrap(), None);
     }
 
-    #[test]
-    fn extract_reads_full_exit_code() {
+    #[tokio::test]
+    async fn misses_are_not_looked_up_again() {
         let temp = TempDir::new().unwrap();
-        let cache_dir = temp.join("cache");
-        // The code is stored as a 4-byte big-endian int; the reader must consume
-        // all 4 bytes rather than truncating a nonzero code to 0.
-        let tar = build_tar_gz(vec![code_entry(1)]);
+        let (url, served) = mock_server(vec![NOT_FOUND.into(), json_response(r#"{"hashes":[]}"#)]);
+        let cache = remote_cache(url, 0, 5);
 
-        let result = HttpRemoteCache::extract_tarball(&tar, cache_dir.to_str().unwrap(), "123")
-            .expect("a valid code entry should extract");
+        for _ in 0..2 {
+            let result = cache
+                .retrieve("1".into(), temp.to_str().unwrap().into())
+                .await
+                .unwrap();
+            assert!(result.is_none());
+        }
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.miss_timings().count, 1);
+        assert!(cache.miss_timings().total_ms > 0.0);
 
-        assert_eq!(
-            result.code, 1,
-            "exit code must round-trip, not truncate to 0"
-        );
+        // Only the hash that hasn't missed yet is looked up, and its miss is
+        // remembered in turn.
+        let results = cache
+            .retrieve_batch(vec!["1".into(), "2".into()], temp.to_str().unwrap().into())
+            .await
+            .unwrap();
+        assert!(results.iter().all(Option::is_none));
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+        assert!(cache.misses.contains("2"));
+    }
+
+    #[tokio::test]
+    async fn modes_restrict_reads_and_writes() {
+        let temp = TempDir::new().unwrap();
+        std::fs::create_dir_all(temp.join("123")).unwrap();
+        let (url, served) = mock_server(vec![]);
+        let mut cache = remote_cache(url, 0, 5);
+
+        cache.mode = RemoteCacheMode::readOnly;
+        let stored = cache
+            .store(
+                "123".into(),
+                temp.to_str().unwrap().into(),
+                "".into(),
+                0,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(!stored);
+
+        cache.mode = RemoteCacheMode::writeOnly;
+        let results = cache
+            .retrieve_batch(vec!["123".into()], temp.to_str().unwrap().into())
+            .await
+            .unwrap();
+        assert!(results[0].is_none());
+        assert_eq!(served.load(Ordering::SeqCst), 0);
     }
 }
@@ -0,0 +1,182 @@
+use rusqlite::types::Value;
+
+use super::provenance::{self, CacheEntryOrigin, CacheProvenance};
+
+/// An entry of the local cache, joined with the details of its task.
+#[napi(object)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheEntry {
+    pub hash: String,
+    pub project: Option<String>,
+    pub target: Option<String>,
+    pub configuration: Option<String>,
+    pub code: i16,
+    /// Size in bytes
+    pub size: i64,
+    /// Milliseconds since the epoch
+    pub created_at: i64,
+    /// Milliseconds since the epoch
+    pub accessed_at: i64,
+    /// How many times the entry was restored
+    pub hits: i64,
+    pub provenance: CacheProvenance,
+}
+
+/// What to sort cache entries by.
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheEntrySortKey {
+    #[allow(non_camel_case_types)]
+    size,
+    #[allow(non_camel_case_types)]
+    createdAt,
+    #[allow(non_camel_case_types)]
+    accessedAt,
+    #[allow(non_camel_case_types)]
+    hits,
+    #[allow(non_camel_case_types)]
+    project,
+}
+
+impl CacheEntrySortKey {
+    fn column(&self) -> &'static str {
+        match self {
+            CacheEntrySortKey::size => "size",
+            CacheEntrySortKey::createdAt => "created_at",
+            CacheEntrySortKey::accessedAt => "accessed_at",
+            CacheEntrySortKey::hits => "hits",
+            CacheEntrySortKey::project => "project",
+        }
+    }
+}
+
+/// Which cache entries to list, in which order.
+#[napi(object)]
+#[derive(Default, Clone, Debug)]
+pub struct CacheEntryQuery {
+    pub project: Option<String>,
+    pub target: Option<String>,
+    pub configuration: Option<String>,
+    /// Only list entries of failed (`true`) or successful (`false`) tasks.
+    pub failed: Option<bool>,
+    /// Only list entries of at least this many bytes.
+    pub min_size: Option<i64>,
+    /// Only list entries produced on this machine.
+    pub machine_id: Option<String>,
+    /// Only list entries produced at this commit.
+    pub git_commit: Option<String>,
+    /// Only list entries produced on this branch.
+    pub git_branch: Option<String>,
+    /// Only list entries produced by this version of Nx.
+    pub nx_version: Option<String>,
+    /// Only list entries produced in this CI run.
+    pub ci_run_id: Option<String>,
+    pub origin: Option<CacheEntryOrigin>,
+    /// Defaults to `accessedAt`.
+    pub sort_by: Option<CacheEntrySortKey>,
+    /// Defaults to `true`: largest, most recent or most used first.
+    pub descending: Option<bool>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+/// A page of cache entries.
+#[napi(object)]
+#[derive(Clone, Debug)]
+pub struct CacheEntryPage {
+    pub entries: Vec<CacheEntry>,
+    /// How many entries match the query, regardless of `limit` and `offset`.
+    pub total: i64,
+    /// The combined size in bytes of the entries matching the query.
+    pub total_size: i64,
+}
+
+const FROM: &str = "FROM cache_outputs
+    LEFT JOIN task_details ON cache_outputs.hash = task_details.hash";
+
+impl CacheEntryQuery {
+    /// The `WHERE` clause selecting the matching entries, and its parameters.
+    fn filter(&self) -> (String, Vec<Value>) {
+        let mut conditions = vec![];
+        let mut params = vec![];
+        for (column, value) in [
+            ("task_details.project", &self.project),
+            ("task_details.target", &self.target),
+            ("task_details.configuration", &self.configuration),
+            ("machine_id", &self.machine_id),
+            ("git_commit", &self.git_commit),
+            ("git_branch", &self.git_branch),
+            ("nx_version", &self.nx_version),
+            ("ci_run_id", &self.ci_run_id),
+        ] {
+            if let Some(value) = value {
+                params.push(Value::from(value.clone()));
+                conditions.push(format!("{} = ?{}", column, params.len()));
+            }
+        }
+        if let Some(origin) = &self.origin {
+            params.push(Value::from(origin.as_str().to_string()));
+            conditions.push(format!("origin = ?{}", params.len()));
+        }
+        match self.failed {
+            Some(true) => conditions.push("code != 0".to_string()),
+            Some(false) => conditions.push("code = 0".to_string()),
+            None => {}
+        }
+        if let Some(min_size) = self.min_size {
+            params.push(Value::from(min_size));
+            conditions.push(format!("size >= ?{}", params.len()));
+        }
+
+        if conditions.is_empty() {
+            (String::new(), params)
+        } else {
+            (format!("WHERE {}", conditions.join(" AND ")), params)
+        }
+    }
+
+    /// The query listing the entries of this page, and its parameters.
+    pub(super) fn entries_sql(&self) -> (String, Vec<Value>) {
+        let (filter, mut params) = self.filter();
+        let sort_by = self
+            .sort_by
+            .unwrap_or(CacheEntrySortKey::accessedAt)
+            .column();
+        let direction = if self.descending.unwrap_or(true) {
+            "DESC"
+        } else {
+            "ASC"
+        };
+        params.push(Value::from(self.limit.map_or(-1, i64::from)));
+        params.push(Value::from(i64::from(self.offset.unwrap_or(0))));
+        let sql = format!(
+            "SELECT cache_outputs.hash, project, target, configuration, code, size,
+                CAST(strftime('%s', created_at) AS INTEGER) * 1000,
+                CAST(strftime('%s', accessed_at) AS INTEGER) * 1000,
+                hits, {}
+             {} {}
+             ORDER BY {} {}, cache_outputs.hash
+             LIMIT ?{} OFFSET ?{}",
+            provenance::COLUMNS,
+            FROM,
+            filter,
+            sort_by,
+            direction,
+            params.len() - 1,
+            params.len()
+        );
+        (sql, params)
+    }
+
+    /// The query counting the matching entries and their size.
+    pub(super) fn totals_sql(&self) -> (String, Vec<Value>) {
+        let (filter, params) = self.filter();
+        (
+            format!(
+                "SELECT COUNT(*), COALESCE(SUM(size), 0) {} {}",
+                FROM, filter
+            ),
+            params,
+        )
+    }
+}
@@ -2,9 +2,35 @@ pub mod expand_outputs;
 pub mod file_ops;
 pub mod validate_outputs;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod blob_store;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bundle;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod cache;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod errors;
 #[cfg(not(target_arch = "wasm32"))]
+pub mod eviction;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod fsck;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod http_remote_cache;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod inventory;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod output_limits;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod provenance;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod remote_access;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod retry;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod shared_fs_remote_cache;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod signing;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tarball;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod terminal_output;
@@ -0,0 +1,99 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Size limits guarding the local cache against outputs that were never
+/// meant to be cached, such as core dumps or an `outputs` entry pointing at
+/// `node_modules`.
+#[napi(object)]
+#[derive(Default, Clone, Debug)]
+pub struct CacheOutputLimits {
+    /// Files over this many bytes are left out of the entry.
+    pub max_file_size: Option<i64>,
+    /// Entries over this many bytes are not stored at all.
+    pub max_entry_size: Option<i64>,
+}
+
+impl CacheOutputLimits {
+    pub fn max_file_size(&self) -> Option<u64> {
+        self.max_file_size
+            .filter(|size| *size > 0)
+            .map(|size| size as u64)
+    }
+
+    pub fn max_entry_size(&self) -> Option<u64> {
+        self.max_entry_size
+            .filter(|size| *size > 0)
+            .map(|size| size as u64)
+    }
+}
+
+/// A file left out of a cache entry for exceeding `max_file_size`.
+#[napi(object)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkippedCacheFile {
+    /// Relative to the workspace root
+    pub path: String,
+    /// Size in bytes
+    pub size: i64,
+}
+
+/// What `NxCache::put` stored.
+#[napi(object)]
+#[derive(Default, Clone, Debug)]
+pub struct CachePutResult {
+    pub expanded_outputs: Vec<String>,
+    /// Files left out of the entry for exceeding `max_file_size`.
+    pub skipped_files: Vec<SkippedCacheFile>,
+    /// Set to the size in bytes of the entry when it was refused for
+    /// exceeding `max_entry_size`; nothing is stored then.
+    pub refused_size: Option<i64>,
+}
+
+/// The combined size in bytes of the files under `path` that are within
+/// `max_file_size`. Symlinks are not followed.
+pub fn measure(path: &Path, max_file_size: Option<u64>) -> io::Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        let mut size = 0;
+        for entry in fs::read_dir(path)? {
+            size += measure(&entry?.path(), max_file_size)?;
+        }
+        Ok(size)
+    } else if metadata.is_file() && max_file_size.is_none_or(|max| metadata.len() <= max) {
+        Ok(metadata.len())
+    } else {
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn should_measure_files_within_the_limit() {
+        let temp = TempDir::new().unwrap();
+        temp.child("dist/small.txt").write_str("12345").unwrap();
+        temp.child("dist/nested/large.txt")
+            .write_str(&"x".repeat(100))
+            .unwrap();
+
+        assert_eq!(measure(&temp.join("dist"), None).unwrap(), 105);
+        assert_eq!(measure(&temp.join("dist"), Some(10)).unwrap(), 5);
+        assert_eq!(measure(&temp.join("dist/small.txt"), Some(10)).unwrap(), 5);
+    }
+
+    #[test]
+    fn should_disable_limits_that_are_not_positive() {
+        let limits = CacheOutputLimits {
+            max_file_size: Some(0),
+            max_entry_size: Some(-1),
+        };
+
+        assert_eq!(limits.max_file_size(), None);
+        assert_eq!(limits.max_entry_size(), None);
+    }
+}
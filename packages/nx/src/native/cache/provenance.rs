@@ -0,0 +1,100 @@
+use rusqlite::Row;
+use serde::{Deserialize, Serialize};
+
+use crate::native::machine_id::get_machine_id;
+
+/// How an entry got into the local cache.
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CacheEntryOrigin {
+    /// The task ran on this machine.
+    #[allow(non_camel_case_types)]
+    local,
+    /// The entry was retrieved from a remote cache or imported from a bundle.
+    #[allow(non_camel_case_types)]
+    remote,
+}
+
+impl CacheEntryOrigin {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheEntryOrigin::local => "local",
+            CacheEntryOrigin::remote => "remote",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "local" => Some(CacheEntryOrigin::local),
+            "remote" => Some(CacheEntryOrigin::remote),
+            _ => None,
+        }
+    }
+}
+
+/// Where a cache entry was produced.
+#[napi(object)]
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheProvenance {
+    /// The machine the task ran on.
+    pub machine_id: Option<String>,
+    pub git_commit: Option<String>,
+    pub git_branch: Option<String>,
+    pub nx_version: Option<String>,
+    /// The CI run the task ran in, if any.
+    pub ci_run_id: Option<String>,
+    /// Not carried over to other machines, it only makes sense locally.
+    #[serde(skip)]
+    pub origin: Option<CacheEntryOrigin>,
+}
+
+/// The `cache_outputs` columns holding the provenance of an entry, in the
+/// order `from_row` reads them.
+pub const COLUMNS: &str = "machine_id, git_commit, git_branch, nx_version, ci_run_id, origin";
+
+impl CacheProvenance {
+    /// The provenance of entries produced on this machine, filling in the
+    /// machine id when the caller didn't.
+    pub fn local(provenance: Option<CacheProvenance>) -> Self {
+        let mut provenance = provenance.unwrap_or_default();
+        provenance.machine_id.get_or_insert_with(get_machine_id);
+        provenance.origin = Some(CacheEntryOrigin::local);
+        provenance
+    }
+
+    /// The provenance of an entry retrieved from elsewhere, which artifacts
+    /// written by older versions of Nx don't carry.
+    pub fn remote(provenance: Option<CacheProvenance>) -> Self {
+        CacheProvenance {
+            origin: Some(CacheEntryOrigin::remote),
+            ..provenance.unwrap_or_default()
+        }
+    }
+
+    /// Read the provenance from `row`, whose columns starting at `offset`
+    /// are `COLUMNS`.
+    pub fn from_row(row: &Row, offset: usize) -> rusqlite::Result<Self> {
+        let origin: Option<String> = row.get(offset + 5)?;
+        Ok(CacheProvenance {
+            machine_id: row.get(offset)?,
+            git_commit: row.get(offset + 1)?,
+            git_branch: row.get(offset + 2)?,
+            nx_version: row.get(offset + 3)?,
+            ci_run_id: row.get(offset + 4)?,
+            origin: origin.as_deref().and_then(CacheEntryOrigin::parse),
+        })
+    }
+
+    /// The values of `COLUMNS`.
+    pub fn values(&self) -> [Option<&str>; 6] {
+        [
+            self.machine_id.as_deref(),
+            self.git_commit.as_deref(),
+            self.git_branch.as_deref(),
+            self.nx_version.as_deref(),
+            self.ci_run_id.as_deref(),
+            self.origin.as_ref().map(CacheEntryOrigin::as_str),
+        ]
+    }
+}
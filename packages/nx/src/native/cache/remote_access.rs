@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+/// How long a hash the remote cache doesn't hold is remembered as a miss.
+const DEFAULT_NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Which way artifacts flow between this machine and the remote cache.
+#[napi(string_enum)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum RemoteCacheMode {
+    /// Artifacts are both retrieved and stored.
+    #[default]
+    #[allow(non_camel_case_types)]
+    readWrite,
+    /// Artifacts are retrieved but never stored, e.g. on developer machines.
+    #[allow(non_camel_case_types)]
+    readOnly,
+    /// Artifacts are stored but never retrieved, e.g. on CI agents
+    /// populating the cache.
+    #[allow(non_camel_case_types)]
+    writeOnly,
+}
+
+impl RemoteCacheMode {
+    /// Read `NX_SELF_HOSTED_REMOTE_CACHE_MODE`: `read-write`, `read-only` or
+    /// `write-only`.
+    pub fn from_env() -> Self {
+        match env::var("NX_SELF_HOSTED_REMOTE_CACHE_MODE") {
+            Ok(value) => Self::parse(&value).unwrap_or_else(|| {
+                warn!(
+                    "Ignoring unknown NX_SELF_HOSTED_REMOTE_CACHE_MODE {:?}, expected read-write, read-only or write-only",
+                    value
+                );
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "read-write" | "readwrite" => Some(Self::readWrite),
+            "read-only" | "readonly" | "read" => Some(Self::readOnly),
+            "write-only" | "writeonly" | "write" => Some(Self::writeOnly),
+            _ => None,
+        }
+    }
+
+    pub fn reads(&self) -> bool {
+        *self != Self::writeOnly
+    }
+
+    pub fn writes(&self) -> bool {
+        *self != Self::readOnly
+    }
+}
+
+/// Options of a remote cache, taking precedence over the environment.
+#[napi(object)]
+#[derive(Default, Clone, Debug)]
+pub struct HttpRemoteCacheOptions {
+    /// Defaults to `NX_SELF_HOSTED_REMOTE_CACHE_MODE`, or `readWrite`.
+    pub mode: Option<RemoteCacheMode>,
+    /// Seconds a hash the remote cache doesn't hold is not looked up again.
+    /// Defaults to `NX_SELF_HOSTED_REMOTE_CACHE_NEGATIVE_CACHE_TTL`, or 60;
+    /// 0 disables it.
+    pub negative_cache_ttl: Option<u32>,
+}
+
+/// Time spent looking up artifacts the remote cache turned out not to hold,
+/// to tell how much remote cache latency costs a run.
+#[napi(object)]
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct RemoteCacheMissTimings {
+    /// Lookups answered with a miss.
+    pub count: u32,
+    /// Milliseconds spent on all of them.
+    pub total_ms: f64,
+    /// Milliseconds spent on the slowest one.
+    pub max_ms: f64,
+}
+
+impl RemoteCacheMissTimings {
+    pub fn record(&mut self, elapsed: Duration) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        self.count += 1;
+        self.total_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+    }
+}
+
+/// Hashes the remote cache recently reported missing, so that the batch
+/// lookup and the single lookups of a run don't each ask for them again.
+#[derive(Debug)]
+pub struct NegativeCache {
+    ttl: Duration,
+    misses: Mutex<HashMap<String, Instant>>,
+}
+
+impl NegativeCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            misses: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let ttl = match env::var("NX_SELF_HOSTED_REMOTE_CACHE_NEGATIVE_CACHE_TTL") {
+            Ok(value) => match value.trim().parse::<u64>() {
+                Ok(seconds) => Duration::from_secs(seconds),
+                Err(_) => {
+                    warn!(
+                        "Ignoring invalid NX_SELF_HOSTED_REMOTE_CACHE_NEGATIVE_CACHE_TTL {:?}",
+                        value
+                    );
+                    DEFAULT_NEGATIVE_CACHE_TTL
+                }
+            },
+            Err(_) => DEFAULT_NEGATIVE_CACHE_TTL,
+        };
+        Self::new(ttl)
+    }
+
+    /// Whether `hash` missed less than `ttl` ago.
+    pub fn contains(&self, hash: &str) -> bool {
+        let mut misses = self.misses.lock().unwrap();
+        match misses.get(hash) {
+            Some(missed_at) if missed_at.elapsed() < self.ttl => true,
+            Some(_) => {
+                misses.remove(hash);
+                false
+            }
+            None => false,
+        }
+    }
+
+    pub fn insert(&self, hash: &str) {
+        if !self.ttl.is_zero() {
+            self.misses
+                .lock()
+                .unwrap()
+                .insert(hash.to_string(), Instant::now());
+        }
+    }
+
+    /// Forget a miss, once the hash has been stored.
+    pub fn remove(&self, hash: &str) {
+        self.misses.lock().unwrap().remove(hash);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn should_parse_modes() {
+        assert_eq!(
+            RemoteCacheMode::parse("read-only"),
+            Some(RemoteCacheMode::readOnly)
+        );
+        assert_eq!(
+            RemoteCacheMode::parse("WRITE-ONLY"),
+            Some(RemoteCacheMode::writeOnly)
+        );
+        assert_eq!(RemoteCacheMode::parse("sometimes"), None);
+        assert!(!RemoteCacheMode::readOnly.writes());
+        assert!(!RemoteCacheMode::writeOnly.reads());
+    }
+
+    #[test]
+    fn should_remember_misses_until_they_expire() {
+        let misses = NegativeCache::new(Duration::from_secs(60));
+        misses.insert("123");
+        assert!(misses.contains("123"));
+        misses.remove("123");
+        assert!(!misses.contains("123"));
+
+        let expired = NegativeCache::new(Duration::from_millis(1));
+        expired.insert("123");
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(!expired.contains("123"));
+
+        let disabled = NegativeCache::new(Duration::ZERO);
+        disabled.insert("123");
+        assert!(!disabled.contains("123"));
+    }
+
+    #[test]
+    fn should_sum_miss_timings() {
+        let mut timings = RemoteCacheMissTimings::default();
+        timings.record(Duration::from_millis(30));
+        timings.record(Duration::from_millis(10));
+
+        assert_eq!(
+            timings,
+            RemoteCacheMissTimings {
+                count: 2,
+                total_ms: 40.0,
+                max_ms: 30.0,
+            }
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::env;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use tracing::warn;
+
+/// How requests to the self-hosted remote cache are retried.
+///
+/// `NX_SELF_HOSTED_REMOTE_CACHE_MAX_RETRIES` sets how many times a request is
+/// retried after a connection error or a 5xx, 408 or 429 response.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> Self {
+        let mut policy = Self::default();
+        if let Some(max_retries) = parse_env("NX_SELF_HOSTED_REMOTE_CACHE_MAX_RETRIES") {
+            policy.max_retries = max_retries;
+        }
+        policy
+    }
+
+    /// The delay before retry number `attempt` (starting at 1). The server's
+    /// `Retry-After` wins when it sent one; otherwise the delay doubles with
+    /// every attempt and is jittered so clients don't retry in lockstep.
+    /// Either way it never exceeds `max_delay`.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        exponential.mul_f64(0.5 + rand::random::<f64>() * 0.5)
+    }
+}
+
+/// Responses worth retrying: the server may well answer the same request
+/// successfully a moment later.
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+}
+
+/// Errors worth retrying: the request never got a response.
+pub fn is_retryable_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request()
+}
+
+/// Read a `Retry-After` header given in seconds. The HTTP date form is not
+/// supported and falls back to exponential backoff.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Stops talking to a remote cache that keeps failing.
+///
+/// After `NX_SELF_HOSTED_REMOTE_CACHE_CIRCUIT_BREAKER_THRESHOLD` consecutive
+/// failed requests (5 by default, 0 disables it) the circuit opens and stays
+/// open for the rest of the process: lookups are misses and nothing is
+/// uploaded, so a broken server costs one timeout instead of one per task.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    consecutive_failures: AtomicU32,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold,
+            consecutive_failures: AtomicU32::new(0),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(parse_env("NX_SELF_HOSTED_REMOTE_CACHE_CIRCUIT_BREAKER_THRESHOLD").unwrap_or(5))
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.threshold != 0 && self.consecutive_failures.load(Ordering::Relaxed) >= self.threshold
+    }
+
+    pub fn record_success(&self) {
+        if !self.is_open() {
+            self.consecutive_failures.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Record a failed request, returning whether it opened the circuit.
+    pub fn record_failure(&self) -> bool {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        self.threshold != 0 && failures == self.threshold
+    }
+}
+
+fn parse_env(name: &str) -> Option<u32> {
+    let value = env::var(name).ok()?;
+    match value.trim().parse::<u32>() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            warn!("Ignoring invalid {} {:?}", name, value);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn delay_grows_exponentially_within_bounds() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+        };
+
+        let first = policy.delay(1, None);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+        let second = policy.delay(2, None);
+        assert!(second >= Duration::from_millis(100) && second <= Duration::from_millis(200));
+        assert!(policy.delay(10, None) <= Duration::from_millis(300));
+    }
+
+    #[test]
+    fn delay_honors_retry_after() {
+        let policy = RetryPolicy::default();
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("2"));
+
+        let retry_after = retry_after(&headers);
+        assert_eq!(retry_after, Some(Duration::from_secs(2)));
+        assert_eq!(policy.delay(1, retry_after), Duration::from_secs(2));
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(600))),
+            policy.max_delay
+        );
+    }
+
+    #[test]
+    fn circuit_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(2);
+        assert!(!breaker.record_failure());
+        breaker.record_success();
+        assert!(!breaker.record_failure());
+        assert!(!breaker.is_open());
+        assert!(breaker.record_failure());
+        assert!(breaker.is_open());
+        // Once open it stays open for the rest of the process.
+        breaker.record_success();
+        assert!(breaker.is_open());
+    }
+
+    #[test]
+    fn circuit_breaker_can_be_disabled() {
+        let breaker = CircuitBreaker::new(0);
+        for _ in 0..10 {
+            assert!(!breaker.record_failure());
+        }
+        assert!(!breaker.is_open());
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+use fs4::fs_std::FileExt;
+use napi::Status;
+use tracing::{trace, warn};
+
+use super::{
+    blob_store::IntegrityError,
+    cache::CachedResult,
+    provenance::CacheProvenance,
+    tarball::{TarballEncoding, TemporaryTarball, extract_tarball, write_tarball},
+};
+
+/// A remote cache kept in a directory shared between machines, such as an
+/// NFS or SMB mount, set with `NX_SHARED_FS_REMOTE_CACHE_DIRECTORY`.
+///
+/// Artifacts use the same tarball format as `HttpRemoteCache` and live at
+/// `<root>/<last two digits of the hash>/<hash>.tar.gz`. They are written
+/// under a temporary name and renamed into place, so readers never see a
+/// partial artifact; writers take an `fs4` lock on the shard so only the
+/// first artifact stored for a hash is kept.
+#[napi]
+pub struct SharedFsRemoteCache {
+    root: PathBuf,
+}
+
+#[napi]
+impl SharedFsRemoteCache {
+    #[napi(constructor)]
+    pub fn new() -> napi::Result<Self> {
+        let root = env::var("NX_SHARED_FS_REMOTE_CACHE_DIRECTORY").map_err(|_| {
+            napi::Error::new(
+                Status::InvalidArg,
+                "NX_SHARED_FS_REMOTE_CACHE_DIRECTORY must be set",
+            )
+        })?;
+        Ok(Self {
+            root: PathBuf::from(root),
+        })
+    }
+
+    #[napi]
+    pub async fn retrieve(
+        &self,
+        hash: String,
+        cache_directory: String,
+    ) -> napi::Result<Option<CachedResult>> {
+        let tarball = self.tarball_path(&hash);
+        let result = tokio::task::spawn_blocking(move || {
+            Self::retrieve_blocking(&tarball, &cache_directory, &hash)
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to retrieve cache artifact: {}", e))??;
+        Ok(result)
+    }
+
+    #[napi]
+    pub async fn store(
+        &self,
+        hash: String,
+        cache_directory: String,
+        terminal_output: String,
+        code: u32,
+        provenance: Option<CacheProvenance>,
+    ) -> napi::Result<bool> {
+        let tarball = self.tarball_path(&hash);
+        let stored = tokio::task::spawn_blocking(move || {
+            Self::store_blocking(
+                &tarball,
+                &cache_directory,
+                &hash,
+                &terminal_output,
+                code,
+                provenance.as_ref(),
+            )
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to store cache artifact: {}", e))??;
+        Ok(stored)
+    }
+
+    /// Shards by the last two digits of the hash, like the local blob store.
+    fn tarball_path(&self, hash: &str) -> PathBuf {
+        let shard = &hash[hash.len().saturating_sub(2)..];
+        self.root.join(shard).join(format!("{}.tar.gz", hash))
+    }
+
+    fn retrieve_blocking(
+        tarball: &Path,
+        cache_directory: &str,
+        hash: &str,
+    ) -> anyhow::Result<Option<CachedResult>> {
+        let file = match File::open(tarball) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                trace!("No artifact at {:?}", tarball);
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        match extract_tarball(
+            BufReader::new(file),
+            TarballEncoding::Gzip,
+            cache_directory,
+            hash,
+        ) {
+            Ok(result) => Ok(Some(result)),
+            Err(e) if e.is::<IntegrityError>() => {
+                warn!("{} ({}), treating it as a cache miss", e, hash);
+                fs::remove_dir_all(Path::new(cache_directory).join(hash)).ok();
+                // Writers never replace an existing artifact, so a corrupt
+                // one has to go for the next writer to store a good one.
+                let lock = Self::lock_shard(tarball)?;
+                fs::remove_file(tarball).ok();
+                FileExt::unlock(&lock)?;
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn store_blocking(
+        tarball: &Path,
+        cache_directory: &str,
+        hash: &str,
+        terminal_output: &str,
+        code: u32,
+        provenance: Option<&CacheProvenance>,
+    ) -> anyhow::Result<bool> {
+        if tarball.exists() {
+            trace!("Artifact for {} already stored", hash);
+            return Ok(false);
+        }
+        let shard = tarball
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Invalid artifact path {:?}", tarball))?;
+        fs::create_dir_all(shard)?;
+
+        // Compressing can take a while, so it happens before taking the lock.
+        let temporary = TemporaryTarball::new(shard, hash, TarballEncoding::Gzip);
+        write_tarball(
+            &temporary.path,
+            TarballEncoding::Gzip,
+            TarballEncoding::Gzip.default_level(),
+            &Path::new(cache_directory).join(hash),
+            terminal_output,
+            code,
+            provenance,
+        )?;
+
+        let lock = Self::lock_shard(tarball)?;
+        let stored = if tarball.exists() {
+            trace!("Artifact for {} was stored concurrently", hash);
+            false
+        } else {
+            fs::rename(&temporary.path, tarball)?;
+            true
+        };
+        FileExt::unlock(&lock)?;
+        Ok(stored)
+    }
+
+    /// Take an exclusive lock on the shard `tarball` belongs to.
+    fn lock_shard(tarball: &Path) -> anyhow::Result<File> {
+        let shard = tarball
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Invalid artifact path {:?}", tarball))?;
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(shard.join(".lock"))?;
+        FileExt::lock_exclusive(&lock)?;
+        Ok(lock)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    fn shared_cache(root: &TempDir) -> SharedFsRemoteCache {
+        SharedFsRemoteCache {
+            root: root.path().to_path_buf(),
+        }
+    }
+
+    #[tokio::test]
+    async fn should_round_trip_artifacts() {
+        let shared = TempDir::new().unwrap();
+        let writer = TempDir::new().unwrap();
+        let reader = TempDir::new().unwrap();
+        writer
+            .child("123/dist/main.js")
+            .write_str("console.log(1)")
+            .unwrap();
+        let cache = shared_cache(&shared);
+
+        let stored = cache
+            .store(
+                "123".into(),
+                writer.to_str().unwrap().into(),
+                "build complete".into(),
+                0,
+                None,
+            )
+            .await
+            .unwrap();
+        let result = cache
+            .retrieve("123".into(), reader.to_str().unwrap().into())
+            .await
+            .unwrap()
+            .expect("the artifact should be found");
+
+        assert!(stored);
+        assert!(shared.child("23/123.tar.gz").exists());
+        assert_eq!(result.code, 0);
+        assert_eq!(result.terminal_output.as_deref(), Some("build complete"));
+        assert_eq!(
+            std::fs::read_to_string(reader.join("123/dist/main.js")).unwrap(),
+            "console.log(1)"
+        );
+    }
+
+    #[tokio::test]
+    async fn should_keep_the_first_artifact_stored() {
+        let shared = TempDir::new().unwrap();
+        let writer = TempDir::new().unwrap();
+        let cache = shared_cache(&shared);
+
+        for (output, expected) in [("first", true), ("second", false)] {
+            writer.child("123/main.js").write_str(output).unwrap();
+            let stored = cache
+                .store(
+                    "123".into(),
+                    writer.to_str().unwrap().into(),
+                    "".into(),
+                    0,
+                    None,
+                )
+                .await
+                .unwrap();
+            assert_eq!(stored, expected);
+        }
+
+        let reader = TempDir::new().unwrap();
+        cache
+            .retrieve("123".into(), reader.to_str().unwrap().into())
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(reader.join("123/main.js")).unwrap(),
+            "first"
+        );
+        // Only the artifact and the shard lock are left behind.
+        assert_eq!(std::fs::read_dir(shared.join("23")).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn should_miss_unknown_hashes() {
+        let shared = TempDir::new().unwrap();
+        let reader = TempDir::new().unwrap();
+
+        let result = shared_cache(&shared)
+            .retrieve("123".into(), reader.to_str().unwrap().into())
+            .await
+            .unwrap();
+
+        assert!(result.is_none());
+    }
+}
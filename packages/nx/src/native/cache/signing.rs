@@ -0,0 +1,186 @@
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use ring::digest::{Context, Digest, SHA256};
+use ring::signature::{ED25519, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+
+use super::errors::HttpRemoteCacheErrors;
+
+/// Header carrying an artifact's signature. Servers store it with the
+/// artifact on `PUT` and send it back on `GET`.
+pub const SIGNATURE_HEADER: &str = "x-nx-cache-signature";
+
+/// Ed25519 signing and verification of remote cache artifacts.
+///
+/// Trusted writers (usually CI) hold the private key in
+/// `NX_SELF_HOSTED_REMOTE_CACHE_SIGNING_KEY`; readers only need the public key
+/// in `NX_SELF_HOSTED_REMOTE_CACHE_VERIFICATION_KEY`. Both are hex encoded
+/// 32 byte keys. A signature covers the task hash and the SHA-256 of the
+/// tarball exactly as it was uploaded, so an artifact can neither be altered
+/// nor replayed under another hash.
+#[derive(Default)]
+pub struct ArtifactSigner {
+    key_pair: Option<Ed25519KeyPair>,
+    verification_key: Option<Vec<u8>>,
+}
+
+impl ArtifactSigner {
+    pub fn from_env() -> Result<Self, HttpRemoteCacheErrors> {
+        let key_pair = match env::var("NX_SELF_HOSTED_REMOTE_CACHE_SIGNING_KEY") {
+            Ok(key) => Some(
+                decode_key("NX_SELF_HOSTED_REMOTE_CACHE_SIGNING_KEY", &key)
+                    .and_then(|seed| {
+                        Ed25519KeyPair::from_seed_unchecked(&seed).map_err(|e| e.to_string())
+                    })
+                    .map_err(HttpRemoteCacheErrors::Misconfigured)?,
+            ),
+            Err(_) => None,
+        };
+        let verification_key = match env::var("NX_SELF_HOSTED_REMOTE_CACHE_VERIFICATION_KEY") {
+            Ok(key) => Some(
+                decode_key("NX_SELF_HOSTED_REMOTE_CACHE_VERIFICATION_KEY", &key)
+                    .map_err(HttpRemoteCacheErrors::Misconfigured)?,
+            ),
+            // Writers verify what they read with their own public key.
+            Err(_) => key_pair
+                .as_ref()
+                .map(|key_pair| key_pair.public_key().as_ref().to_vec()),
+        };
+        Ok(Self {
+            key_pair,
+            verification_key,
+        })
+    }
+
+    /// Whether artifacts are signed on upload.
+    pub fn signs(&self) -> bool {
+        self.key_pair.is_some()
+    }
+
+    /// Whether downloaded artifacts must carry a valid signature.
+    pub fn verifies(&self) -> bool {
+        self.verification_key.is_some()
+    }
+
+    /// Sign the artifact for `hash` whose tarball has the SHA-256 `digest`,
+    /// returning the hex encoded signature.
+    pub fn sign(&self, hash: &str, digest: &Digest) -> Option<String> {
+        self.key_pair
+            .as_ref()
+            .map(|key_pair| hex::encode(key_pair.sign(&signed_message(hash, digest))))
+    }
+
+    /// Check the signature a server returned for the artifact of `hash`.
+    pub fn verify(
+        &self,
+        hash: &str,
+        digest: &Digest,
+        signature: Option<&str>,
+    ) -> Result<(), HttpRemoteCacheErrors> {
+        let Some(verification_key) = &self.verification_key else {
+            return Ok(());
+        };
+        let signature = signature.ok_or_else(|| {
+            HttpRemoteCacheErrors::InvalidSignature(format!("{} is not signed", hash))
+        })?;
+        let signature = hex::decode(signature.trim()).map_err(|_| {
+            HttpRemoteCacheErrors::InvalidSignature(format!("{} has a malformed signature", hash))
+        })?;
+        UnparsedPublicKey::new(&ED25519, verification_key)
+            .verify(&signed_message(hash, digest), &signature)
+            .map_err(|_| {
+                HttpRemoteCacheErrors::InvalidSignature(format!(
+                    "{} has an invalid signature",
+                    hash
+                ))
+            })
+    }
+}
+
+/// Compute the SHA-256 of the file at `path`.
+pub fn digest_file(path: &Path) -> std::io::Result<Digest> {
+    let mut file = File::open(path)?;
+    let mut context = Context::new(&SHA256);
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => break,
+            read => context.update(&buffer[..read]),
+        }
+    }
+    Ok(context.finish())
+}
+
+fn signed_message(hash: &str, digest: &Digest) -> Vec<u8> {
+    format!("nx-remote-cache-v1:{}:{}", hash, hex::encode(digest)).into_bytes()
+}
+
+fn decode_key(name: &str, key: &str) -> Result<Vec<u8>, String> {
+    match hex::decode(key.trim()) {
+        Ok(bytes) if bytes.len() == 32 => Ok(bytes),
+        _ => Err(format!(
+            "{} must be a hex encoded 32 byte Ed25519 key",
+            name
+        )),
+    }
+}
+
+#[cfg(test)]
+impl ArtifactSigner {
+    /// A signer that signs and verifies with the key of `seed`.
+    pub(crate) fn with_seed(seed: [u8; 32]) -> Self {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&seed).unwrap();
+        let verification_key = key_pair.public_key().as_ref().to_vec();
+        ArtifactSigner {
+            key_pair: Some(key_pair),
+            verification_key: Some(verification_key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ring::digest::digest;
+
+    fn signer() -> ArtifactSigner {
+        ArtifactSigner::with_seed([7; 32])
+    }
+
+    #[test]
+    fn should_accept_artifacts_it_signed() {
+        let signer = signer();
+        let tarball = digest(&SHA256, b"tarball");
+        let signature = signer.sign("123", &tarball).unwrap();
+
+        assert!(signer.verify("123", &tarball, Some(&signature)).is_ok());
+    }
+
+    #[test]
+    fn should_reject_unsigned_or_tampered_artifacts() {
+        let signer = signer();
+        let tarball = digest(&SHA256, b"tarball");
+        let signature = signer.sign("123", &tarball).unwrap();
+
+        assert!(signer.verify("123", &tarball, None).is_err());
+        assert!(signer.verify("123", &tarball, Some("not hex")).is_err());
+        // The signature is bound to the content and to the task hash.
+        let tampered = digest(&SHA256, b"tampered");
+        assert!(signer.verify("123", &tampered, Some(&signature)).is_err());
+        assert!(signer.verify("456", &tarball, Some(&signature)).is_err());
+    }
+
+    #[test]
+    fn should_not_require_signatures_without_a_key() {
+        let signer = ArtifactSigner {
+            key_pair: None,
+            verification_key: None,
+        };
+        let tarball = digest(&SHA256, b"tarball");
+
+        assert!(signer.sign("123", &tarball).is_none());
+        assert!(signer.verify("123", &tarball, None).is_ok());
+    }
+}
@@ -0,0 +1,773 @@
+//! The tarball format shared by the remote cache backends.
+//!
+//! An artifact is a tar archive of a cache entry directory (`<cache>/<hash>`)
+//! plus three extra members: `terminalOutput`, `manifest` and `code`, and a
+//! fourth, `provenance`, when the writer knows where the entry came from.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::Compression;
+use reqwest::header::HeaderValue;
+use tar::{Archive, Builder};
+use tracing::{trace, warn};
+
+use super::blob_store::{IntegrityError, Manifest, ManifestEntry};
+use super::cache::CachedResult;
+use super::provenance::CacheProvenance;
+
+/// How a cache tarball is compressed on the wire, as named by the
+/// `Content-Encoding` header. Servers that predate negotiation neither send
+/// nor expect the header, which means gzip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TarballEncoding {
+    Gzip,
+    Zstd,
+}
+
+impl TarballEncoding {
+    pub fn from_content_encoding(value: Option<&HeaderValue>) -> anyhow::Result<Self> {
+        let Some(value) = value else {
+            return Ok(TarballEncoding::Gzip);
+        };
+        match value
+            .to_str()
+            .map(|v| v.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            Ok("gzip") | Ok("x-gzip") => Ok(TarballEncoding::Gzip),
+            Ok("zstd") => Ok(TarballEncoding::Zstd),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported remote cache content encoding: {:?}",
+                value
+            )),
+        }
+    }
+
+    /// The level used when none is configured.
+    pub fn default_level(&self) -> i32 {
+        match self {
+            TarballEncoding::Gzip => Compression::default().level() as i32,
+            TarballEncoding::Zstd => zstd::DEFAULT_COMPRESSION_LEVEL,
+        }
+    }
+
+    pub fn content_encoding(&self) -> &'static str {
+        match self {
+            TarballEncoding::Gzip => "gzip",
+            TarballEncoding::Zstd => "zstd",
+        }
+    }
+
+    /// The file extension of a tarball in this encoding.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TarballEncoding::Gzip => "tar.gz",
+            TarballEncoding::Zstd => "tar.zst",
+        }
+    }
+}
+
+/// Write the cache tarball for `outputs_path` to `tarball_path`,
+/// compressed with `encoding`, returning its size in bytes.
+pub fn write_tarball(
+    tarball_path: &Path,
+    encoding: TarballEncoding,
+    level: i32,
+    outputs_path: &Path,
+    terminal_output: &str,
+    code: u32,
+    provenance: Option<&CacheProvenance>,
+) -> anyhow::Result<u64> {
+    let file = BufWriter::new(File::create(tarball_path)?);
+    let mut file = match encoding {
+        TarballEncoding::Gzip => {
+            let enc = flate2::write::GzEncoder::new(file, Compression::new(level as u32));
+            append_to_tarball(enc, outputs_path, terminal_output, code, provenance)?.finish()?
+        }
+        TarballEncoding::Zstd => {
+            let enc = zstd::Encoder::new(file, level)?;
+            append_to_tarball(enc, outputs_path, terminal_output, code, provenance)?.finish()?
+        }
+    };
+    file.flush()?;
+    trace!("Finished tarball");
+
+    Ok(fs::metadata(tarball_path)?.len())
+}
+
+/// Write the cache artifacts, terminal output and exit code as a tar
+/// archive into `writer`, returning the writer once the archive is done.
+fn append_to_tarball<W: Write>(
+    writer: W,
+    outputs_path: &Path,
+    terminal_output: &str,
+    code: u32,
+    provenance: Option<&CacheProvenance>,
+) -> anyhow::Result<W> {
+    let mut archive = Builder::new(writer);
+    archive.follow_symlinks(false);
+    trace!("Created tar file for writing");
+
+    trace!("Adding cache artifacts to tarball");
+    archive.append_dir_all("", outputs_path)?;
+    trace!("Added cache directory to tarball");
+
+    trace!("Adding terminal output to tarball");
+    let mut terminal_output_header = tar::Header::new_old();
+    let terminal_output_bytes = terminal_output.as_bytes();
+    terminal_output_header.set_size(terminal_output_bytes.len() as u64);
+    terminal_output_header.set_cksum(); // Ensure the checksum is set correctly
+    archive.append_data(
+        &mut terminal_output_header,
+        "terminalOutput",
+        terminal_output_bytes,
+    )?;
+    trace!("Added terminal output to tarball");
+
+    trace!("Adding manifest to tarball");
+    let manifest = serde_json::to_vec(&Manifest::scan(outputs_path)?)?;
+    let mut manifest_header = tar::Header::new_old();
+    manifest_header.set_size(manifest.len() as u64);
+    manifest_header.set_cksum(); // Ensure the checksum is set correctly
+    archive.append_data(&mut manifest_header, "manifest", manifest.as_slice())?;
+    trace!("Added manifest to tarball");
+
+    trace!("Adding code to tarball");
+    let mut code_header = tar::Header::new_old();
+    code_header.set_size(4);
+    code_header.set_cksum(); // Ensure the checksum is set correctly
+    archive.append_data(&mut code_header, "code", &code.to_be_bytes()[..])?;
+    trace!("Added code to tarball");
+
+    if let Some(provenance) = provenance {
+        trace!("Adding provenance to tarball");
+        let provenance = serde_json::to_vec(provenance)?;
+        let mut provenance_header = tar::Header::new_old();
+        provenance_header.set_size(provenance.len() as u64);
+        provenance_header.set_cksum(); // Ensure the checksum is set correctly
+        archive.append_data(&mut provenance_header, "provenance", provenance.as_slice())?;
+        trace!("Added provenance to tarball");
+    }
+
+    trace!("Finishing tarball");
+    archive
+        .finish()
+        .map_err(|e| anyhow::anyhow!(format!("Failed to finish tarball: {}", e)))?;
+    Ok(archive.into_inner()?)
+}
+
+/// Check an extracted artifact against the manifest it shipped with: every
+/// listed entry must be intact, and no file may be missing from the list.
+fn verify_extracted(
+    manifest: &Manifest,
+    output_dir: &Path,
+    extracted_files: &[String],
+) -> Result<(), IntegrityError> {
+    let listed = manifest
+        .entries
+        .iter()
+        .filter(|entry| matches!(entry, ManifestEntry::File { .. }))
+        .map(|entry| entry.path())
+        .collect::<std::collections::HashSet<_>>();
+    if let Some(unlisted) = extracted_files
+        .iter()
+        .find(|path| !listed.contains(path.trim_start_matches("./")))
+    {
+        return Err(IntegrityError(format!(
+            "{} is not listed in the manifest",
+            unlisted
+        )));
+    }
+    manifest.verify(output_dir)
+}
+
+/// Extract a cache tarball compressed with `encoding` into
+/// `<cache_directory>/<hash>`.
+pub fn extract_tarball(
+    content: impl Read,
+    encoding: TarballEncoding,
+    cache_directory: &str,
+    hash: &str,
+) -> anyhow::Result<CachedResult> {
+    extract(content, encoding, cache_directory, hash, false)
+}
+
+/// Like `extract_tarball`, but rejects tarballs without a manifest, for
+/// sources that always write one.
+pub fn extract_verified_tarball(
+    content: impl Read,
+    encoding: TarballEncoding,
+    cache_directory: &str,
+    hash: &str,
+) -> anyhow::Result<CachedResult> {
+    extract(content, encoding, cache_directory, hash, true)
+}
+
+fn extract(
+    content: impl Read,
+    encoding: TarballEncoding,
+    cache_directory: &str,
+    hash: &str,
+    require_manifest: bool,
+) -> anyhow::Result<CachedResult> {
+    match encoding {
+        TarballEncoding::Gzip => unpack_tarball(
+            Archive::new(flate2::read::GzDecoder::new(content)),
+            cache_directory,
+            hash,
+            require_manifest,
+        ),
+        TarballEncoding::Zstd => unpack_tarball(
+            Archive::new(zstd::Decoder::new(content)?),
+            cache_directory,
+            hash,
+            require_manifest,
+        ),
+    }
+}
+
+/// Uses `tar`'s `unpack_in` so a malicious cache server can't escape
+/// `output_dir` via `..`, absolute paths, or symlinks.
+fn unpack_tarball<R: Read>(
+    mut archive: Archive<R>,
+    cache_directory: &str,
+    hash: &str,
+    require_manifest: bool,
+) -> anyhow::Result<CachedResult> {
+    let entries = archive
+        .entries() // Get the entries in the archive
+        .map_err(|_| anyhow::anyhow!("Failed to read entries from tarball"))?;
+
+    let mut code: Option<i16> = None;
+    let mut terminal_output: Option<String> = None;
+    let mut manifest: Option<Manifest> = None;
+    let mut provenance: Option<CacheProvenance> = None;
+    let mut extracted_files: Vec<String> = vec![];
+    let mut size: i64 = 0;
+
+    let output_dir = Path::new(cache_directory).join(hash);
+    // `unpack_in` canonicalizes `output_dir`, so it must exist beforehand.
+    fs::create_dir_all(&output_dir)?;
+
+    // Extract the archive to the specified cache directory
+    for entry in entries {
+        let mut entry = entry.map_err(|_| anyhow::anyhow!("Failed to read entry from tarball"))?;
+
+        let entry_path = entry
+            .path()
+            .map_err(|e| anyhow::anyhow!("Invalid entry path in cache artifact: {}", e))?
+            .to_string_lossy()
+            .into_owned();
+
+        if entry_path == "code" {
+            let code_file_bytes = entry.bytes().collect::<Result<Vec<u8>, _>>()?;
+            // The exit code is stored as a 4-byte big-endian integer (see `write_tarball`).
+            let code_bytes: [u8; 4] = code_file_bytes
+                .as_slice()
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid exit code in cache artifact"))?;
+            code = Some(u32::from_be_bytes(code_bytes) as i16);
+            trace!("Retrieved exit code from cache: {}", code.unwrap());
+        } else if entry_path == "terminalOutput" {
+            let terminal_output_bytes = entry.bytes().collect::<Result<Vec<u8>, _>>()?;
+            let terminal_output_size = terminal_output_bytes.len();
+
+            terminal_output = Some(String::from_utf8(terminal_output_bytes)?);
+            size += terminal_output_size as i64;
+
+            trace!(
+                "Retrieved terminal output from cache: {} bytes",
+                terminal_output_size
+            );
+        } else if entry_path == "manifest" {
+            let manifest_bytes = entry.bytes().collect::<Result<Vec<u8>, _>>()?;
+            manifest = Some(
+                serde_json::from_slice(&manifest_bytes)
+                    .map_err(|e| IntegrityError(format!("invalid manifest: {}", e)))?,
+            );
+        } else if entry_path == "provenance" {
+            let provenance_bytes = entry.bytes().collect::<Result<Vec<u8>, _>>()?;
+            // Provenance is informational, so an unreadable one doesn't
+            // make the artifact unusable.
+            provenance = serde_json::from_slice(&provenance_bytes)
+                .inspect_err(|e| warn!("Ignoring invalid provenance in cache artifact: {}", e))
+                .ok();
+        } else {
+            trace!(
+                "Extracting entry {} into {}",
+                entry_path,
+                output_dir.display()
+            );
+            let is_file = entry.header().entry_type().is_file();
+            let entry_size = entry.size();
+            // Reject entries `unpack_in` skips (`..`) or refuses (symlink escape).
+            let unpacked = entry
+                .unpack_in(&output_dir)
+                .map_err(|e| anyhow::anyhow!("Failed to unpack entry: {}", e))?;
+            if !unpacked {
+                return Err(anyhow::anyhow!(
+                    "Refusing to extract cache entry with unsafe path: {}",
+                    entry_path
+                ));
+            }
+            if is_file {
+                size += entry_size as i64;
+                extracted_files.push(entry_path);
+            }
+        }
+    }
+
+    trace!("Extracted tarball to {}", output_dir.display());
+
+    // Artifacts uploaded by older versions of Nx have no manifest.
+    match manifest {
+        Some(manifest) => verify_extracted(&manifest, &output_dir, &extracted_files)?,
+        None if require_manifest => {
+            return Err(IntegrityError("the artifact has no manifest".to_string()).into());
+        }
+        None => trace!("Cache artifact has no manifest, skipping verification"),
+    }
+
+    let code = code.ok_or_else(|| anyhow::anyhow!("Exit code not found in cache artifact"))?;
+    Ok(CachedResult {
+        terminal_output,
+        code,
+        outputs_path: output_dir.to_string_lossy().into_owned(),
+        size: Some(size),
+        provenance,
+    })
+}
+
+/// A tarball written for upload, removed once the upload is done.
+pub struct TemporaryTarball {
+    pub path: PathBuf,
+}
+
+impl TemporaryTarball {
+    pub fn new(cache_path: &Path, hash: &str, encoding: TarballEncoding) -> Self {
+        Self {
+            path: cache_path.join(format!(
+                "{}.{}.{}",
+                hash,
+                uuid::Uuid::new_v4(),
+                encoding.extension()
+            )),
+        }
+    }
+}
+
+impl Drop for TemporaryTarball {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_fs::TempDir;
+    use flate2::write::GzEncoder;
+    use tar::{EntryType, Header};
+
+    /// Forge a tar entry, writing `name` straight into the header to bypass the
+    /// `tar` Builder's `..`/absolute-path validation, like a hostile server.
+    fn raw_entry(name: &str, ty: EntryType, link: Option<&str>, data: &[u8]) -> (Header, Vec<u8>) {
+        let mut header = Header::new_ustar();
+        header.set_size(data.len() as u64);
+        header.set_entry_type(ty);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        if let Some(target) = link {
+            header.set_link_name(target).unwrap();
+        }
+        let name_bytes = name.as_bytes();
+        header.as_mut_bytes()[..name_bytes.len()].copy_from_slice(name_bytes);
+        header.set_cksum();
+        (header, data.to_vec())
+    }
+
+    fn code_entry(code: u32) -> (Header, Vec<u8>) {
+        raw_entry("code", EntryType::Regular, None, &code.to_be_bytes())
+    }
+
+    fn terminal_output_entry(output: &str) -> (Header, Vec<u8>) {
+        raw_entry(
+            "terminalOutput",
+            EntryType::Regular,
+            None,
+            output.as_bytes(),
+        )
+    }
+
+    fn build_tar_gz(entries: Vec<(Header, Vec<u8>)>) -> Vec<u8> {
+        let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (header, data) in &entries {
+            builder.append(header, data.as_slice()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn manifest_entry(files: &[(&str, &[u8])]) -> (Header, Vec<u8>) {
+        let manifest = Manifest {
+            entries: files
+                .iter()
+                .map(|(path, content)| ManifestEntry::File {
+                    path: path.to_string(),
+                    hash: crate::native::hasher::hash(content),
+                    size: content.len() as u64,
+                    mode: None,
+                })
+                .collect(),
+        };
+        raw_entry(
+            "manifest",
+            EntryType::Regular,
+            None,
+            &serde_json::to_vec(&manifest).unwrap(),
+        )
+    }
+
+    #[test]
+    fn extract_rejects_parent_dir_traversal() {
+        let temp = TempDir::new().unwrap();
+        let cache_dir = temp.join("cache");
+        // `../../escape.txt` from <cache>/123 resolves to <temp>/escape.txt.
+        let tar = build_tar_gz(vec![raw_entry(
+            "../../escape.txt",
+            EntryType::Regular,
+            None,
+            b"PWNED",
+        )]);
+
+        let result = extract_tarball(
+            tar.as_slice(),
+            TarballEncoding::Gzip,
+            cache_dir.to_str().unwrap(),
+            "123",
+        );
+
+        assert!(result.is_err(), "a `..` entry must be rejected");
+        assert!(
+            !temp.join("escape.txt").exists(),
+            "extraction must not write outside the cache directory"
+        );
+    }
+
+    #[test]
+    fn extract_contains_absolute_paths() {
+        let temp = TempDir::new().unwrap();
+        let cache_dir = temp.join("cache");
+        let abs_target = temp.join("outside").join("pwned.txt");
+        let tar = build_tar_gz(vec![
+            raw_entry(
+                abs_target.to_str().unwrap(),
+                EntryType::Regular,
+                None,
+                b"PWNED",
+            ),
+            code_entry(0),
+            terminal_output_entry("done"),
+        ]);
+
+        let result = extract_tarball(
+            tar.as_slice(),
+            TarballEncoding::Gzip,
+            cache_dir.to_str().unwrap(),
+            "123",
+        );
+
+        // Absolute paths are stripped to relative, so extraction is contained, not rejected.
+        assert!(
+            result.is_ok(),
+            "absolute paths should be contained, not error: {:?}",
+            result.err()
+        );
+        assert!(
+            !abs_target.exists(),
+            "an absolute entry must not escape the cache directory"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_rejects_symlink_escape() {
+        let temp = TempDir::new().unwrap();
+        let cache_dir = temp.join("cache");
+        let outside = temp.join("outside");
+        std::fs::create_dir_all(&outside).unwrap();
+
+        // `evil` -> <temp>/outside, then `evil/pwned.txt` would escape if followed.
+        let tar = build_tar_gz(vec![
+            raw_entry(
+                "evil",
+                EntryType::Symlink,
+                Some(outside.to_str().unwrap()),
+                b"",
+            ),
+            raw_entry("evil/pwned.txt", EntryType::Regular, None, b"PWNED"),
+        ]);
+
+        let result = extract_tarball(
+            tar.as_slice(),
+            TarballEncoding::Gzip,
+            cache_dir.to_str().unwrap(),
+            "123",
+        );
+
+        assert!(
+            result.is_err(),
+            "writing through a symlink must be rejected"
+        );
+        assert!(
+            !outside.join("pwned.txt").exists(),
+            "extraction must not write through a symlink out of the cache directory"
+        );
+    }
+
+    #[test]
+    fn extract_unpacks_legitimate_tarball() {
+        let temp = TempDir::new().unwrap();
+        let cache_dir = temp.join("cache");
+        let tar = build_tar_gz(vec![
+            raw_entry("dist/main.js", EntryType::Regular, None, b"console.log(1)"),
+            code_entry(0),
+            terminal_output_entry("build complete"),
+        ]);
+
+        let result = extract_tarball(
+            tar.as_slice(),
+            TarballEncoding::Gzip,
+            cache_dir.to_str().unwrap(),
+            "123",
+        )
+        .expect("legitimate tarball should extract");
+
+        assert_eq!(result.code, 0);
+        assert_eq!(result.terminal_output.as_deref(), Some("build complete"));
+        let extracted = cache_dir.join("123").join("dist").join("main.js");
+        assert!(extracted.exists(), "expected extracted output file");
+        assert_eq!(
+            std::fs::read_to_string(&extracted).unwrap(),
+            "console.log(1)"
+        );
+        assert_eq!(
+            result.size,
+            Some(("build complete".len() + "console.log(1)".len()) as i64)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_rejects_hardlink_escape() {
+        let temp = TempDir::new().unwrap();
+        let cache_dir = temp.join("cache");
+        // A file outside the cache dir the hardlink entry tries to reach.
+        let outside = temp.join("outside.txt");
+        std::fs::write(&outside, b"secret").unwrap();
+
+        // A hard link entry whose target points outside output_dir.
+        let tar = build_tar_gz(vec![raw_entry(
+            "evil",
+            EntryType::Link,
+            Some(outside.to_str().unwrap()),
+            b"",
+        )]);
+
+        let result = extract_tarball(
+            tar.as_slice(),
+            TarballEncoding::Gzip,
+            cache_dir.to_str().unwrap(),
+            "123",
+        );
+
+        assert!(
+            result.is_err(),
+            "a hardlink escaping the cache dir must be rejected"
+        );
+    }
+
+    #[test]
+    fn extract_rejects_short_code_entry() {
+        let temp = TempDir::new().unwrap();
+        let cache_dir = temp.join("cache");
+        let tar = build_tar_gz(vec![raw_entry("code", EntryType::Regular, None, b"\0")]);
+
+        let result = extract_tarball(
+            tar.as_slice(),
+            TarballEncoding::Gzip,
+            cache_dir.to_str().unwrap(),
+            "123",
+        );
+
+        assert!(
+            result.is_err(),
+            "a short code entry must be rejected, not panic"
+        );
+    }
+
+    #[test]
+    fn extract_rejects_missing_code_entry() {
+        let temp = TempDir::new().unwrap();
+        let cache_dir = temp.join("cache");
+        let tar = build_tar_gz(vec![terminal_output_entry("done")]);
+
+        let result = extract_tarball(
+            tar.as_slice(),
+            TarballEncoding::Gzip,
+            cache_dir.to_str().unwrap(),
+            "123",
+        );
+
+        assert!(
+            result.is_err(),
+            "a missing code entry must be rejected, not panic"
+        );
+    }
+
+    #[test]
+    fn written_tarball_round_trips() {
+        let temp = TempDir::new().unwrap();
+        let outputs = temp.join("outputs");
+        std::fs::create_dir_all(outputs.join("dist")).unwrap();
+        std::fs::write(outputs.join("dist").join("main.js"), "console.log(1)").unwrap();
+        let provenance = CacheProvenance {
+            machine_id: Some("agent-1".to_string()),
+            git_commit: Some("abc123".to_string()),
+            ci_run_id: Some("42".to_string()),
+            ..Default::default()
+        };
+
+        for encoding in [TarballEncoding::Gzip, TarballEncoding::Zstd] {
+            let tarball = temp.join(format!("artifact.{}", encoding.extension()));
+            let size = write_tarball(
+                &tarball,
+                encoding,
+                encoding.default_level(),
+                &outputs,
+                "build complete",
+                1,
+                Some(&provenance),
+            )
+            .unwrap();
+            assert_eq!(size, std::fs::metadata(&tarball).unwrap().len());
+
+            let cache_dir = temp.join(format!("cache-{}", encoding.content_encoding()));
+            let result = extract_tarball(
+                File::open(&tarball).unwrap(),
+                encoding,
+                cache_dir.to_str().unwrap(),
+                "123",
+            )
+            .unwrap();
+
+            assert_eq!(result.code, 1);
+            assert_eq!(result.terminal_output.as_deref(), Some("build complete"));
+            assert_eq!(result.provenance.as_ref(), Some(&provenance));
+            assert!(!cache_dir.join("123").join("provenance").exists());
+            assert_eq!(
+                std::fs::read_to_string(cache_dir.join("123").join("dist").join("main.js"))
+                    .unwrap(),
+                "console.log(1)"
+            );
+        }
+    }
+
+    #[test]
+    fn temporary_tarballs_are_named_by_encoding() {
+        let temp = TempDir::new().unwrap();
+        let gzip = TemporaryTarball::new(temp.path(), "123", TarballEncoding::Gzip);
+        let zstd = TemporaryTarball::new(temp.path(), "123", TarballEncoding::Zstd);
+
+        assert!(gzip.path.to_str().unwrap().ends_with(".tar.gz"));
+        assert!(zstd.path.to_str().unwrap().ends_with(".tar.zst"));
+        assert!(zstd.path.starts_with(temp.path()));
+    }
+
+    #[test]
+    fn content_encoding_defaults_to_gzip() {
+        assert_eq!(
+            TarballEncoding::from_content_encoding(None).unwrap(),
+            TarballEncoding::Gzip
+        );
+        assert_eq!(
+            TarballEncoding::from_content_encoding(Some(&HeaderValue::from_static("zstd")))
+                .unwrap(),
+            TarballEncoding::Zstd
+        );
+        assert!(
+            TarballEncoding::from_content_encoding(Some(&HeaderValue::from_static("br"))).is_err()
+        );
+    }
+
+    #[test]
+    fn extract_reads_full_exit_code() {
+        let temp = TempDir::new().unwrap();
+        let cache_dir = temp.join("cache");
+        // The code is stored as a 4-byte big-endian int; the reader must consume
+        // all 4 bytes rather than truncating a nonzero code to 0.
+        let tar = build_tar_gz(vec![code_entry(1)]);
+
+        let result = extract_tarball(
+            tar.as_slice(),
+            TarballEncoding::Gzip,
+            cache_dir.to_str().unwrap(),
+            "123",
+        )
+        .expect("a valid code entry should extract");
+
+        assert_eq!(
+            result.code, 1,
+            "exit code must round-trip, not truncate to 0"
+        );
+    }
+
+    #[test]
+    fn extract_verifies_files_against_manifest() {
+        let temp = TempDir::new().unwrap();
+        let cache_dir = temp.join("cache");
+        let tar = build_tar_gz(vec![
+            raw_entry("main.js", EntryType::Regular, None, b"console.log(1)"),
+            manifest_entry(&[("main.js", b"console.log(1)")]),
+            code_entry(0),
+        ]);
+
+        let result = extract_tarball(
+            tar.as_slice(),
+            TarballEncoding::Gzip,
+            cache_dir.to_str().unwrap(),
+            "123",
+        );
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn extract_rejects_files_that_do_not_match_manifest() {
+        let temp = TempDir::new().unwrap();
+        let cache_dir = temp.join("cache");
+        let tampered = build_tar_gz(vec![
+            raw_entry("main.js", EntryType::Regular, None, b"console.log(2)"),
+            manifest_entry(&[("main.js", b"console.log(1)")]),
+            code_entry(0),
+        ]);
+        let unlisted = build_tar_gz(vec![
+            raw_entry("main.js", EntryType::Regular, None, b"console.log(1)"),
+            raw_entry("extra.js", EntryType::Regular, None, b"extra"),
+            manifest_entry(&[("main.js", b"console.log(1)")]),
+            code_entry(0),
+        ]);
+
+        for (hash, tar) in [("1", tampered), ("2", unlisted)] {
+            let err = extract_tarball(
+                tar.as_slice(),
+                TarballEncoding::Gzip,
+                cache_dir.to_str().unwrap(),
+                hash,
+            )
+            .unwrap_err();
+            assert!(err.is::<IntegrityError>(), "unexpected error: {}", err);
+        }
+    }
+}
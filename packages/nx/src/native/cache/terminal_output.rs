@@ -0,0 +1,154 @@
+//! Terminal outputs of cache entries.
+//!
+//! Outputs are stored zstd-compressed at `terminalOutputs/<hash>.zst`.
+//! `terminalOutputs/<hash>` is where a task writes its output while it runs
+//! (see `NxCache::get_task_outputs_path`); it is removed once the compressed
+//! copy is written, and still read for entries cached before outputs were
+//! compressed.
+
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use tracing::trace;
+
+/// zstd's default level, which already shrinks ANSI-heavy logs many times over.
+const COMPRESSION_LEVEL: i32 = 3;
+
+pub struct TerminalOutputStore {
+    directory: PathBuf,
+    /// Outputs over this many bytes only keep their head and tail.
+    max_size: Option<usize>,
+}
+
+impl TerminalOutputStore {
+    pub fn new(directory: PathBuf, max_size: Option<i64>) -> Self {
+        Self {
+            directory,
+            max_size: max_size.filter(|size| *size > 0).map(|size| size as usize),
+        }
+    }
+
+    /// The uncompressed file a task writes its output to while it runs.
+    pub fn path(&self, hash: &str) -> PathBuf {
+        self.directory.join(hash)
+    }
+
+    fn compressed_path(&self, hash: &str) -> PathBuf {
+        self.directory.join(format!("{}.zst", hash))
+    }
+
+    /// Every file that may hold the output of `hash`.
+    pub fn paths(&self, hash: &str) -> [PathBuf; 2] {
+        [self.compressed_path(hash), self.path(hash)]
+    }
+
+    /// Store the output of `hash`, returning the number of bytes written.
+    pub fn write(&self, hash: &str, output: &str) -> io::Result<u64> {
+        let output = match self.max_size {
+            Some(max_size) => truncate(output, max_size),
+            None => Cow::Borrowed(output),
+        };
+        let compressed = zstd::encode_all(output.as_bytes(), COMPRESSION_LEVEL)?;
+        fs::write(self.compressed_path(hash), &compressed)?;
+        match fs::remove_file(self.path(hash)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        trace!(
+            "Compressed terminal output of {} from {} to {} bytes",
+            hash,
+            output.len(),
+            compressed.len()
+        );
+        Ok(compressed.len() as u64)
+    }
+
+    /// Read the output of `hash`. A missing output reads as empty.
+    pub fn read(&self, hash: &str) -> io::Result<String> {
+        match fs::read(self.compressed_path(hash)) {
+            Ok(compressed) => {
+                let output = zstd::decode_all(compressed.as_slice())?;
+                Ok(String::from_utf8_lossy(&output).into_owned())
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                match fs::read_to_string(self.path(hash)) {
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+                    result => result,
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Keep about `max_size` bytes of `output`, half from its start and half
+/// from its end, where the summary and errors usually are.
+fn truncate(output: &str, max_size: usize) -> Cow<'_, str> {
+    if output.len() <= max_size {
+        return Cow::Borrowed(output);
+    }
+    let mut head = max_size / 2;
+    while !output.is_char_boundary(head) {
+        head -= 1;
+    }
+    let mut tail = output.len() - (max_size - head);
+    while !output.is_char_boundary(tail) {
+        tail += 1;
+    }
+    // Reset the style in case the cut lands inside a colored section.
+    Cow::Owned(format!(
+        "{}\x1b[0m\n\n... {} bytes truncated ...\n\n{}",
+        &output[..head],
+        tail - head,
+        &output[tail..]
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn should_store_outputs_compressed() {
+        let temp = TempDir::new().unwrap();
+        let store = TerminalOutputStore::new(temp.to_path_buf(), None);
+        let output = "\x1b[32m✓\x1b[0m test passed\n".repeat(1000);
+        temp.child("123").write_str(&output).unwrap();
+
+        let size = store.write("123", &output).unwrap();
+
+        assert!(size < output.len() as u64 / 10);
+        assert_eq!(fs::metadata(temp.join("123.zst")).unwrap().len(), size);
+        assert!(!temp.child("123").exists());
+        assert_eq!(store.read("123").unwrap(), output);
+    }
+
+    #[test]
+    fn should_read_uncompressed_and_missing_outputs() {
+        let temp = TempDir::new().unwrap();
+        let store = TerminalOutputStore::new(temp.to_path_buf(), None);
+        temp.child("123").write_str("built").unwrap();
+
+        assert_eq!(store.read("123").unwrap(), "built");
+        assert_eq!(store.read("456").unwrap(), "");
+    }
+
+    #[test]
+    fn should_keep_the_head_and_tail_of_large_outputs() {
+        let temp = TempDir::new().unwrap();
+        let store = TerminalOutputStore::new(temp.to_path_buf(), Some(20));
+        let output = format!("first line\n{}last line\n", "é".repeat(100));
+
+        store.write("123", &output).unwrap();
+        let stored = store.read("123").unwrap();
+
+        assert!(stored.starts_with("first line"));
+        assert!(stored.ends_with("last line\n"));
+        assert!(stored.contains("bytes truncated"));
+        assert_eq!(truncate("short", 20), "short");
+    }
+}
@@ -1,5 +1,7 @@
 use crate::native::db::connection::NxDbConnection;
 use crate::native::tasks::details::SCHEMA as TASK_DETAILS_SCHEMA;
+use crate::native::tasks::hash_details::SCHEMA as TASK_HASH_DETAILS_SCHEMA;
+use crate::native::tasks::instruction_cache::SCHEMA as INSTRUCTION_HASHES_SCHEMA;
 use crate::native::tasks::running_tasks_service::SCHEMA as RUNNING_TASKS_SCHEMA;
 use crate::native::tasks::task_history::SCHEMA as TASK_HISTORY_SCHEMA;
 use crate::native::tasks::task_invocation_tracker::SCHEMA as TASK_INVOCATIONS_SCHEMA;
@@ -11,7 +13,7 @@ use std::path::{Path, PathBuf};
 use tracing::{debug, trace};
 
 /// Bump this ONLY when the database schema changes.
-pub const DB_VERSION: &str = "3";
+pub const DB_VERSION: &str = "7";
 
 // Error reporting constants - static strings to avoid allocations in error paths
 const REPORTING_INSTRUCTIONS_PERSISTENT: &str = "If the issue persists, please help us improve Nx by capturing logs and reporting this issue:\n\
@@ -199,6 +201,8 @@ fn create_all_tables(c: &mut NxDbConnection) -> anyhow::Result<()> {
         conn.execute_batch(TASK_DETAILS_SCHEMA)?;
         conn.execute_batch(RUNNING_TASKS_SCHEMA)?;
         conn.execute_batch(TASK_INVOCATIONS_SCHEMA)?;
+        conn.execute_batch(TASK_HASH_DETAILS_SCHEMA)?;
+        conn.execute_batch(INSTRUCTION_HASHES_SCHEMA)?;
 
         // Metadata table (used by telemetry for session tracking)
         conn.execute_batch(
@@ -1,3 +1,5 @@
+use std::fs::File;
+use std::io::{self, Read};
 use std::path::Path;
 
 use tracing::trace;
@@ -32,20 +34,35 @@ pub fn hash_file(file: String) -> Option<String> {
 pub fn hash_file_path<P: AsRef<Path>>(path: P) -> Option<String> {
     let path = path.as_ref();
     trace!("Reading {:?} to hash", path);
-    let Ok(content) = std::fs::read(path) else {
+    let Ok(hash) = hash_file_contents(path) else {
         trace!("Failed to read file: {:?}", path);
         return None;
     };
-    trace!("Hashing {:?}", path);
-    let hash = hash(&content);
     trace!("Hashed file {:?} - {:?}", path, hash);
 
     Some(hash)
 }
 
+/// Hashes a file in chunks, so large files are never held in memory. The
+/// hash is the same `hash` gives for the whole contents.
+fn hash_file_contents(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = xxh3::Xxh3::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => hasher.update(&buffer[..read]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(hasher.digest().to_string())
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::native::hasher::{hash_array, hash_file};
+    use crate::native::hasher::{hash, hash_array, hash_file};
     use assert_fs::TempDir;
     use assert_fs::prelude::*;
 
@@ -82,6 +99,17 @@ mod tests {
         assert_eq!(content.unwrap(), "6193209363630369380");
     }
 
+    #[test]
+    fn it_hashes_large_files_like_their_contents() {
+        let temp_dir = TempDir::new().unwrap();
+        let content = "0123456789".repeat(20_000);
+        temp_dir.child("large.txt").write_str(&content).unwrap();
+
+        let file_hash = hash_file(temp_dir.display().to_string() + "/large.txt");
+
+        assert_eq!(file_hash.unwrap(), hash(content.as_bytes()));
+    }
+
     #[test]
     fn it_hashes_an_array() {
         // Resilient to None values (e.g. null values passed from the JS side)
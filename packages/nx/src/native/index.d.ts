@@ -88,8 +88,10 @@ export declare class HashPlanInspector {
    * file path. JsonFileSet is resolved to the matched JSON file paths (field/excludeField
    * filters only affect hashing, not which files are reported as inputs).
    * ProjectConfiguration is skipped for now. Cwd is skipped as it's ambient.
+   * Environment patterns such as `VITE_*` are resolved to the variables of
+   * `env` they match, which defaults to the environment of the process.
    */
-  inspectInputs(hashPlans: ExternalObject<Record<string, Array<HashInstruction>>>): Record<string, HashInputs>
+  inspectInputs(hashPlans: ExternalObject<Record<string, Array<HashInstruction>>>, env?: Record<string, string> | undefined | null): Record<string, HashInputs>
 }
 
 export declare class HashPlanner {
@@ -99,9 +101,28 @@ export declare class HashPlanner {
 }
 
 export declare class HttpRemoteCache {
-  constructor()
+  constructor(options?: HttpRemoteCacheOptions | undefined | null)
   retrieve(hash: string, cacheDirectory: string): Promise<CachedResult | null>
-  store(hash: string, cacheDirectory: string, terminalOutput: string, code: number): Promise<boolean>
+  /**
+   * Look up which of `hashes` the server holds with a single request.
+   * The result is index-aligned with `hashes`, or `null` when the server
+   * doesn't implement `POST /v1/cache/exists`. Hashes that recently
+   * missed are not looked up again.
+   */
+  existsBatch(hashes: Array<string>): Promise<Array<boolean> | null>
+  /**
+   * Retrieve many artifacts, index-aligned with `hashes`. A single
+   * `exists_batch` lookup decides which hashes are worth downloading; the
+   * hits are then fetched concurrently, a bounded number at a time.
+   */
+  retrieveBatch(hashes: Array<string>, cacheDirectory: string): Promise<Array<CachedResult | undefined | null>>
+  /**
+   * Time spent on lookups that missed since this cache was created.
+   * Hashes ruled out by `exists_batch`, or skipped as recent misses,
+   * are not counted.
+   */
+  missTimings(): RemoteCacheMissTimings
+  store(hash: string, cacheDirectory: string, terminalOutput: string, code: number, provenance?: CacheProvenance | undefined | null): Promise<boolean>
 }
 
 export declare class ImportResult {
@@ -113,20 +134,56 @@ export declare class ImportResult {
 
 export declare class NxCache {
   cacheDirectory: string
-  constructor(workspaceRoot: string, cachePath: string, dbConnection: ExternalObject<NxDbConnection>, linkTaskDetails?: boolean | undefined | null, maxCacheSize?: number | undefined | null)
+  constructor(workspaceRoot: string, cachePath: string, dbConnection: ExternalObject<NxDbConnection>, linkTaskDetails?: boolean | undefined | null, maxCacheSize?: number | undefined | null, evictionPolicy?: CacheEvictionPolicy | undefined | null, maxTerminalOutputSize?: number | undefined | null, provenance?: CacheProvenance | undefined | null, outputLimits?: CacheOutputLimits | undefined | null)
   get(hash: string): CachedResult | null
   /**
    * Batch version of get() that fetches multiple cache entries in a single
    * SQL query and reads terminal output files in parallel via Rayon.
    */
   getBatch(hashes: Array<string>): Array<CachedResult | undefined | null>
-  put(hash: string, terminalOutput: string, outputs: Array<string>, code: number): Array<string>
+  /**
+   * Store the outputs of a task. Files over the `max_file_size` limit are
+   * left out, and an entry over the `max_entry_size` limit is refused so
+   * that a single misconfigured target cannot evict the rest of the cache.
+   */
+  put(hash: string, terminalOutput: string, outputs: Array<string>, code: number): CachePutResult
   applyRemoteCacheResults(hash: string, result: CachedResult, outputs?: Array<string> | undefined | null): void
   getTaskOutputsPath(hash: string): string
   getCacheSize(): number
+  /**
+   * List the entries of the cache along with the details of their tasks,
+   * filtered, sorted and paginated according to `query`.
+   */
+  listEntries(query?: CacheEntryQuery | undefined | null): CacheEntryPage
+  /**
+   * Remove every entry matching `query`, e.g. all entries produced by a
+   * misbehaving CI agent, regardless of `limit` and `offset`. Returns the
+   * hashes of the removed entries.
+   */
+  removeEntries(query: CacheEntryQuery): Array<string>
+  /**
+   * Write the entries matching `query`, limited to `hashes` when given,
+   * into a single bundle at `path` that `import_bundle` can merge into
+   * the cache of another machine. Returns the hashes of the entries
+   * written.
+   */
+  exportBundle(path: string, hashes?: Array<string> | undefined | null, query?: CacheEntryQuery | undefined | null): Array<string>
+  /**
+   * Verify the entries of the bundle at `path` and merge them into the
+   * cache. Entries the cache already holds are kept as they are.
+   */
+  importBundle(path: string): CacheBundleImport
   copyFilesFromCache(cachedResult: CachedResult, outputs: Array<string>): number
   removeOldCacheRecords(): void
   checkCacheFsInSync(): boolean
+  /**
+   * Check that the database and the files of the cache agree: every
+   * entry has its outputs and terminal output, every file belongs to an
+   * entry, and recorded sizes match what is on disk. With `repair`,
+   * dangling entries are dropped, orphaned files deleted and sizes
+   * recomputed.
+   */
+  fsck(repair?: boolean | undefined | null): CacheFsckReport
 }
 
 export declare class NxConsolePreferences {
@@ -198,9 +255,34 @@ export declare class RustPseudoTerminal {
   fork(id: string, forkScript: string, pseudoIpcPath: string, commandDir: string | undefined | null, jsEnv: Record<string, string> | undefined | null, execArgv: Array<string> | undefined | null, quiet: boolean, commandLabel?: string | undefined | null): ChildProcess
 }
 
+/**
+ * A remote cache kept in a directory shared between machines, such as an
+ * NFS or SMB mount, set with `NX_SHARED_FS_REMOTE_CACHE_DIRECTORY`.
+ *
+ * Artifacts use the same tarball format as `HttpRemoteCache` and live at
+ * `<root>/<last two digits of the hash>/<hash>.tar.gz`. They are written
+ * under a temporary name and renamed into place, so readers never see a
+ * partial artifact; writers take an `fs4` lock on the shard so only the
+ * first artifact stored for a hash is kept.
+ */
+export declare class SharedFsRemoteCache {
+  constructor()
+  retrieve(hash: string, cacheDirectory: string): Promise<CachedResult | null>
+  store(hash: string, cacheDirectory: string, terminalOutput: string, code: number, provenance?: CacheProvenance | undefined | null): Promise<boolean>
+}
+
 export declare class TaskDetails {
   constructor(db: ExternalObject<NxDbConnection>)
   recordTaskDetails(tasks: Array<HashedTask>): void
+  /** The last hash the task of `hash` ran with, other than `hash` itself. */
+  getPreviousHash(hash: string): string | null
+  /**
+   * Explain why `current` differs from `previous`, by default the hash
+   * the same task last ran with. `None` when there is no previous hash,
+   * or when the details of either hash were not recorded (see
+   * `TaskHasher.recordDetails`).
+   */
+  explainHashChange(current: string, previous?: string | undefined | null): HashDiff | null
 }
 
 export declare class TaskHasher {
@@ -214,6 +296,17 @@ export declare class TaskHasher {
    * every task id.
    */
   hashPlans(hashPlans: ExternalObject<Record<string, Array<HashInstruction>>>, perTaskEnvs: Record<string, Record<string, string>>, cwd: string, collectTaskInputs?: boolean | undefined | null): Record<string, HashDetails>
+  /**
+   * Record the files of the file sets hashed from now on in `db`, so
+   * that `TaskDetails.explainHashChange` can tell which files changed.
+   */
+  recordDetails(db: ExternalObject<NxDbConnection>): void
+  /**
+   * Keep the hashes of JSON file sets and semantic file sets in `db`, so
+   * that later runs only recompute them when their inputs change. Task
+   * outputs and runtime inputs are only kept when opted in.
+   */
+  cacheInstructionHashes(db: ExternalObject<NxDbConnection>): void
 }
 
 export declare class TaskInvocationTracker {
@@ -278,11 +371,180 @@ export declare const enum BatchStatus {
   Failure = 'Failure'
 }
 
+/** The outcome of importing a bundle. */
+export interface CacheBundleImport {
+  /** Entries added to the cache. */
+  imported: Array<string>
+  /** Entries the cache already held, which are left untouched. */
+  skipped: Array<string>
+  /** Entries that failed verification and were not imported. */
+  rejected: Array<string>
+}
+
 export interface CachedResult {
   code: number
   terminalOutput?: string
   outputsPath: string
   size?: number
+  provenance?: CacheProvenance
+}
+
+/** An entry of the local cache, joined with the details of its task. */
+export interface CacheEntry {
+  hash: string
+  project?: string
+  target?: string
+  configuration?: string
+  code: number
+  /** Size in bytes */
+  size: number
+  /** Milliseconds since the epoch */
+  createdAt: number
+  /** Milliseconds since the epoch */
+  accessedAt: number
+  /** How many times the entry was restored */
+  hits: number
+  provenance: CacheProvenance
+}
+
+/** A page of cache entries. */
+export interface CacheEntryPage {
+  entries: Array<CacheEntry>
+  /** How many entries match the query, regardless of `limit` and `offset`. */
+  total: number
+  /** The combined size in bytes of the entries matching the query. */
+  totalSize: number
+}
+
+/** How an entry got into the local cache. */
+export declare const enum CacheEntryOrigin {
+  /** The task ran on this machine. */
+  local = 'local',
+  /** The entry was retrieved from a remote cache or imported from a bundle. */
+  remote = 'remote'
+}
+
+/** Which cache entries to list, in which order. */
+export interface CacheEntryQuery {
+  project?: string
+  target?: string
+  configuration?: string
+  /** Only list entries of failed (`true`) or successful (`false`) tasks. */
+  failed?: boolean
+  /** Only list entries of at least this many bytes. */
+  minSize?: number
+  /** Only list entries produced on this machine. */
+  machineId?: string
+  /** Only list entries produced at this commit. */
+  gitCommit?: string
+  /** Only list entries produced on this branch. */
+  gitBranch?: string
+  /** Only list entries produced by this version of Nx. */
+  nxVersion?: string
+  /** Only list entries produced in this CI run. */
+  ciRunId?: string
+  origin?: CacheEntryOrigin
+  /** Defaults to `accessedAt`. */
+  sortBy?: CacheEntrySortKey
+  /** Defaults to `true`: largest, most recent or most used first. */
+  descending?: boolean
+  limit?: number
+  offset?: number
+}
+
+/** What to sort cache entries by. */
+export declare const enum CacheEntrySortKey {
+  size = 'size',
+  createdAt = 'createdAt',
+  accessedAt = 'accessedAt',
+  hits = 'hits',
+  project = 'project'
+}
+
+/** How the local cache decides which entries to remove. */
+export interface CacheEvictionPolicy {
+  /**
+   * Which entries are evicted first when a size limit is exceeded.
+   * Defaults to `lru`.
+   */
+  strategy?: EvictionStrategy
+  /** Seconds an entry is kept after it was last used. Defaults to 7 days. */
+  maxAge?: number
+  /**
+   * Seconds an entry of a failed task is kept after it was last used.
+   * Defaults to 1 day, or `max_age` if that is shorter.
+   */
+  failedMaxAge?: number
+  /**
+   * Share of a size limit freed up once it is exceeded, between 0 and 1.
+   * Defaults to 0.1.
+   */
+  buffer?: number
+  /** The maximum size in bytes of the entries of each listed project. */
+  projectQuotas?: Record<string, number>
+}
+
+/** The inconsistencies found between the cache database and its files. */
+export interface CacheFsckReport {
+  /** Entries whose outputs directory is missing. */
+  missingOutputs: Array<string>
+  /** Entries whose terminal output is missing. */
+  missingTerminalOutputs: Array<string>
+  /** Outputs directories without an entry. */
+  orphanedOutputs: Array<string>
+  /** Terminal outputs without an entry. */
+  orphanedTerminalOutputs: Array<string>
+  /** Manifests without an entry. */
+  orphanedManifests: Array<string>
+  sizeMismatches: Array<CacheSizeMismatch>
+  /** Whether the inconsistencies were repaired. */
+  repaired: boolean
+}
+
+/**
+ * Size limits guarding the local cache against outputs that were never
+ * meant to be cached, such as core dumps or an `outputs` entry pointing at
+ * `node_modules`.
+ */
+export interface CacheOutputLimits {
+  /** Files over this many bytes are left out of the entry. */
+  maxFileSize?: number
+  /** Entries over this many bytes are not stored at all. */
+  maxEntrySize?: number
+}
+
+/** Where a cache entry was produced. */
+export interface CacheProvenance {
+  /** The machine the task ran on. */
+  machineId?: string
+  gitCommit?: string
+  gitBranch?: string
+  nxVersion?: string
+  /** The CI run the task ran in, if any. */
+  ciRunId?: string
+  /** Not carried over to other machines, it only makes sense locally. */
+  origin?: CacheEntryOrigin
+}
+
+/** What `NxCache::put` stored. */
+export interface CachePutResult {
+  expandedOutputs: Array<string>
+  /** Files left out of the entry for exceeding `max_file_size`. */
+  skippedFiles: Array<SkippedCacheFile>
+  /**
+   * Set to the size in bytes of the entry when it was refused for
+   * exceeding `max_entry_size`; nothing is stored then.
+   */
+  refusedSize?: number
+}
+
+/** An entry whose recorded size disagrees with the files it holds. */
+export interface CacheSizeMismatch {
+  hash: string
+  /** Size in bytes recorded in the database */
+  recorded: number
+  /** Size in bytes of the terminal output and outputs on disk */
+  actual: number
 }
 
 /**
@@ -360,6 +622,20 @@ export declare const enum EventType {
   create = 'create'
 }
 
+/**
+ * The order in which entries are evicted once the cache, or a project,
+ * is over its size limit.
+ */
+export declare const enum EvictionStrategy {
+  /** Least recently used entries go first. */
+  lru = 'lru',
+  /**
+   * Least frequently used entries go first, the least recently used among
+   * entries with as many hits.
+   */
+  lfu = 'lfu'
+}
+
 export declare function expandOutputs(directory: string, entries: Array<string>): Array<string>
 
 export interface ExternalDependenciesInput {
@@ -385,6 +661,12 @@ export interface FileMap {
 export interface FileSetInput {
   fileset: string
   dependencies?: boolean
+  /**
+   * Hash a canonical form of the files instead of their contents, so that
+   * formatting and comment changes don't change the hash. Comments that
+   * tools act on, like `@ts-ignore` or `#__PURE__`, are ignored too.
+   */
+  semantic?: boolean
 }
 
 export declare function findImports(projectFileMap: Record<string, Array<string>>): Array<ImportResult>
@@ -434,6 +716,13 @@ export declare function getMainWorktreeRoot(workspaceRoot: string): string | nul
 
 export declare function getTransformableOutputs(outputs: Array<string>): Array<string>
 
+export interface GitInput {
+  /** `HEAD`, or a fileset whose last commit is hashed. */
+  git: string
+  /** Also hash whether the files have uncommitted changes. */
+  dirty?: boolean
+}
+
 /**
  * Group information - union of different process group types
  * Use group_type to discriminate which optional fields are present
@@ -468,11 +757,23 @@ export interface HashDetails {
   inputs: HashInputs
 }
 
+/** Why `current` differs from `previous`, sorted by instruction. */
+export interface HashDiff {
+  current: string
+  previous: string
+  inputs: Array<HashInputDiff>
+}
+
 export interface HashedTask {
   hash: string
   project: string
   target: string
   configuration?: string
+  /**
+   * What the hash was computed from, recorded so that a change of the
+   * hash can be explained later on.
+   */
+  details?: TaskHashDetails
 }
 
 export interface HasherOptions {
@@ -481,6 +782,28 @@ export interface HasherOptions {
 
 export declare function hashFile(file: string): string | null
 
+/** How an instruction differs between two hashes. */
+export declare const enum HashInputChange {
+  /** Only the current hash has the instruction. */
+  added = 'added',
+  /** Only the previous hash has the instruction. */
+  removed = 'removed',
+  /** Both hashes have the instruction with different values. */
+  changed = 'changed'
+}
+
+export interface HashInputDiff {
+  /** The instruction key, as in `HashDetails.details` */
+  instruction: string
+  change: HashInputChange
+  /** Files of a file set only the current hash includes */
+  addedFiles: Array<string>
+  /** Files of a file set only the previous hash includes */
+  removedFiles: Array<string>
+  /** Files of a file set whose contents changed */
+  modifiedFiles: Array<string>
+}
+
 /** NAPI-compatible struct for returning hash inputs to JavaScript */
 export interface HashInputs {
   /** Expanded file paths that were used as inputs */
@@ -493,6 +816,20 @@ export interface HashInputs {
   depOutputs: Array<string>
   /** External dependencies */
   external: Array<string>
+  /** Git state: `HEAD`, or the filesets whose last commit is hashed */
+  git: Array<string>
+}
+
+/** Options of a remote cache, taking precedence over the environment. */
+export interface HttpRemoteCacheOptions {
+  /** Defaults to `NX_SELF_HOSTED_REMOTE_CACHE_MODE`, or `readWrite`. */
+  mode?: RemoteCacheMode
+  /**
+   * Seconds a hash the remote cache doesn't hold is not looked up again.
+   * Defaults to `NX_SELF_HOSTED_REMOTE_CACHE_NEGATIVE_CACHE_TTL`, or 60;
+   * 0 disables it.
+   */
+  negativeCacheTtl?: number
 }
 
 /**
@@ -607,7 +944,7 @@ export interface MetricsUpdate {
 
 /** Stripped version of the NxJson interface for use in rust */
 export interface NxJson {
-  namedInputs?: Record<string, Array<InputsInput | string | FileSetInput | RuntimeInput | EnvironmentInput | ExternalDependenciesInput | DepsOutputsInput | WorkingDirectoryInput | JsonInput>>
+  namedInputs?: Record<string, Array<InputsInput | string | FileSetInput | RuntimeInput | EnvironmentInput | ExternalDependenciesInput | DepsOutputsInput | WorkingDirectoryInput | JsonInput | GitInput | YamlInput | TomlInput>>
 }
 
 export interface NxWorkspaceFiles {
@@ -669,7 +1006,7 @@ export interface ProcessMetrics {
 
 export interface Project {
   root: string
-  namedInputs?: Record<string, Array<InputsInput | string | FileSetInput | RuntimeInput | EnvironmentInput | ExternalDependenciesInput | DepsOutputsInput | WorkingDirectoryInput | JsonInput>>
+  namedInputs?: Record<string, Array<InputsInput | string | FileSetInput | RuntimeInput | EnvironmentInput | ExternalDependenciesInput | DepsOutputsInput | WorkingDirectoryInput | JsonInput | GitInput | YamlInput | TomlInput>>
   tags?: Array<string>
   targets: Record<string, Target>
 }
@@ -680,6 +1017,32 @@ export interface ProjectGraph {
   externalNodes: Record<string, ExternalNode>
 }
 
+/**
+ * Time spent looking up artifacts the remote cache turned out not to hold,
+ * to tell how much remote cache latency costs a run.
+ */
+export interface RemoteCacheMissTimings {
+  /** Lookups answered with a miss. */
+  count: number
+  /** Milliseconds spent on all of them. */
+  totalMs: number
+  /** Milliseconds spent on the slowest one. */
+  maxMs: number
+}
+
+/** Which way artifacts flow between this machine and the remote cache. */
+export declare const enum RemoteCacheMode {
+  /** Artifacts are both retrieved and stored. */
+  readWrite = 'readWrite',
+  /** Artifacts are retrieved but never stored, e.g. on developer machines. */
+  readOnly = 'readOnly',
+  /**
+   * Artifacts are stored but never retrieved, e.g. on CI agents
+   * populating the cache.
+   */
+  writeOnly = 'writeOnly'
+}
+
 export declare function remove(src: string): void
 
 export declare function restoreTerminal(): void
@@ -689,8 +1052,44 @@ export declare const enum RunMode {
   RunMany = 1
 }
 
+/** What a runtime input whose command exits with a non-zero code does to the hash. */
+export declare const enum RuntimeFailurePolicy {
+  /** Hashing fails, naming the task and the input. */
+  error = 'error',
+  /**
+   * The exit code is hashed along with the output, so that a failing
+   * command never hashes like a succeeding one.
+   */
+  hashExitCode = 'hashExitCode'
+}
+
 export interface RuntimeInput {
   runtime: string
+  /**
+   * Milliseconds the command may run before hashing fails. Defaults to
+   * 60 seconds.
+   */
+  timeout?: number
+  /** What a failing command does to the hash. Defaults to `hashExitCode`. */
+  onFailure?: RuntimeFailurePolicy
+  /**
+   * Run the command with only the environment variables needed to find
+   * and run it, instead of the environment of the task.
+   */
+  minimalEnv?: boolean
+  /**
+   * Keep the hash of the output across runs, keyed by the command and
+   * the environment it runs with. Defaults to `false`.
+   */
+  cacheAcrossRuns?: boolean
+}
+
+/** A file left out of a cache entry for exceeding `max_file_size`. */
+export interface SkippedCacheFile {
+  /** Relative to the workspace root */
+  path: string
+  /** Size in bytes */
+  size: number
 }
 
 export declare const enum SupportedEditor {
@@ -710,7 +1109,7 @@ export interface SystemInfo {
 
 export interface Target {
   executor?: string
-  inputs?: Array<InputsInput | string | FileSetInput | RuntimeInput | EnvironmentInput | ExternalDependenciesInput | DepsOutputsInput | WorkingDirectoryInput | JsonInput>
+  inputs?: Array<InputsInput | string | FileSetInput | RuntimeInput | EnvironmentInput | ExternalDependenciesInput | DepsOutputsInput | WorkingDirectoryInput | JsonInput | GitInput | YamlInput | TomlInput>
   outputs?: Array<string>
   options?: string
   configurations?: string
@@ -813,6 +1212,12 @@ export interface TaskTarget {
 
 export declare function testOnlyTransferFileMap(projectFiles: Record<string, Array<FileData>>, nonProjectFiles: Array<FileData>): NxWorkspaceFilesExternals
 
+export interface TomlInput {
+  toml: string
+  fields?: Array<string>
+  excludeFields?: Array<string>
+}
+
 /** Track an event using the global telemetry instance */
 export declare function trackEvent(eventName: string, parameters?: Record<string, string> | undefined | null): void
 
@@ -856,3 +1261,9 @@ export declare const enum WorkspaceErrors {
   ParseError = 'ParseError',
   Generic = 'Generic'
 }
+
+export interface YamlInput {
+  yaml: string
+  fields?: Array<string>
+  excludeFields?: Array<string>
+}
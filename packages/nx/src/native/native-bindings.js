@@ -589,12 +589,15 @@ module.exports.NxTaskHistory = nativeBinding.NxTaskHistory
 module.exports.ProcessMetricsCollector = nativeBinding.ProcessMetricsCollector
 module.exports.RunningTasksService = nativeBinding.RunningTasksService
 module.exports.RustPseudoTerminal = nativeBinding.RustPseudoTerminal
+module.exports.SharedFsRemoteCache = nativeBinding.SharedFsRemoteCache
 module.exports.TaskDetails = nativeBinding.TaskDetails
 module.exports.TaskHasher = nativeBinding.TaskHasher
 module.exports.TaskInvocationTracker = nativeBinding.TaskInvocationTracker
 module.exports.Watcher = nativeBinding.Watcher
 module.exports.WorkspaceContext = nativeBinding.WorkspaceContext
 module.exports.BatchStatus = nativeBinding.BatchStatus
+module.exports.CacheEntryOrigin = nativeBinding.CacheEntryOrigin
+module.exports.CacheEntrySortKey = nativeBinding.CacheEntrySortKey
 module.exports.canInstallNxConsole = nativeBinding.canInstallNxConsole
 module.exports.canInstallNxConsoleForEditor = nativeBinding.canInstallNxConsoleForEditor
 module.exports.closeDbConnection = nativeBinding.closeDbConnection
@@ -602,6 +605,7 @@ module.exports.connectToNxDb = nativeBinding.connectToNxDb
 module.exports.copy = nativeBinding.copy
 module.exports.detectAiAgent = nativeBinding.detectAiAgent
 module.exports.EventType = nativeBinding.EventType
+module.exports.EvictionStrategy = nativeBinding.EvictionStrategy
 module.exports.expandOutputs = nativeBinding.expandOutputs
 module.exports.findImports = nativeBinding.findImports
 module.exports.flushTelemetry = nativeBinding.flushTelemetry
@@ -616,6 +620,7 @@ module.exports.getTransformableOutputs = nativeBinding.getTransformableOutputs
 module.exports.GroupType = nativeBinding.GroupType
 module.exports.hashArray = nativeBinding.hashArray
 module.exports.hashFile = nativeBinding.hashFile
+module.exports.HashInputChange = nativeBinding.HashInputChange
 module.exports.initializeTelemetry = nativeBinding.initializeTelemetry
 module.exports.initializeTelemetryWithSessionId = nativeBinding.initializeTelemetryWithSessionId
 module.exports.installNxConsole = nativeBinding.installNxConsole
@@ -629,9 +634,11 @@ module.exports.logDebug = nativeBinding.logDebug
 module.exports.matchGlobPaths = nativeBinding.matchGlobPaths
 module.exports.matchOutputPaths = nativeBinding.matchOutputPaths
 module.exports.parseTaskStatus = nativeBinding.parseTaskStatus
+module.exports.RemoteCacheMode = nativeBinding.RemoteCacheMode
 module.exports.remove = nativeBinding.remove
 module.exports.restoreTerminal = nativeBinding.restoreTerminal
 module.exports.RunMode = nativeBinding.RunMode
+module.exports.RuntimeFailurePolicy = nativeBinding.RuntimeFailurePolicy
 module.exports.SupportedEditor = nativeBinding.SupportedEditor
 module.exports.TaskStatus = nativeBinding.TaskStatus
 module.exports.testOnlyTransferFileMap = nativeBinding.testOnlyTransferFileMap
@@ -1,4 +1,8 @@
 use crate::native::db::connection::NxDbConnection;
+use crate::native::tasks::hash_details::{
+    HashDiff, diff_hashes, get_previous_hash, instruction_values_of,
+};
+use crate::native::tasks::types::TaskHashDetails;
 use napi::bindgen_prelude::External;
 use rusqlite::params;
 use std::sync::{Arc, Mutex};
@@ -18,6 +22,9 @@ pub struct HashedTask {
     pub project: String,
     pub target: String,
     pub configuration: Option<String>,
+    /// What the hash was computed from, recorded so that a change of the
+    /// hash can be explained later on.
+    pub details: Option<TaskHashDetails>,
 }
 
 #[napi]
@@ -49,9 +56,47 @@ impl TaskDetails {
                     params![task.hash, task.project, task.target, task.configuration],
                 )?;
             }
+            let mut stmt = conn.prepare(
+                "INSERT OR IGNORE INTO task_hash_details (hash, instruction, value) VALUES (?1, ?2, ?3)",
+            )?;
+            for task in tasks.iter() {
+                let Some(details) = &task.details else {
+                    continue;
+                };
+                for (instruction, value) in instruction_values_of(details) {
+                    stmt.execute(params![task.hash, instruction, value])?;
+                }
+            }
             Ok(())
         })?;
 
         Ok(())
     }
+
+    /// The last hash the task of `hash` ran with, other than `hash` itself.
+    #[napi]
+    pub fn get_previous_hash(&self, hash: String) -> anyhow::Result<Option<String>> {
+        get_previous_hash(&self.db.lock().unwrap(), &hash)
+    }
+
+    /// Explain why `current` differs from `previous`, by default the hash
+    /// the same task last ran with. `None` when there is no previous hash,
+    /// or when the details of either hash were not recorded (see
+    /// `TaskHasher.recordDetails`).
+    #[napi]
+    pub fn explain_hash_change(
+        &self,
+        current: String,
+        previous: Option<String>,
+    ) -> anyhow::Result<Option<HashDiff>> {
+        let db = self.db.lock().unwrap();
+        let previous = match previous {
+            Some(previous) => previous,
+            None => match get_previous_hash(&db, &current)? {
+                Some(previous) => previous,
+                None => return Ok(None),
+            },
+        };
+        diff_hashes(&db, &current, &previous)
+    }
 }
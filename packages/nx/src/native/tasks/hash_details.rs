@@ -0,0 +1,496 @@
+use crate::native::db::connection::NxDbConnection;
+use crate::native::tasks::hash_plan_inspector::HashPlanInspector;
+use crate::native::tasks::hashers::ProjectFileIndicesCache;
+use crate::native::tasks::task_hasher::{HashDetails, TaskHasher};
+use crate::native::tasks::types::{HashInstruction, HashPlans, TaskHashDetails};
+use crate::native::types::{FileData, NapiDashMap, SharedStr};
+use napi::bindgen_prelude::External;
+use rusqlite::{params, types::Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::trace;
+
+/// The value of every instruction of a task hash, and the files behind the
+/// values of file set instructions. File sets are keyed by their value, so a
+/// file set that didn't change between two hashes is only stored once.
+pub const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS task_hash_details (
+    hash    TEXT NOT NULL,
+    instruction  TEXT NOT NULL,
+    value  TEXT NOT NULL,
+    created_at  TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (hash, instruction)
+);
+CREATE INDEX IF NOT EXISTS task_hash_details_value_idx ON task_hash_details (value);
+CREATE INDEX IF NOT EXISTS task_hash_details_created_at_idx ON task_hash_details (created_at);
+CREATE TABLE IF NOT EXISTS file_set_details (
+    value    TEXT NOT NULL,
+    file  TEXT NOT NULL,
+    file_hash  TEXT NOT NULL,
+    created_at  TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (value, file)
+);";
+
+/// How long the details of a hash are kept, as an SQLite date modifier.
+const RETENTION: &str = "-30 days";
+
+/// How an instruction differs between two hashes.
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashInputChange {
+    /// Only the current hash has the instruction.
+    #[allow(non_camel_case_types)]
+    added,
+    /// Only the previous hash has the instruction.
+    #[allow(non_camel_case_types)]
+    removed,
+    /// Both hashes have the instruction with different values.
+    #[allow(non_camel_case_types)]
+    changed,
+}
+
+#[napi(object)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct HashInputDiff {
+    /// The instruction key, as in `HashDetails.details`
+    pub instruction: String,
+    pub change: HashInputChange,
+    /// Files of a file set only the current hash includes
+    pub added_files: Vec<String>,
+    /// Files of a file set only the previous hash includes
+    pub removed_files: Vec<String>,
+    /// Files of a file set whose contents changed
+    pub modified_files: Vec<String>,
+}
+
+/// Why `current` differs from `previous`, sorted by instruction.
+#[napi(object)]
+#[derive(Debug, Clone)]
+pub struct HashDiff {
+    pub current: String,
+    pub previous: String,
+    pub inputs: Vec<HashInputDiff>,
+}
+
+/// Persists the files behind the file sets a `TaskHasher` hashes. The
+/// values of the other instructions are recorded along with the task
+/// details, as the task hash also covers the command.
+pub(crate) struct HashDetailsRecorder {
+    db: Arc<Mutex<NxDbConnection>>,
+    pruned: AtomicBool,
+}
+
+impl HashDetailsRecorder {
+    pub(crate) fn new(db: Arc<Mutex<NxDbConnection>>) -> Self {
+        Self {
+            db,
+            pruned: AtomicBool::new(false),
+        }
+    }
+
+    /// Record the files of the file sets whose values weren't seen before,
+    /// resolved through the inspector.
+    pub(crate) fn record(
+        &self,
+        hash_plans: &HashPlans,
+        hashes: &NapiDashMap<String, HashDetails>,
+        inspector: &HashPlanInspector,
+        project_file_indices_cache: &ProjectFileIndicesCache,
+        all_workspace_files: &[FileData],
+    ) -> anyhow::Result<()> {
+        let mut file_sets: HashMap<SharedStr, u32> = HashMap::new();
+        for entry in hashes.iter() {
+            let (task_id, hash_details) = entry.pair();
+            let Some(ids) = hash_plans.plans.get(task_id) else {
+                continue;
+            };
+            for id in ids {
+                if !matches!(
+                    hash_plans.pool.get(*id).value(),
+                    HashInstruction::WorkspaceFileSet(_)
+                        | HashInstruction::ProjectFileSet(_, _)
+                        | HashInstruction::JsonFileSet(_)
+                        | HashInstruction::SemanticFileSet(_, _)
+                ) {
+                    continue;
+                }
+                let key = SharedStr::from(hash_plans.pool.key(*id));
+                if let Some(value) = hash_details.details.get(&key) {
+                    file_sets.entry(value.clone()).or_insert(*id);
+                }
+            }
+        }
+
+        let mut db = self.db.lock().unwrap();
+        if !self.pruned.swap(true, Ordering::Relaxed) {
+            prune(&db)?;
+        }
+        if file_sets.is_empty() {
+            return Ok(());
+        }
+
+        let values = Rc::new(
+            file_sets
+                .keys()
+                .map(|v| Value::from(v.to_string()))
+                .collect::<Vec<Value>>(),
+        );
+        let recorded: HashSet<String> = db
+            .query_map(
+                "SELECT DISTINCT value FROM file_set_details WHERE value IN rarray(?1)",
+                [values],
+                |row| row.get(0),
+            )?
+            .into_iter()
+            .collect();
+        file_sets.retain(|value, _| !recorded.contains(&**value));
+        if file_sets.is_empty() {
+            return Ok(());
+        }
+
+        let file_hashes: HashMap<&str, &str> = all_workspace_files
+            .iter()
+            .map(|f| (f.file.as_str(), f.hash.as_str()))
+            .collect();
+        let mut files: Vec<(&SharedStr, String, &str)> = vec![];
+        for (value, id) in file_sets.iter() {
+            let inputs = inspector.resolve_instruction_inputs(
+                hash_plans.pool.get(*id).value(),
+                project_file_indices_cache,
+            )?;
+            for file in inputs.files {
+                if let Some(file_hash) = file_hashes.get(file.as_str()) {
+                    files.push((value, file, *file_hash));
+                }
+            }
+        }
+
+        trace!(
+            "Recording {} files of {} file sets",
+            files.len(),
+            file_sets.len()
+        );
+        db.transaction(|conn| {
+            let mut stmt = conn.prepare(
+                "INSERT OR IGNORE INTO file_set_details (value, file, file_hash) VALUES (?1, ?2, ?3)",
+            )?;
+            for (value, file, file_hash) in files.iter() {
+                stmt.execute(params![&***value, file, file_hash])?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Flatten what a task hash was computed from into instruction values.
+pub(crate) fn instruction_values_of(details: &TaskHashDetails) -> Vec<(String, String)> {
+    let mut values = vec![("command".to_string(), details.command.clone())];
+    values.extend(details.nodes.iter().map(|(k, v)| (k.clone(), v.clone())));
+    for (prefix, map) in [
+        ("implicitDeps", &details.implicit_deps),
+        ("runtime", &details.runtime),
+    ] {
+        if let Some(map) = map {
+            values.extend(
+                map.iter()
+                    .map(|(k, v)| (format!("{}:{}", prefix, k), v.clone())),
+            );
+        }
+    }
+    values
+}
+
+/// Drop details past the retention period, along with the file sets no
+/// recorded hash refers to anymore. File sets are recorded at hash time,
+/// before the hash itself, so only expired ones are considered orphaned.
+fn prune(db: &NxDbConnection) -> anyhow::Result<()> {
+    db.execute(
+        "DELETE FROM task_hash_details WHERE created_at < datetime('now', ?1)",
+        [RETENTION],
+    )?;
+    db.execute(
+        "DELETE FROM file_set_details WHERE created_at < datetime('now', ?1)
+            AND value NOT IN (SELECT value FROM task_hash_details)",
+        [RETENTION],
+    )?;
+    Ok(())
+}
+
+/// The last hash the task of `hash` ran with, other than `hash` itself.
+pub(crate) fn get_previous_hash(db: &NxDbConnection, hash: &str) -> anyhow::Result<Option<String>> {
+    db.query_row(
+        "SELECT task_history.hash FROM task_history
+            JOIN task_details previous ON task_history.hash = previous.hash
+            JOIN task_details current ON current.project = previous.project
+                AND current.target = previous.target
+                AND current.configuration IS previous.configuration
+            WHERE current.hash = ?1 AND task_history.hash != ?1
+            ORDER BY task_history.end DESC
+            LIMIT 1",
+        [hash],
+        |row| row.get(0),
+    )
+}
+
+/// Diff the recorded details of two hashes. `None` when either of them has
+/// no recorded details.
+pub(crate) fn diff_hashes(
+    db: &NxDbConnection,
+    current: &str,
+    previous: &str,
+) -> anyhow::Result<Option<HashDiff>> {
+    let current_details = instruction_values(db, current)?;
+    let previous_details = instruction_values(db, previous)?;
+    if current_details.is_empty() || previous_details.is_empty() {
+        return Ok(None);
+    }
+
+    let instructions: Vec<&String> = current_details
+        .keys()
+        .chain(previous_details.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let mut inputs = vec![];
+    for instruction in instructions {
+        let current_value = current_details.get(instruction);
+        let previous_value = previous_details.get(instruction);
+        let change = match (current_value, previous_value) {
+            (Some(c), Some(p)) if c == p => continue,
+            (Some(_), Some(_)) => HashInputChange::changed,
+            (Some(_), None) => HashInputChange::added,
+            (None, _) => HashInputChange::removed,
+        };
+        let current_files = match current_value {
+            Some(value) => file_set_files(db, value)?,
+            None => BTreeMap::new(),
+        };
+        let previous_files = match previous_value {
+            Some(value) => file_set_files(db, value)?,
+            None => BTreeMap::new(),
+        };
+        let mut diff = HashInputDiff {
+            instruction: instruction.clone(),
+            change,
+            added_files: vec![],
+            removed_files: vec![],
+            modified_files: vec![],
+        };
+        for (file, hash) in current_files.iter() {
+            match previous_files.get(file) {
+                None => diff.added_files.push(file.clone()),
+                Some(previous_hash) if previous_hash != hash => {
+                    diff.modified_files.push(file.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed_files = previous_files
+            .into_keys()
+            .filter(|file| !current_files.contains_key(file))
+            .collect();
+        inputs.push(diff);
+    }
+
+    Ok(Some(HashDiff {
+        current: current.to_string(),
+        previous: previous.to_string(),
+        inputs,
+    }))
+}
+
+fn instruction_values(db: &NxDbConnection, hash: &str) -> anyhow::Result<BTreeMap<String, String>> {
+    Ok(db
+        .query_map(
+            "SELECT instruction, value FROM task_hash_details WHERE hash = ?1",
+            [hash],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?
+        .into_iter()
+        .collect())
+}
+
+fn file_set_files(db: &NxDbConnection, value: &str) -> anyhow::Result<BTreeMap<String, String>> {
+    Ok(db
+        .query_map(
+            "SELECT file, file_hash FROM file_set_details WHERE value = ?1",
+            [value],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?
+        .into_iter()
+        .collect())
+}
+
+#[napi]
+impl TaskHasher {
+    /// Record the files of the file sets hashed from now on in `db`, so
+    /// that `TaskDetails.explainHashChange` can tell which files changed.
+    #[napi]
+    pub fn record_details(
+        &mut self,
+        #[napi(ts_arg_type = "ExternalObject<NxDbConnection>")] db: &External<
+            Arc<Mutex<NxDbConnection>>,
+        >,
+    ) {
+        self.details_recorder = Some(HashDetailsRecorder::new(Arc::clone(db)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::native::db::initialize::initialize_db;
+    use assert_fs::TempDir;
+
+    fn insert_details(db: &NxDbConnection, hash: &str, details: &[(&str, &str)]) {
+        for (instruction, value) in details {
+            db.execute(
+                "INSERT INTO task_hash_details (hash, instruction, value) VALUES (?1, ?2, ?3)",
+                params![hash, instruction, value],
+            )
+            .unwrap();
+        }
+    }
+
+    fn insert_files(db: &NxDbConnection, value: &str, files: &[(&str, &str)]) {
+        for (file, file_hash) in files {
+            db.execute(
+                "INSERT INTO file_set_details (value, file, file_hash) VALUES (?1, ?2, ?3)",
+                params![value, file, file_hash],
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn should_diff_instructions_and_file_sets() {
+        let temp = TempDir::new().unwrap();
+        let db = initialize_db(&temp.join("test.db")).unwrap();
+        insert_details(
+            &db,
+            "previous",
+            &[
+                ("proj:app", "1"),
+                ("env:CI", "2"),
+                ("runtime:node -v", "3"),
+                ("AllExternalDependencies", "4"),
+            ],
+        );
+        insert_details(
+            &db,
+            "current",
+            &[
+                ("proj:app", "10"),
+                ("env:CI", "2"),
+                ("runtime:node -v", "30"),
+                ("env:NODE_ENV", "5"),
+            ],
+        );
+        insert_files(&db, "1", &[("a.ts", "a"), ("b.ts", "b"), ("c.ts", "c")]);
+        insert_files(&db, "10", &[("a.ts", "a"), ("b.ts", "b2"), ("d.ts", "d")]);
+
+        let diff = diff_hashes(&db, "current", "previous").unwrap().unwrap();
+
+        assert_eq!(
+            diff.inputs,
+            vec![
+                HashInputDiff {
+                    instruction: "AllExternalDependencies".into(),
+                    change: HashInputChange::removed,
+                    added_files: vec![],
+                    removed_files: vec![],
+                    modified_files: vec![],
+                },
+                HashInputDiff {
+                    instruction: "env:NODE_ENV".into(),
+                    change: HashInputChange::added,
+                    added_files: vec![],
+                    removed_files: vec![],
+                    modified_files: vec![],
+                },
+                HashInputDiff {
+                    instruction: "proj:app".into(),
+                    change: HashInputChange::changed,
+                    added_files: vec!["d.ts".into()],
+                    removed_files: vec!["c.ts".into()],
+                    modified_files: vec!["b.ts".into()],
+                },
+                HashInputDiff {
+                    instruction: "runtime:node -v".into(),
+                    change: HashInputChange::changed,
+                    added_files: vec![],
+                    removed_files: vec![],
+                    modified_files: vec![],
+                },
+            ]
+        );
+        assert!(diff_hashes(&db, "current", "unknown").unwrap().is_none());
+    }
+
+    #[test]
+    fn should_find_the_previous_hash_of_the_same_task() {
+        let temp = TempDir::new().unwrap();
+        let db = initialize_db(&temp.join("test.db")).unwrap();
+        for (hash, project, end) in [
+            ("older", "app", 1),
+            ("previous", "app", 2),
+            ("current", "app", 3),
+            ("other", "lib", 4),
+        ] {
+            db.execute(
+                "INSERT INTO task_details (hash, project, target, configuration) VALUES (?1, ?2, 'build', NULL)",
+                params![hash, project],
+            )
+            .unwrap();
+            db.execute(
+                "INSERT INTO task_history (hash, status, code, start, end) VALUES (?1, 'success', 0, ?2, ?2)",
+                params![hash, end],
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            get_previous_hash(&db, "current").unwrap(),
+            Some("previous".to_string())
+        );
+        assert_eq!(get_previous_hash(&db, "other").unwrap(), None);
+    }
+
+    #[test]
+    fn should_prune_expired_details() {
+        let temp = TempDir::new().unwrap();
+        let db = initialize_db(&temp.join("test.db")).unwrap();
+        insert_details(&db, "fresh", &[("proj:app", "1")]);
+        insert_details(&db, "expired", &[("proj:lib", "2")]);
+        db.execute(
+            "UPDATE task_hash_details SET created_at = datetime('now', '-31 days') WHERE hash = 'expired'",
+            [],
+        )
+        .unwrap();
+        insert_files(&db, "1", &[("a.ts", "a")]);
+        insert_files(&db, "2", &[("b.ts", "b")]);
+        insert_files(&db, "3", &[("c.ts", "c")]);
+        db.execute(
+            "UPDATE file_set_details SET created_at = datetime('now', '-31 days') WHERE value != '3'",
+            [],
+        )
+        .unwrap();
+
+        prune(&db).unwrap();
+
+        let hashes: Vec<String> = db
+            .query_map("SELECT hash FROM task_hash_details", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(hashes, vec!["fresh".to_string()]);
+        // "3" is not referred to yet, but might be by a hash being recorded
+        let values: Vec<String> = db
+            .query_map(
+                "SELECT value FROM file_set_details ORDER BY value",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(values, vec!["1".to_string(), "3".to_string()]);
+    }
+}
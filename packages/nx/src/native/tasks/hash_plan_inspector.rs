@@ -1,6 +1,7 @@
 use crate::native::tasks::hashers::{
     ProjectFileIndicesCache, collect_json_input_files, collect_project_file_paths_cached,
-    collect_workspace_file_paths, resolve_task_output_files,
+    collect_semantic_files, collect_workspace_file_paths, matching_env_names,
+    resolve_task_output_files,
 };
 use crate::native::tasks::task_hasher::{HashInputs, HashInputsBuilder};
 use crate::native::tasks::types::{HashInstruction, HashPlans};
@@ -18,6 +19,22 @@ pub struct HashPlanInspector {
     workspace_root: String,
 }
 
+impl HashPlanInspector {
+    /// An inspector over the files a `TaskHasher` already holds.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn from_parts(
+        all_workspace_files: Arc<Vec<FileData>>,
+        project_file_map: Arc<HashMap<String, Vec<FileData>>>,
+        workspace_root: String,
+    ) -> Self {
+        Self {
+            all_workspace_files,
+            project_file_map,
+            workspace_root,
+        }
+    }
+}
+
 #[napi]
 impl HashPlanInspector {
     #[napi(constructor)]
@@ -56,7 +73,8 @@ impl HashPlanInspector {
                 let strings = match instruction {
                     // File-set instructions: resolve to actual file paths
                     HashInstruction::WorkspaceFileSet(_)
-                    | HashInstruction::ProjectFileSet(_, _) => {
+                    | HashInstruction::ProjectFileSet(_, _)
+                    | HashInstruction::SemanticFileSet(_, _) => {
                         let builder = self
                             .resolve_instruction_inputs(instruction, &project_file_indices_cache)?;
                         builder
@@ -84,14 +102,19 @@ impl HashPlanInspector {
     /// Each `HashInstruction` is categorized into the appropriate bucket (files, runtime,
     /// environment, depOutputs, external). TsConfiguration is resolved to the root tsconfig
     /// file path. JsonFileSet is resolved to the matched JSON file paths (field/excludeField
-    /// filters only affect hashing, not which files are reported as inputs).
+    /// filters only affect hashing, not which files are reported as inputs), and
+    /// SemanticFileSet to the files it matches.
     /// ProjectConfiguration is skipped for now. Cwd is skipped as it's ambient.
+    /// Environment patterns such as `VITE_*` are resolved to the variables of
+    /// `env` they match, which defaults to the environment of the process.
     #[napi(ts_return_type = "Record<string, HashInputs>")]
     pub fn inspect_inputs(
         &self,
         #[napi(ts_arg_type = "ExternalObject<Record<string, Array<HashInstruction>>>")]
         hash_plans: &External<HashPlans>,
+        env: Option<HashMap<String, String>>,
     ) -> anyhow::Result<HashMap<String, HashInputs>> {
+        let env = env.unwrap_or_else(|| std::env::vars().collect());
         let project_file_indices_cache = ProjectFileIndicesCache::new();
         let pool = &hash_plans.pool;
         let results: Vec<(&String, HashInputsBuilder)> = hash_plans
@@ -101,10 +124,15 @@ impl HashPlanInspector {
             .par_bridge()
             .map(|(task_id, id)| {
                 let instruction_ref = pool.get(id);
-                let builder = self.resolve_instruction_inputs(
-                    instruction_ref.value(),
-                    &project_file_indices_cache,
-                )?;
+                let builder = match instruction_ref.value() {
+                    HashInstruction::Environment(env_name) => HashInputsBuilder {
+                        environment: matching_env_names(env_name, &env).into_iter().collect(),
+                        ..Default::default()
+                    },
+                    instruction => {
+                        self.resolve_instruction_inputs(instruction, &project_file_indices_cache)?
+                    }
+                };
                 Ok::<_, anyhow::Error>((task_id, builder))
             })
             .collect::<anyhow::Result<_>>()?;
@@ -125,7 +153,7 @@ impl HashPlanInspector {
     /// Resolves a single `HashInstruction` into its structured inputs without hashing.
     /// Context-dependent variants are handled explicitly with access to workspace files,
     /// project graph, etc. Context-free variants fall through to `instruction.into()`.
-    fn resolve_instruction_inputs(
+    pub(crate) fn resolve_instruction_inputs(
         &self,
         instruction: &HashInstruction,
         project_file_indices_cache: &ProjectFileIndicesCache,
@@ -151,6 +179,18 @@ impl HashPlanInspector {
                     ..Default::default()
                 })
             }
+            HashInstruction::SemanticFileSet(project_name, file_sets) => {
+                let files = collect_semantic_files(
+                    project_name.as_deref(),
+                    file_sets,
+                    &self.project_file_map,
+                    &self.all_workspace_files,
+                )?;
+                Ok(HashInputsBuilder {
+                    files: files.into_iter().map(|f| f.file.clone()).collect(),
+                    ..Default::default()
+                })
+            }
             HashInstruction::TaskOutput(glob, dep_outputs) => {
                 let dep_output_files: HashSet<String> =
                     resolve_task_output_files(&self.workspace_root, glob, dep_outputs)
@@ -1,7 +1,10 @@
 use crate::native::logger::enable_logger;
 use crate::native::tasks::{
     dep_outputs::get_dep_output,
-    types::{CwdMode, HashInstruction, HashPlans, InstructionPool, JsonFileSetInput, TaskGraph},
+    types::{
+        CwdMode, GitStateInput, HashInstruction, HashPlans, InstructionPool, JsonFileSetInput,
+        RuntimeCommandInput, TaskGraph,
+    },
 };
 use crate::native::types::{Input, NxJson};
 use crate::native::{
@@ -13,7 +16,7 @@ use rayon::prelude::*;
 use std::collections::HashMap;
 use tracing::trace;
 
-use crate::native::tasks::hashers::OnceCache;
+use crate::native::tasks::hashers::{GIT_HEAD, OnceCache};
 use crate::native::tasks::inputs::{
     expand_single_project_inputs, get_inputs, get_inputs_for_dependency, get_named_inputs,
 };
@@ -458,7 +461,9 @@ impl HashPlanner {
     ) -> anyhow::Result<Arc<SubtreeResult>> {
         let cache_key = match input {
             Input::Inputs { input, .. } => format!("{dep}\0i\0{input}"),
-            Input::FileSet { fileset, .. } => format!("{dep}\0f\0{fileset}"),
+            Input::FileSet {
+                fileset, semantic, ..
+            } => format!("{dep}\0{}\0{fileset}", if *semantic { "s" } else { "f" }),
             // Other input kinds never reach dependencies (get_inputs_for_dependency
             // returns None for them), so they share one empty entry per project.
             _ => format!("{dep}\0none"),
@@ -626,15 +631,41 @@ impl HashPlanner {
         project_name: &str,
         self_inputs: &[Input],
     ) -> Vec<HashInstruction> {
-        let (project_file_sets, workspace_file_sets): (Vec<&str>, Vec<&str>) = self_inputs
-            .iter()
-            .filter_map(|input| match input {
-                Input::FileSet { fileset, .. } => Some(*fileset),
-                _ => None,
-            })
-            .partition(|file_set| {
-                file_set.starts_with("{projectRoot}/") || file_set.starts_with("!{projectRoot}/")
-            });
+        let mut file_sets: Vec<String> = vec![];
+        let mut semantic_file_sets: Vec<String> = vec![];
+        for input in self_inputs {
+            if let Input::FileSet {
+                fileset, semantic, ..
+            } = input
+            {
+                if *semantic && !fileset.starts_with('!') {
+                    semantic_file_sets.push(fileset.to_string());
+                } else {
+                    file_sets.push(fileset.to_string());
+                }
+            }
+        }
+        if !semantic_file_sets.is_empty() {
+            // Exclusions apply to semantic file sets too, and the files they
+            // match are left out of the other file sets, so that their
+            // formatting doesn't reach the hash through those.
+            let exclusions: Vec<String> = file_sets
+                .iter()
+                .filter(|file_set| file_set.starts_with('!'))
+                .cloned()
+                .collect();
+            file_sets.extend(semantic_file_sets.iter().map(|f| format!("!{f}")));
+            semantic_file_sets.extend(exclusions);
+        }
+        let is_project_file_set = |file_set: &String| {
+            file_set.starts_with("{projectRoot}/") || file_set.starts_with("!{projectRoot}/")
+        };
+        let (project_file_sets, workspace_file_sets): (Vec<String>, Vec<String>) =
+            file_sets.into_iter().partition(is_project_file_set);
+        let (semantic_project_file_sets, semantic_workspace_file_sets): (Vec<String>, Vec<String>) =
+            semantic_file_sets
+                .into_iter()
+                .partition(is_project_file_set);
 
         let project_root = &self.project_graph.nodes[project_name].root;
 
@@ -667,8 +698,25 @@ impl HashPlanner {
                     .collect(),
             )]
         };
+        let semantic_file_set_inputs = [
+            (Some(project_name.to_string()), semantic_project_file_sets),
+            (None, semantic_workspace_file_sets),
+        ]
+        .into_iter()
+        .filter(|(_, file_sets)| file_sets.iter().any(|f| !f.starts_with('!')))
+        .map(|(project, file_sets)| {
+            HashInstruction::SemanticFileSet(
+                project,
+                file_sets
+                    .iter()
+                    .map(|f| resolve_tokens(f, project_root, project_name))
+                    .collect(),
+            )
+        });
         let runtime_and_env_inputs = self_inputs.iter().filter_map(|i| match i {
-            Input::Runtime(runtime) => Some(HashInstruction::Runtime(runtime.to_string())),
+            Input::Runtime(runtime) => Some(HashInstruction::Runtime(RuntimeCommandInput::from(
+                *runtime,
+            ))),
             Input::Environment(env) => Some(HashInstruction::Environment(env.to_string())),
             Input::WorkingDirectory(mode) => {
                 let cwd_mode = match mode.to_lowercase().as_str() {
@@ -679,6 +727,7 @@ impl HashPlanner {
             }
             Input::Json {
                 json,
+                format,
                 fields,
                 exclude_fields,
             } => {
@@ -690,16 +739,27 @@ impl HashPlanner {
                 Some(HashInstruction::JsonFileSet(Box::new(JsonFileSetInput {
                     project_name: proj_name,
                     json_path: resolve_tokens(json, project_root, project_name),
+                    format: *format,
                     fields: fields.map(|f| f.to_vec()),
                     exclude_fields: exclude_fields.map(|f| f.to_vec()),
                 })))
             }
+            Input::Git { git, dirty } => Some(HashInstruction::Git(GitStateInput {
+                path: (*git != GIT_HEAD).then(|| {
+                    let path = resolve_tokens(git, project_root, project_name);
+                    path.strip_prefix("{workspaceRoot}/")
+                        .map(str::to_string)
+                        .unwrap_or(path)
+                }),
+                dirty: *dirty,
+            })),
             _ => None,
         });
 
         project_inputs
             .into_iter()
             .chain(workspace_file_set_inputs)
+            .chain(semantic_file_set_inputs)
             .chain(runtime_and_env_inputs)
             .collect()
     }
@@ -797,7 +857,83 @@ fn find_external_dependency_node_name<'a>(
 
 #[cfg(test)]
 mod tests {
-    use super::VisitedTracker;
+    use super::*;
+    use crate::native::project_graph::types::{ExternalNode, Project, Target};
+    use crate::native::tasks::hashers::hash_all_externals;
+    use dashmap::DashMap;
+
+    /// `app` depends on `npm:parent` only, which depends on `npm:child`.
+    fn planner(child_version: &str) -> HashPlanner {
+        let external = |name: &str, version: &str| ExternalNode {
+            package_name: Some(name.into()),
+            version: version.into(),
+            hash: None,
+        };
+        let project_graph = ProjectGraph {
+            nodes: HashMap::from([(
+                "app".to_string(),
+                Project {
+                    root: "app".into(),
+                    targets: HashMap::from([("build".to_string(), Target::default())]),
+                    ..Default::default()
+                },
+            )]),
+            dependencies: HashMap::from([
+                ("app".to_string(), vec!["npm:parent".to_string()]),
+                ("npm:parent".to_string(), vec!["npm:child".to_string()]),
+            ]),
+            external_nodes: HashMap::from([
+                ("npm:parent".to_string(), external("parent", "1.0.0")),
+                ("npm:child".to_string(), external("child", child_version)),
+            ]),
+        };
+
+        HashPlanner {
+            nx_json: NxJson { named_inputs: None },
+            project_graph: Arc::new(project_graph),
+            external_deps_mapped: OnceLock::new(),
+            subtree_memo: OnceCache::new(),
+            is_acyclic: OnceLock::new(),
+            instruction_pool: Arc::new(InstructionPool::new()),
+        }
+    }
+
+    /// The externals in the plan of `app:build`, and their combined hash.
+    fn planned_externals(planner: &HashPlanner) -> (Vec<String>, String) {
+        let task = Task::new("app", "build");
+        let task_graph = TaskGraph {
+            roots: vec![task.id.clone()],
+            tasks: HashMap::from([(task.id.clone(), task)]),
+            dependencies: HashMap::new(),
+            continuous_dependencies: HashMap::new(),
+        };
+        let plans = planner
+            .get_plans_materialized(vec!["app:build"], task_graph)
+            .unwrap();
+        let externals = plans["app:build"]
+            .iter()
+            .filter_map(|instruction| match instruction {
+                HashInstruction::External(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let hash = hash_all_externals(
+            &externals,
+            &planner.project_graph.external_nodes,
+            Arc::new(DashMap::new()),
+        )
+        .unwrap();
+        (externals, hash)
+    }
+
+    #[test]
+    fn transitive_externals_are_planned_for_dependents() {
+        let (externals, before) = planned_externals(&planner("1.0.0"));
+        assert_eq!(externals, vec!["npm:child", "npm:parent"]);
+
+        let (_, after) = planned_externals(&planner("1.0.1"));
+        assert_ne!(before, after);
+    }
 
     #[test]
     fn insert_reports_first_insertion_only() {
@@ -1,10 +1,12 @@
 mod hash_cwd;
 mod hash_env;
 mod hash_external;
+mod hash_git;
 mod hash_json;
 mod hash_project_config;
 mod hash_project_files;
 mod hash_runtime;
+mod hash_semantic;
 mod hash_task_output;
 mod hash_tsconfig;
 mod hash_workspace_files;
@@ -13,6 +15,7 @@ mod once_cache;
 pub use hash_cwd::*;
 pub use hash_env::*;
 pub use hash_external::*;
+pub use hash_git::*;
 pub use hash_json::*;
 pub use hash_project_config::*;
 pub(crate) use hash_project_files::{
@@ -23,7 +26,20 @@ pub use hash_project_files::{
     collect_project_file_paths, collect_project_files, hash_project_files,
 };
 pub use hash_runtime::*;
+pub use hash_semantic::*;
 pub use hash_task_output::*;
 pub use hash_tsconfig::*;
 pub use hash_workspace_files::*;
 pub(crate) use once_cache::OnceCache;
+
+/// Version of the way hashers derive the hashes that are persisted across
+/// runs. Bump it whenever one of them changes what it hashes, so that hashes
+/// computed by an older Nx are not reused. The DB version only covers
+/// changes to the schema.
+pub const INSTRUCTION_HASH_VERSION: u32 = 1;
+
+/// Key of a persisted instruction hash: its kind, the hash version and a
+/// digest of everything the hash is derived from.
+pub fn instruction_cache_key(kind: &str, digest: impl std::fmt::Display) -> String {
+    format!("{kind}:v{INSTRUCTION_HASH_VERSION}:{digest}")
+}
@@ -2,8 +2,70 @@ use crate::native::hasher::hash;
 use std::collections::HashMap;
 
 pub fn hash_env(env_name: &str, env: &HashMap<String, String>) -> String {
-    let env_value = env.get(env_name).map(|s| s.as_str()).unwrap_or("");
-    hash(env_value.as_bytes())
+    if !is_env_pattern(env_name) {
+        let env_value = env.get(env_name).map(|s| s.as_str()).unwrap_or("");
+        return hash(env_value.as_bytes());
+    }
+
+    // Names and values are NUL-separated: neither can contain one.
+    let entries = matching_env_names(env_name, env)
+        .into_iter()
+        .map(|name| format!("{}={}\0", name, env[&name]))
+        .collect::<String>();
+    hash(entries.as_bytes())
+}
+
+/// Whether an env input names a pattern, such as `VITE_*`, rather than a
+/// single variable. `*` matches any run of characters, `?` a single one.
+pub fn is_env_pattern(env_name: &str) -> bool {
+    env_name.contains(['*', '?'])
+}
+
+/// The sorted names of the variables in `env` that the input `env_name`
+/// refers to. An exact name is returned as is, whether or not it is set.
+pub fn matching_env_names(env_name: &str, env: &HashMap<String, String>) -> Vec<String> {
+    if !is_env_pattern(env_name) {
+        return vec![env_name.to_string()];
+    }
+    let pattern = env_name.chars().collect::<Vec<_>>();
+    let mut names = env
+        .keys()
+        .filter(|name| matches_pattern(&pattern, &name.chars().collect::<Vec<_>>()))
+        .cloned()
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+fn matches_pattern(pattern: &[char], name: &[char]) -> bool {
+    // Greedy wildcard matching, backtracking to the last `*` on a mismatch.
+    let (mut p, mut n) = (0, 0);
+    let mut last_star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                last_star = Some((p, n));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                n += 1;
+            }
+            Some(c) if *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match last_star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    last_star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
 }
 
 #[cfg(test)]
@@ -27,4 +89,38 @@ mod test {
 
         assert_eq!(hash, "3244421341483603138");
     }
+
+    #[test]
+    fn should_match_env_patterns() {
+        let env = HashMap::from([
+            ("VITE_API".to_string(), "a".to_string()),
+            ("VITE_APP_NAME".to_string(), "b".to_string()),
+            ("NX_VITE".to_string(), "c".to_string()),
+            ("VITE".to_string(), "d".to_string()),
+        ]);
+
+        assert_eq!(
+            matching_env_names("VITE_*", &env),
+            vec!["VITE_API", "VITE_APP_NAME"]
+        );
+        assert_eq!(matching_env_names("*VITE", &env), vec!["NX_VITE", "VITE"]);
+        assert_eq!(matching_env_names("VITE_AP?", &env), vec!["VITE_API"]);
+        assert_eq!(matching_env_names("*_A*_*", &env), vec!["VITE_APP_NAME"]);
+        assert_eq!(matching_env_names("MISSING", &env), vec!["MISSING"]);
+    }
+
+    #[test]
+    fn should_hash_every_variable_matching_a_pattern() {
+        let mut env = HashMap::from([
+            ("VITE_API".to_string(), "a".to_string()),
+            ("OTHER".to_string(), "b".to_string()),
+        ]);
+        let before = hash_env("VITE_*", &env);
+
+        env.insert("OTHER".to_string(), "changed".to_string());
+        assert_eq!(hash_env("VITE_*", &env), before);
+
+        env.insert("VITE_NEW".to_string(), "".to_string());
+        assert_ne!(hash_env("VITE_*", &env), before);
+    }
 }
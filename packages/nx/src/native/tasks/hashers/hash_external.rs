@@ -6,6 +6,8 @@ use std::sync::Arc;
 use anyhow::*;
 use dashmap::DashMap;
 
+/// Hashes an external on its own. Its transitive dependencies are hashed by
+/// instructions of their own, added by `HashPlanner` from the project graph.
 pub fn hash_external(
     external_name: &str,
     externals: &HashMap<String, ExternalNode>,
@@ -82,6 +84,16 @@ mod test {
         assert_eq!(external_node_hash.unwrap(), "4204073044699973956");
     }
 
+    #[test]
+    fn test_hash_external_requires_the_node_even_when_cached() {
+        let external_nodes = get_external_nodes_map();
+        let cache: Arc<DashMap<String, String>> = Arc::new(DashMap::new());
+        cache.insert("npm:removed".into(), "123".into());
+
+        let result = hash_external("npm:removed", &external_nodes, Arc::clone(&cache));
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_hash_all_externals() {
         let external_nodes = get_external_nodes_map();
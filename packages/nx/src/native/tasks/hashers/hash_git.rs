@@ -0,0 +1,233 @@
+use crate::native::hasher::hash;
+use crate::native::tasks::types::GitStateInput;
+use crate::native::utils::command::create_command;
+use crate::native::utils::git::find_git_root;
+use dashmap::DashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::trace;
+
+/// The value of a git input that hashes the commit HEAD points to.
+pub const GIT_HEAD: &str = "HEAD";
+
+/// How many symbolic refs are followed before giving up, like git itself.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+pub fn hash_git(
+    workspace_root: &str,
+    input: &GitStateInput,
+    cache: &DashMap<String, String>,
+) -> anyhow::Result<String> {
+    let cache_key = input.to_string();
+    if let Some(cache_results) = cache.get(&cache_key) {
+        return Ok(cache_results.clone());
+    }
+
+    let workspace_root = Path::new(workspace_root);
+    let head = read_head_commit(workspace_root)?;
+    // Without commits there is no history to search, and git log fails.
+    let commit = match (&input.path, head) {
+        (Some(path), Some(_)) => last_commit_touching(workspace_root, path)?,
+        (None, head) => head,
+        (Some(_), None) => None,
+    };
+    trace!("git commit for {}: {:?}", cache_key, commit);
+
+    let mut value = commit.unwrap_or_default();
+    if input.dirty {
+        let dirty = has_uncommitted_changes(workspace_root, input.path.as_deref())?;
+        value.push_str(if dirty { ":dirty" } else { ":clean" });
+    }
+    let hash_result = hash(value.as_bytes());
+
+    cache.insert(cache_key, hash_result.clone());
+
+    Ok(hash_result)
+}
+
+/// The commit HEAD points to, read from the `.git` directory of the
+/// repository containing `workspace_root`. `None` on a branch without
+/// commits.
+pub fn read_head_commit(workspace_root: &Path) -> anyhow::Result<Option<String>> {
+    let (git_dir, common_dir) = find_git_dirs(workspace_root)?;
+    let mut name = GIT_HEAD.to_string();
+    for _ in 0..MAX_SYMREF_DEPTH {
+        // HEAD belongs to the worktree, branches to the repository.
+        let content = [&git_dir, &common_dir]
+            .into_iter()
+            .find_map(|dir| fs::read_to_string(dir.join(&name)).ok());
+        let Some(content) = content else {
+            return read_packed_ref(&common_dir, &name);
+        };
+        match content.trim().strip_prefix("ref:") {
+            Some(target) => name = target.trim().to_string(),
+            None => return Ok(Some(content.trim().to_string())),
+        }
+    }
+    anyhow::bail!("Too many levels of symbolic refs resolving {}", name)
+}
+
+/// The git directory of the repository containing `workspace_root`, and the
+/// directory shared by all of its worktrees.
+fn find_git_dirs(workspace_root: &Path) -> anyhow::Result<(PathBuf, PathBuf)> {
+    let git_root = find_git_root(workspace_root).ok_or_else(|| {
+        anyhow::anyhow!("{} is not in a git repository", workspace_root.display())
+    })?;
+    let mut git_dir = git_root.join(".git");
+    // In worktrees and submodules, .git is a file pointing to the git dir.
+    if git_dir.is_file() {
+        let content = fs::read_to_string(&git_dir)?;
+        let target = content
+            .trim()
+            .strip_prefix("gitdir:")
+            .ok_or_else(|| anyhow::anyhow!("Unrecognized .git file in {}", git_root.display()))?;
+        git_dir = git_root.join(target.trim());
+    }
+    let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common_dir) => git_dir.join(common_dir.trim()),
+        Err(_) => git_dir.clone(),
+    };
+    Ok((git_dir, common_dir))
+}
+
+fn read_packed_ref(common_dir: &Path, name: &str) -> anyhow::Result<Option<String>> {
+    let Ok(packed_refs) = fs::read_to_string(common_dir.join("packed-refs")) else {
+        return Ok(None);
+    };
+    Ok(packed_refs
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+        .find_map(|line| {
+            let (commit, ref_name) = line.split_once(' ')?;
+            (ref_name.trim() == name).then(|| commit.to_string())
+        }))
+}
+
+/// The last commit that changed a file matching `path`.
+///
+/// Unlike HEAD, this can't be read from the `.git` directory without
+/// reimplementing most of git: commits and trees live in packfiles as delta
+/// chains against other objects, shallow clones and replace refs change what
+/// the history is, and which side of a merge "changed" a path follows git's
+/// history simplification. `git log` already does all of that, and is
+/// only run once per fileset and run.
+fn last_commit_touching(workspace_root: &Path, path: &str) -> anyhow::Result<Option<String>> {
+    let stdout = run_git(
+        workspace_root,
+        &["log", "-1", "--format=%H", "--", &glob_pathspec(path)],
+    )?;
+    let commit = stdout.trim();
+    Ok((!commit.is_empty()).then(|| commit.to_string()))
+}
+
+/// Whether files matching `path` differ from HEAD. Like `git status`, this
+/// needs the index, ignore rules and the clean filters of the repository,
+/// so it asks git too.
+fn has_uncommitted_changes(workspace_root: &Path, path: Option<&str>) -> anyhow::Result<bool> {
+    let pathspec = path.map_or_else(|| ".".to_string(), glob_pathspec);
+    let stdout = run_git(workspace_root, &["status", "--porcelain", "--", &pathspec])?;
+    Ok(!stdout.trim().is_empty())
+}
+
+/// A pathspec matching `path` the way filesets do, with `**` spanning
+/// directories. Negated filesets are rejected when inputs are expanded.
+fn glob_pathspec(path: &str) -> String {
+    format!(":(glob){}", path)
+}
+
+fn run_git(workspace_root: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = create_command("git")
+        .args(args)
+        .current_dir(workspace_root)
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to execute git: {}", e))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = create_command("git")
+            .args(["-c", "user.email=test@example.com", "-c", "user.name=Test"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .expect("failed to run git");
+        assert!(
+            output.status.success(),
+            "git {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    fn commit_file(dir: &Path, file: &str, content: &str) -> String {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        git(dir, &["add", "."]);
+        git(dir, &["commit", "-m", file]);
+        git(dir, &["rev-parse", "HEAD"])
+    }
+
+    fn init_repo() -> TempDir {
+        let tmp = TempDir::new().unwrap();
+        git(tmp.path(), &["init", "-b", "main"]);
+        tmp
+    }
+
+    #[test]
+    fn should_read_head_from_loose_and_packed_refs() {
+        let tmp = init_repo();
+        assert_eq!(read_head_commit(tmp.path()).unwrap(), None);
+
+        let commit = commit_file(tmp.path(), "a.txt", "a");
+        assert_eq!(read_head_commit(tmp.path()).unwrap(), Some(commit.clone()));
+
+        git(tmp.path(), &["pack-refs", "--all"]);
+        assert_eq!(read_head_commit(tmp.path()).unwrap(), Some(commit));
+    }
+
+    #[test]
+    fn should_only_change_when_matching_files_are_committed() {
+        let tmp = init_repo();
+        let root = tmp.path().to_str().unwrap();
+        commit_file(tmp.path(), "libs/a/index.ts", "a");
+        let input = GitStateInput {
+            path: Some("libs/a/**/*".into()),
+            dirty: false,
+        };
+        let before = hash_git(root, &input, &DashMap::new()).unwrap();
+
+        commit_file(tmp.path(), "libs/b/index.ts", "b");
+        assert_eq!(hash_git(root, &input, &DashMap::new()).unwrap(), before);
+
+        commit_file(tmp.path(), "libs/a/nested/index.ts", "a");
+        assert_ne!(hash_git(root, &input, &DashMap::new()).unwrap(), before);
+    }
+
+    #[test]
+    fn should_hash_uncommitted_changes_when_asked_to() {
+        let tmp = init_repo();
+        let root = tmp.path().to_str().unwrap();
+        commit_file(tmp.path(), "a.txt", "a");
+        let input = GitStateInput {
+            path: None,
+            dirty: true,
+        };
+        let clean = hash_git(root, &input, &DashMap::new()).unwrap();
+
+        fs::write(tmp.path().join("a.txt"), "changed").unwrap();
+        assert_ne!(hash_git(root, &input, &DashMap::new()).unwrap(), clean);
+    }
+}
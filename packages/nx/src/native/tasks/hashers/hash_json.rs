@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::path::Path;
 
 use anyhow::Result;
+use serde::Deserialize;
 use serde_json::Value;
 use tracing::{debug, debug_span, trace};
 use xxhash_rust::xxh3::Xxh3;
@@ -9,6 +10,8 @@ use xxhash_rust::xxh3::Xxh3;
 use crate::native::glob::build_glob_set;
 use crate::native::hasher::hash;
 use crate::native::tasks::hashers::hash_workspace_files::globs_from_workspace_globs;
+use crate::native::tasks::hashers::instruction_cache_key;
+use crate::native::tasks::types::StructuredFormat;
 use crate::native::types::FileData;
 
 #[derive(Clone)]
@@ -32,6 +35,25 @@ pub fn collect_json_input_files<'a>(
     project_file_map: &'a HashMap<String, Vec<FileData>>,
     all_workspace_files: &'a [FileData],
 ) -> Result<Vec<&'a str>> {
+    Ok(collect_json_input_file_data(
+        json_path,
+        project_name,
+        project_file_map,
+        all_workspace_files,
+    )?
+    .into_iter()
+    .map(|f| f.file.as_str())
+    .collect())
+}
+
+/// Like `collect_json_input_files`, but returns the matched `FileData` so
+/// that callers can key on the content hashes of the files.
+pub fn collect_json_input_file_data<'a>(
+    json_path: &str,
+    project_name: Option<&str>,
+    project_file_map: &'a HashMap<String, Vec<FileData>>,
+    all_workspace_files: &'a [FileData],
+) -> Result<Vec<&'a FileData>> {
     if let Some(project_name) = project_name {
         // Project-root path: already resolved by the planner (e.g. "libs/my-lib/package.json")
         let glob_set = build_glob_set(&[json_path.to_string()])?;
@@ -41,7 +63,6 @@ pub fn collect_json_input_files<'a>(
                 files
                     .iter()
                     .filter(|f| glob_set.is_match(&f.file))
-                    .map(|f| f.file.as_str())
                     .collect()
             })
             .unwrap_or_default())
@@ -55,7 +76,6 @@ pub fn collect_json_input_files<'a>(
         Ok(all_workspace_files
             .iter()
             .filter(|f| glob_set.is_match(&f.file))
-            .map(|f| f.file.as_str())
             .collect())
     }
 }
@@ -68,7 +88,7 @@ pub fn collect_json_input_files<'a>(
 ///
 /// Returns `None` when neither parser accepts the bytes — callers should fall
 /// back to hashing the raw bytes in that case.
-fn parse_json_or_jsonc(bytes: &[u8]) -> Option<Value> {
+pub(super) fn parse_json_or_jsonc(bytes: &[u8]) -> Option<Value> {
     if let Ok(v) = serde_json::from_slice::<Value>(bytes) {
         return Some(v);
     }
@@ -78,7 +98,63 @@ fn parse_json_or_jsonc(bytes: &[u8]) -> Option<Value> {
         .ok()?
 }
 
+/// Parses every YAML document in `bytes`. A single document parses to its
+/// value, several (separated by `---`) to an array of them.
+///
+/// Returns `None` when the YAML is invalid or can't be represented as JSON,
+/// e.g. for mappings with non-string keys.
+fn parse_yaml(bytes: &[u8]) -> Option<Value> {
+    let mut documents = serde_norway::Deserializer::from_slice(bytes)
+        .map(Value::deserialize)
+        .collect::<Result<Vec<_>, _>>()
+        .inspect_err(|e| trace!("yaml parse failed: {}", e))
+        .ok()?;
+    if documents.len() == 1 {
+        documents.pop()
+    } else {
+        Some(Value::Array(documents))
+    }
+}
+
+fn parse_toml(bytes: &[u8]) -> Option<Value> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    toml::from_str(text)
+        .inspect_err(|e| trace!("toml parse failed: {}", e))
+        .ok()
+}
+
+fn parse_structured(bytes: &[u8], format: StructuredFormat) -> Option<Value> {
+    match format {
+        StructuredFormat::Json => parse_json_or_jsonc(bytes),
+        StructuredFormat::Yaml => parse_yaml(bytes),
+        StructuredFormat::Toml => parse_toml(bytes),
+    }
+}
+
 /// Hashes JSON files, optionally filtering to specific fields.
+/// See `hash_structured_files`.
+pub fn hash_json_files(
+    workspace_root: &str,
+    json_path: &str,
+    project_name: Option<&str>,
+    fields: Option<&[String]>,
+    exclude_fields: Option<&[String]>,
+    project_file_map: &HashMap<String, Vec<FileData>>,
+    all_workspace_files: &[FileData],
+) -> Result<JsonHashResult> {
+    hash_structured_files(
+        workspace_root,
+        json_path,
+        StructuredFormat::Json,
+        project_name,
+        fields,
+        exclude_fields,
+        project_file_map,
+        all_workspace_files,
+    )
+}
+
+/// Hashes JSON, YAML or TOML files, optionally filtering to specific fields.
 ///
 /// Token resolution (`{projectRoot}`, `{projectName}`) is handled upstream by the
 /// `HashPlanner`, so `json_path` arrives here already resolved. `project_name` is
@@ -86,12 +162,17 @@ fn parse_json_or_jsonc(bytes: &[u8]) -> Option<Value> {
 /// `project_file_map`) and `None` for `{workspaceRoot}` patterns (match against
 /// `all_workspace_files` after stripping the prefix).
 ///
-/// Reads matching files from disk, parses JSON (falling back to JSONC for files
-/// like `tsconfig.json` that may carry `//` comments or trailing commas),
+/// Reads matching files from disk, parses them (falling back to JSONC for JSON
+/// files like `tsconfig.json` that may carry `//` comments or trailing commas),
 /// filters by fields/excludeFields, and hashes the result deterministically.
-pub fn hash_json_files(
+/// YAML and TOML files are hashed as the JSON they parse to, so reformatting
+/// them, reordering keys or switching between equivalent syntaxes (such as
+/// inline and standard TOML tables) does not change the hash.
+#[allow(clippy::too_many_arguments)]
+pub fn hash_structured_files(
     workspace_root: &str,
     json_path: &str,
+    format: StructuredFormat,
     project_name: Option<&str>,
     fields: Option<&[String]>,
     exclude_fields: Option<&[String]>,
@@ -125,12 +206,12 @@ pub fn hash_json_files(
                 continue;
             };
 
-            let Some(parsed) = parse_json_or_jsonc(&bytes) else {
-                // Neither strict JSON nor JSONC accepted the bytes. Fall back
+            let Some(parsed) = parse_structured(&bytes, format) else {
+                // The file doesn't parse in its format. Fall back
                 // to hashing the raw file contents so the input is still
                 // covered by the cache key — but field filters are necessarily
                 // ignored in this branch since we have no parsed tree.
-                trace!("Failed to parse JSON/JSONC file {:?}", abs_path);
+                trace!("Failed to parse {} file {:?}", format, abs_path);
                 hasher.update(file_path.as_bytes());
                 hasher.update(&bytes);
                 result_files.push(file_path.to_string());
@@ -164,6 +245,20 @@ pub fn hash_json_files(
     })
 }
 
+/// Key of a JSON file set: the instruction, which covers the fields, and
+/// the content hashes of the files it reads.
+pub fn json_file_set_key(instruction: &str, files: &[&FileData]) -> String {
+    let mut hasher = Xxh3::new();
+    hasher.update(instruction.as_bytes());
+    for file in files {
+        hasher.update(b"\0");
+        hasher.update(file.file.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(file.hash.as_bytes());
+    }
+    instruction_cache_key("json", hasher.digest())
+}
+
 /// Filters a JSON value based on field allowlist and denylist.
 /// Fields use dot notation for nested access (e.g., "compilerOptions.target").
 fn filter_json_value(
@@ -298,7 +393,7 @@ fn remove_nested_field(obj: &mut serde_json::Map<String, Value>, path: &str) {
 ///
 /// The produced byte sequence defines the cache key and MUST NOT change once
 /// released — do not reformat without a migration.
-fn hash_sorted_json(hasher: &mut Xxh3, value: &Value) {
+pub(super) fn hash_sorted_json(hasher: &mut Xxh3, value: &Value) {
     match value {
         Value::Object(map) => {
             let mut sorted: Vec<(&String, &Value)> = map.iter().collect();
@@ -337,6 +432,7 @@ fn hash_sorted_json(hasher: &mut Xxh3, value: &Value) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::native::tasks::hashers::INSTRUCTION_HASH_VERSION;
     use std::collections::HashMap;
 
     #[test]
@@ -646,4 +742,111 @@ mod tests {
              fallback is not being hit and we're hashing raw bytes"
         );
     }
+
+    #[test]
+    fn test_json_file_set_key_changes_with_file_contents() {
+        let file = |hash: &str| FileData {
+            file: "package.json".into(),
+            hash: hash.into(),
+        };
+        let before = file("1");
+        let after = file("2");
+
+        assert_eq!(
+            json_file_set_key("json:package.json", &[&before]),
+            json_file_set_key("json:package.json", &[&file("1")])
+        );
+        assert_ne!(
+            json_file_set_key("json:package.json", &[&before]),
+            json_file_set_key("json:package.json", &[&after])
+        );
+        assert_ne!(
+            json_file_set_key("json:package.json", &[&before]),
+            json_file_set_key("json:package.json[version]", &[&before])
+        );
+        assert!(
+            json_file_set_key("json:package.json", &[&before])
+                .starts_with(&format!("json:v{INSTRUCTION_HASH_VERSION}:"))
+        );
+    }
+
+    fn hash_file_as(
+        format: StructuredFormat,
+        file: &str,
+        content: &str,
+        fields: Option<&[String]>,
+    ) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(file), content).unwrap();
+        let all_workspace_files = vec![FileData {
+            file: file.into(),
+            hash: "abc123".into(),
+        }];
+
+        hash_structured_files(
+            dir.path().to_str().unwrap(),
+            &format!("{{workspaceRoot}}/{file}"),
+            format,
+            None,
+            fields,
+            None,
+            &HashMap::new(),
+            &all_workspace_files,
+        )
+        .unwrap()
+        .hash
+    }
+
+    #[test]
+    fn test_hash_yaml_ignores_formatting() {
+        let fields = ["packages".to_string()];
+        let block = hash_file_as(
+            StructuredFormat::Yaml,
+            "pnpm-workspace.yaml",
+            "# workspace\npackages:\n  - 'packages/*'\n  - apps/*\ncatalog:\n  react: ^19\n",
+            Some(&fields),
+        );
+        let flow = hash_file_as(
+            StructuredFormat::Yaml,
+            "pnpm-workspace.yaml",
+            "catalog: { react: ^18 }\npackages: [\"packages/*\", apps/*]\n",
+            Some(&fields),
+        );
+        assert_eq!(block, flow);
+
+        let reordered = hash_file_as(
+            StructuredFormat::Yaml,
+            "pnpm-workspace.yaml",
+            "packages: [apps/*, packages/*]\n",
+            Some(&fields),
+        );
+        assert_ne!(block, reordered);
+    }
+
+    #[test]
+    fn test_hash_toml_ignores_formatting() {
+        let fields = ["dependencies.serde".to_string()];
+        let standard = hash_file_as(
+            StructuredFormat::Toml,
+            "Cargo.toml",
+            "[package]\nname = \"a\"\n\n[dependencies.serde]\nversion = \"1\"\nfeatures = [\"derive\"]\n",
+            Some(&fields),
+        );
+        let inline = hash_file_as(
+            StructuredFormat::Toml,
+            "Cargo.toml",
+            "[dependencies]\nserde = { features = [ \"derive\" ], version = '1' }\nanyhow = \"1\"\n",
+            Some(&fields),
+        );
+        assert_eq!(standard, inline);
+    }
+
+    #[test]
+    fn test_hash_yaml_and_toml_fall_back_to_raw_contents() {
+        let invalid_toml = |content| hash_file_as(StructuredFormat::Toml, "a.toml", content, None);
+        assert_ne!(invalid_toml("a = "), invalid_toml("a ="));
+
+        let invalid_yaml = |content| hash_file_as(StructuredFormat::Yaml, "a.yaml", content, None);
+        assert_ne!(invalid_yaml("a: ["), invalid_yaml("a:  ["));
+    }
 }
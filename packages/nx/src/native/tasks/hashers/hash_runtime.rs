@@ -1,64 +1,204 @@
 use crate::native::hasher::hash;
+use crate::native::tasks::types::RuntimeCommandInput;
+use crate::native::types::RuntimeFailurePolicy;
 use crate::native::utils::command::create_shell_command;
 use dashmap::DashMap;
 use std::collections::HashMap;
+use std::io::Read;
+use std::process::{Command, Output, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 use tracing::trace;
 
+/// How long a runtime command may run when its input doesn't set a timeout.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often a running command is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// The environment variables passed to commands of `minimalEnv` runtime
+/// inputs: those needed to find and start programs on each platform.
+const MINIMAL_ENV: &[&str] = &[
+    "PATH",
+    "PATHEXT",
+    "HOME",
+    "USERPROFILE",
+    "SYSTEMROOT",
+    "SYSTEMDRIVE",
+    "WINDIR",
+    "COMSPEC",
+    "TEMP",
+    "TMP",
+    "TMPDIR",
+];
+
 pub fn hash_runtime(
     workspace_root: &str,
-    command: &str,
+    runtime: &RuntimeCommandInput,
     env: &HashMap<String, String>,
     cache: &DashMap<String, String>,
 ) -> anyhow::Result<String> {
-    let cache_key = runtime_cache_key(command, env);
+    let minimal_env;
+    let env = if runtime.minimal_env {
+        minimal_env = command_env(env);
+        &minimal_env
+    } else {
+        env
+    };
+    let cache_key = runtime_cache_key(&runtime.to_string(), env);
 
     if let Some(cache_results) = cache.get(&cache_key) {
         return Ok(cache_results.clone());
     }
 
+    let command = &runtime.command;
     let mut command_builder = create_shell_command();
 
     command_builder.arg(command);
 
     command_builder.current_dir(workspace_root);
+    if runtime.minimal_env {
+        command_builder.env_clear();
+    }
     env.iter().for_each(|(key, value)| {
         command_builder.env(key, value);
     });
     trace!("executing: {:?}", command_builder);
-    let output = command_builder
-        .output()
-        .map_err(|e| anyhow::anyhow!("Failed to execute: '{}'\n{}", command, e))?;
+    let timeout = runtime
+        .timeout
+        .map_or(DEFAULT_TIMEOUT, |ms| Duration::from_millis(ms as u64));
+    let output = output_with_timeout(command_builder, timeout)
+        .map_err(|e| anyhow::anyhow!("Failed to execute: '{}'\n{}", command, e))?
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "'{}' did not finish within {}ms",
+                command,
+                timeout.as_millis()
+            )
+        })?;
     trace!("{} output: {:?}", command, output);
 
     let std_out = std::str::from_utf8(&output.stdout)?.trim();
     let std_err = std::str::from_utf8(&output.stderr)?.trim();
-    let hash_result = hash(&[std_out.as_bytes(), std_err.as_bytes()].concat());
+    let hash_result = if output.status.success() {
+        hash(&[std_out.as_bytes(), std_err.as_bytes()].concat())
+    } else {
+        // Killed by a signal, the command has no exit code.
+        let exit_code = output
+            .status
+            .code()
+            .map_or_else(|| "none".to_string(), |code| code.to_string());
+        match runtime.on_failure {
+            RuntimeFailurePolicy::error => anyhow::bail!(
+                "'{}' failed with exit code {}\n{}",
+                command,
+                exit_code,
+                std_err
+            ),
+            RuntimeFailurePolicy::hashExitCode => hash(
+                &[
+                    std_out.as_bytes(),
+                    std_err.as_bytes(),
+                    format!("exit code {exit_code}").as_bytes(),
+                ]
+                .concat(),
+            ),
+        }
+    };
 
     cache.insert(cache_key, hash_result.clone());
 
     Ok(hash_result)
 }
 
+/// Key of the runtime input within a run: the command, its options and the
+/// environment it runs with.
+pub fn runtime_key(runtime: &RuntimeCommandInput, env: &HashMap<String, String>) -> String {
+    if runtime.minimal_env {
+        runtime_cache_key(&runtime.to_string(), &command_env(env))
+    } else {
+        runtime_cache_key(&runtime.to_string(), env)
+    }
+}
+
+/// The variables of `env` passed to commands of `minimalEnv` runtime inputs.
+fn command_env(env: &HashMap<String, String>) -> HashMap<String, String> {
+    env.iter()
+        .filter(|(key, _)| MINIMAL_ENV.iter().any(|m| m.eq_ignore_ascii_case(key)))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
 fn runtime_cache_key(command: &str, env: &HashMap<String, String>) -> String {
     let mut entries: Vec<_> = env.iter().collect();
     entries.sort_by(|(a, _), (b, _)| a.cmp(b));
     format!("{}-{:?}", command, entries)
 }
 
+/// Like `Command::output`, but kills the command once `timeout` has passed
+/// and returns `None` instead of its output.
+fn output_with_timeout(mut command: Command, timeout: Duration) -> anyhow::Result<Option<Output>> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Read both pipes while waiting, so that a command filling one of them
+    // doesn't block before exiting.
+    let stdout = child.stdout.take().map(read_to_end_in_background);
+    let stderr = child.stderr.take().map(read_to_end_in_background);
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    let join = |reader: Option<JoinHandle<Vec<u8>>>| {
+        reader
+            .map(|reader| reader.join().unwrap_or_default())
+            .unwrap_or_default()
+    };
+    Ok(Some(Output {
+        status,
+        stdout: join(stdout),
+        stderr: join(stderr),
+    }))
+}
+
+fn read_to_end_in_background(mut pipe: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = vec![];
+        let _ = pipe.read_to_end(&mut buffer);
+        buffer
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use dashmap::DashMap;
     use std::collections::HashMap;
 
+    fn workspace_root() -> &'static str {
+        if cfg!(windows) { "C:\\" } else { "/tmp" }
+    }
+
     #[test]
     fn test_hash_runtime() {
-        let workspace_root = if cfg!(windows) { "C:\\" } else { "/tmp" };
-        let command = "echo runtime";
+        let workspace_root = workspace_root();
+        let runtime = RuntimeCommandInput::new("echo runtime");
         let env: HashMap<String, String> = HashMap::new();
         let cache = DashMap::new();
 
-        let result = hash_runtime(workspace_root, command, &env, &cache).unwrap();
+        let result = hash_runtime(workspace_root, &runtime, &env, &cache).unwrap();
         assert_eq!(result, "10571312846059850300");
     }
 
@@ -78,4 +218,98 @@ mod tests {
 
         assert_eq!(key_a, key_b);
     }
+
+    #[test]
+    fn should_hash_the_exit_code_of_failing_commands() {
+        let env = HashMap::new();
+        let cache = DashMap::new();
+        let exit_1 = RuntimeCommandInput::new("echo runtime && exit 1");
+        let exit_2 = RuntimeCommandInput::new("echo runtime && exit 2");
+
+        let success = hash_runtime(
+            workspace_root(),
+            &RuntimeCommandInput::new("echo runtime"),
+            &env,
+            &cache,
+        )
+        .unwrap();
+        let exit_1 = hash_runtime(workspace_root(), &exit_1, &env, &cache).unwrap();
+        let exit_2 = hash_runtime(workspace_root(), &exit_2, &env, &cache).unwrap();
+
+        assert_ne!(exit_1, success);
+        assert_ne!(exit_1, exit_2);
+    }
+
+    #[test]
+    fn should_error_on_failure_when_asked_to() {
+        let runtime = RuntimeCommandInput {
+            on_failure: RuntimeFailurePolicy::error,
+            ..RuntimeCommandInput::new("exit 3")
+        };
+
+        let error =
+            hash_runtime(workspace_root(), &runtime, &HashMap::new(), &DashMap::new()).unwrap_err();
+        assert!(error.to_string().contains("exit code 3"), "{error}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_stop_commands_that_time_out() {
+        let runtime = RuntimeCommandInput {
+            timeout: Some(100),
+            ..RuntimeCommandInput::new("sleep 5")
+        };
+
+        let started = Instant::now();
+        let error =
+            hash_runtime(workspace_root(), &runtime, &HashMap::new(), &DashMap::new()).unwrap_err();
+        assert!(error.to_string().contains("100ms"), "{error}");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_only_pass_the_minimal_env() {
+        let env = HashMap::from([
+            (
+                "PATH".to_string(),
+                std::env::var("PATH").unwrap_or_default(),
+            ),
+            ("SECRET".to_string(), "value".to_string()),
+        ]);
+        let cache = DashMap::new();
+        let runtime = RuntimeCommandInput {
+            minimal_env: true,
+            ..RuntimeCommandInput::new("echo $SECRET")
+        };
+
+        let minimal = hash_runtime(workspace_root(), &runtime, &env, &cache).unwrap();
+        let empty = hash_runtime(
+            workspace_root(),
+            &RuntimeCommandInput::new("echo"),
+            &env,
+            &cache,
+        )
+        .unwrap();
+        assert_eq!(minimal, empty);
+    }
+
+    #[test]
+    fn runtime_key_only_depends_on_the_env_the_command_sees() {
+        let env = HashMap::from([("SECRET".to_string(), "value".to_string())]);
+        let runtime = RuntimeCommandInput::new("node -v");
+        assert_ne!(
+            runtime_key(&runtime, &env),
+            runtime_key(&runtime, &HashMap::new())
+        );
+
+        let runtime = RuntimeCommandInput {
+            minimal_env: true,
+            ..runtime
+        };
+        assert_eq!(
+            runtime_key(&runtime, &env),
+            runtime_key(&runtime, &HashMap::new())
+        );
+    }
 }
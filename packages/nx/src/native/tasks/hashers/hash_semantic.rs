@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use rayon::prelude::*;
+use swc_common::BytePos;
+use swc_ecma_ast::EsVersion::EsNext;
+use swc_ecma_parser::lexer::Lexer;
+use swc_ecma_parser::token::Token;
+use swc_ecma_parser::{StringInput, Syntax, TsConfig};
+use tracing::{debug, debug_span, trace};
+use xxhash_rust::xxh3::Xxh3;
+
+use super::hash_json::{hash_sorted_json, parse_json_or_jsonc};
+use super::once_cache::OnceCache;
+use crate::native::glob::build_glob_set;
+use crate::native::tasks::hashers::hash_project_files::collect_project_files;
+use crate::native::tasks::hashers::hash_workspace_files::globs_from_workspace_globs;
+use crate::native::tasks::hashers::instruction_cache_key;
+use crate::native::types::FileData;
+
+/// Compute-once cache for semantic file sets, holding the hash and the
+/// matched files. Per invocation: the canonical forms are read from disk.
+pub(crate) type SemanticFileSetCache = OnceCache<(String, Vec<String>)>;
+
+/// How the canonical form of a file is derived from its contents.
+enum CanonicalForm {
+    /// The parsed value, with object keys sorted.
+    Json,
+    /// The tokens, without comments or whitespace.
+    Script { tsx: bool, dts: bool },
+}
+
+impl CanonicalForm {
+    fn of(file: &str) -> Option<Self> {
+        let extension = Path::new(file).extension()?.to_str()?;
+        match extension {
+            "json" => Some(CanonicalForm::Json),
+            // Type assertions like `<T>value` only exist in TypeScript, so
+            // every other script can be lexed with JSX enabled.
+            "ts" | "mts" | "cts" => Some(CanonicalForm::Script {
+                tsx: false,
+                dts: file.ends_with(".d.ts"),
+            }),
+            "tsx" | "js" | "jsx" | "mjs" | "cjs" => Some(CanonicalForm::Script {
+                tsx: true,
+                dts: false,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The files a SemanticFileSet instruction hashes. `project_name` is `Some`
+/// for `{projectRoot}` file sets (matched against `project_file_map`) and
+/// `None` for `{workspaceRoot}` ones (matched against `all_workspace_files`).
+pub fn collect_semantic_files<'a>(
+    project_name: Option<&str>,
+    file_sets: &[String],
+    project_file_map: &'a HashMap<String, Vec<FileData>>,
+    all_workspace_files: &'a [FileData],
+) -> Result<Vec<&'a FileData>> {
+    if let Some(project_name) = project_name {
+        return collect_project_files(project_name, file_sets, project_file_map);
+    }
+    let globs = globs_from_workspace_globs(file_sets);
+    if globs.is_empty() {
+        return Ok(vec![]);
+    }
+    let glob_set = build_glob_set(&globs)?;
+    Ok(all_workspace_files
+        .iter()
+        .filter(|file| glob_set.is_match(&file.file))
+        .collect())
+}
+
+/// Hashes `files` by their canonical forms, so that reformatting them or
+/// editing their comments doesn't change the hash. JSON files are hashed
+/// like JSON inputs, TS and JS files by their tokens. Other files, and files
+/// that fail to parse, are hashed by their contents.
+pub fn hash_semantic_files(workspace_root: &str, files: &[&FileData]) -> String {
+    debug_span!("Hashing semantic fileset").in_scope(|| {
+        let file_hashes = files
+            .par_iter()
+            .map(|file| semantic_file_hash(workspace_root, file))
+            .collect::<Vec<_>>();
+
+        let mut hasher = Xxh3::new();
+        for (file, file_hash) in files.iter().zip(file_hashes) {
+            debug!("Adding {:?} ({:?}) to hash", file_hash, file.file);
+            hasher.update(file.file.as_bytes());
+            hasher.update(file_hash.as_bytes());
+        }
+        hasher.digest().to_string()
+    })
+}
+
+/// Key of a semantic file set: the instruction and the content hashes of
+/// the files it reads.
+pub fn semantic_file_set_key(instruction: &str, files: &[&FileData]) -> String {
+    let mut hasher = Xxh3::new();
+    hasher.update(instruction.as_bytes());
+    for file in files {
+        hasher.update(b"\0");
+        hasher.update(file.file.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(file.hash.as_bytes());
+    }
+    instruction_cache_key("semantic", hasher.digest())
+}
+
+fn semantic_file_hash(workspace_root: &str, file: &FileData) -> String {
+    let Some(form) = CanonicalForm::of(&file.file) else {
+        return file.hash.clone();
+    };
+    let path = Path::new(workspace_root).join(&file.file);
+    let Ok(bytes) =
+        std::fs::read(&path).inspect_err(|e| trace!("Failed to read {:?}: {}", path, e))
+    else {
+        return file.hash.clone();
+    };
+
+    let canonical = match form {
+        CanonicalForm::Json => hash_canonical_json(&bytes),
+        CanonicalForm::Script { tsx, dts } => hash_script_tokens(&bytes, tsx, dts),
+    };
+    canonical.unwrap_or_else(|| {
+        trace!("Failed to parse {}, hashing its contents", file.file);
+        file.hash.clone()
+    })
+}
+
+fn hash_canonical_json(bytes: &[u8]) -> Option<String> {
+    let value = parse_json_or_jsonc(bytes)?;
+    let mut hasher = Xxh3::new();
+    hash_sorted_json(&mut hasher, &value);
+    Some(hasher.digest().to_string())
+}
+
+/// Hashes the tokens of a TS or JS file. The lexer is not given comments to
+/// collect, so they are dropped along with whitespace, pragmas like
+/// `@ts-ignore` or `/*#__PURE__*/` included. String literals are hashed by
+/// their value, so switching quotes doesn't change the hash. Whether a line
+/// break precedes a token is hashed with it, since automatic semicolon
+/// insertion makes `return\nx` differ from `return x`.
+fn hash_script_tokens(bytes: &[u8], tsx: bool, dts: bool) -> Option<String> {
+    let source = std::str::from_utf8(bytes).ok()?;
+    let source_len = u32::try_from(source.len()).ok()?;
+    let lexer = Lexer::new(
+        Syntax::Typescript(TsConfig {
+            tsx,
+            decorators: false,
+            dts,
+            no_early_errors: true,
+            disallow_ambiguous_jsx_like: false,
+        }),
+        EsNext,
+        StringInput::new(source, BytePos(0), BytePos(source_len)),
+        None,
+    );
+
+    let mut hasher = Xxh3::new();
+    for (index, token) in lexer.enumerate() {
+        let text = match &token.token {
+            Token::Error(e) => {
+                trace!("Failed to lex: {:?}", e);
+                return None;
+            }
+            Token::Str { value, .. } => &**value,
+            _ => source.get(token.span.lo.0 as usize..token.span.hi.0 as usize)?,
+        };
+        // Comments before the first token may or may not end in a line break.
+        hasher.update(&[u8::from(index > 0 && token.had_line_break)]);
+        // Length-prefixed, so that tokens can't run into each other.
+        hasher.update(&(text.len() as u64).to_le_bytes());
+        hasher.update(text.as_bytes());
+    }
+    Some(hasher.digest().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash_file(file: &str, content: &str) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(file), content).unwrap();
+        let file = FileData {
+            file: file.into(),
+            hash: content.len().to_string(),
+        };
+        hash_semantic_files(dir.path().to_str().unwrap(), &[&file])
+    }
+
+    #[test]
+    fn should_ignore_formatting_and_comments_in_scripts() {
+        let original = hash_file(
+            "index.ts",
+            "import {a} from './a';\nexport const b = a+'b';",
+        );
+
+        let reformatted = hash_file(
+            "index.ts",
+            "// Adds b\nimport { a } from \"./a\";\n\nexport const b = a + \"b\"; /* done */\n",
+        );
+        assert_eq!(reformatted, original);
+
+        let changed = hash_file(
+            "index.ts",
+            "import {a} from './a';\nexport const b = a+'c';",
+        );
+        assert_ne!(changed, original);
+    }
+
+    #[test]
+    fn should_hash_line_breaks_that_semicolons_depend_on() {
+        assert_ne!(
+            hash_file("index.js", "function f() { return\nx }"),
+            hash_file("index.js", "function f() { return x }")
+        );
+        assert_ne!(
+            hash_file("index.js", "a\n(b)"),
+            hash_file("index.js", "a;(b)")
+        );
+        assert_ne!(
+            hash_file("index.js", "a\n++b"),
+            hash_file("index.js", "a++\nb")
+        );
+    }
+
+    #[test]
+    fn should_lex_jsx_in_js_files() {
+        let original = hash_file("app.jsx", "const app = <App title='a' />;");
+
+        let reformatted = hash_file("app.jsx", "const app = <App  title=\"a\"/>;");
+        assert_eq!(reformatted, original);
+        assert_ne!(
+            hash_file("app.jsx", "const app = <App title='b' />;"),
+            original
+        );
+    }
+
+    #[test]
+    fn should_ignore_formatting_and_key_order_in_json() {
+        let original = hash_file("package.json", r#"{"name":"a","version":"1.0.0"}"#);
+        let reformatted = hash_file(
+            "package.json",
+            "{\n  \"version\": \"1.0.0\",\n  \"name\": \"a\"\n}\n",
+        );
+
+        assert_eq!(reformatted, original);
+        assert_ne!(
+            hash_file("package.json", r#"{"name":"a","version":"1.0.1"}"#),
+            original
+        );
+    }
+
+    #[test]
+    fn should_hash_other_and_invalid_files_by_their_contents() {
+        assert_ne!(hash_file("a.css", "a {}"), hash_file("a.css", "a  {}"));
+        assert_ne!(
+            hash_file("a.json", "{\"a\": "),
+            hash_file("a.json", "{ \"a\": ")
+        );
+    }
+}
@@ -1,10 +1,12 @@
 use crate::native::cache::expand_outputs::get_files_for_outputs;
 use crate::native::glob::build_glob_set;
 use crate::native::hasher::hash_file;
+use crate::native::tasks::hashers::instruction_cache_key;
 use anyhow::*;
 use dashmap::DashMap;
 use rayon::prelude::*;
 use std::path::Path;
+use std::time::UNIX_EPOCH;
 use tracing::trace;
 use xxhash_rust::xxh3;
 
@@ -35,6 +37,52 @@ pub fn resolve_task_output_files(
         .collect())
 }
 
+/// Whether task output hashes are kept across runs, keyed by `task_output_key`.
+/// Off unless `NX_PERSIST_TASK_OUTPUT_HASHES` is `true`: the key trusts file
+/// sizes and modification times, which tools like `cp -p`, `rsync -t` or
+/// `tar` restore along with different contents.
+pub fn persist_task_output_hashes() -> bool {
+    std::env::var("NX_PERSIST_TASK_OUTPUT_HASHES").unwrap_or_default() == "true"
+}
+
+/// Key of task outputs within a run.
+pub fn task_output_cache_key(glob: &str, outputs: &[String]) -> String {
+    format!("{}|{}", glob, outputs.join("|"))
+}
+
+/// Key of task outputs across runs: the outputs and glob, and the size and
+/// modification time of the matching files, so that outputs aren't read to
+/// be keyed. Only used when `persist_task_output_hashes` is set.
+pub fn task_output_key(workspace_root: &str, glob: &str, outputs: &[String]) -> Result<String> {
+    let mut files = resolve_task_output_files(workspace_root, glob, outputs)?;
+    files.sort();
+
+    let mut hasher = xxh3::Xxh3::new();
+    hasher.update(workspace_root.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(glob.as_bytes());
+    for output in outputs {
+        hasher.update(b"\0");
+        hasher.update(output.as_bytes());
+    }
+    for file in files {
+        let Ok(metadata) = Path::new(workspace_root).join(&file).metadata() else {
+            continue;
+        };
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_nanos())
+            .unwrap_or_default();
+        hasher.update(b"\0");
+        hasher.update(file.as_bytes());
+        hasher.update(&metadata.len().to_le_bytes());
+        hasher.update(&modified.to_le_bytes());
+    }
+    Ok(instruction_cache_key("output", hasher.digest()))
+}
+
 pub fn hash_task_output(
     workspace_root: &str,
     glob: &str,
@@ -42,7 +90,7 @@ pub fn hash_task_output(
     cache: &DashMap<String, CachedTaskOutput>,
 ) -> Result<TaskOutputHashResult> {
     // Create cache key from glob pattern and outputs
-    let cache_key = format!("{}|{}", glob, outputs.join("|"));
+    let cache_key = task_output_cache_key(glob, outputs);
 
     // Check cache first
     if let Some(cached) = cache.get(&cache_key) {
@@ -103,3 +151,28 @@ pub fn hash_task_output(
         files,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn test_task_output_key_changes_with_file_metadata() {
+        let temp = TempDir::new().unwrap();
+        temp.child("dist/main.js").write_str("one").unwrap();
+        let root = temp.display().to_string();
+        let outputs = vec!["dist".to_string()];
+
+        let before = task_output_key(&root, "**/*", &outputs).unwrap();
+        assert_eq!(before, task_output_key(&root, "**/*", &outputs).unwrap());
+
+        temp.child("dist/main.js").write_str("three").unwrap();
+        assert_ne!(before, task_output_key(&root, "**/*", &outputs).unwrap());
+        assert_ne!(
+            before,
+            task_output_key(&root, "**/*.css", &outputs).unwrap()
+        );
+    }
+}
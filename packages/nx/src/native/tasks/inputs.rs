@@ -1,4 +1,5 @@
 use crate::native::project_graph::types::{Project, ProjectGraph};
+use crate::native::tasks::hashers::GIT_HEAD;
 use crate::native::tasks::types::Task;
 use crate::native::types::{Input, NxJson};
 use std::collections::HashMap;
@@ -69,16 +70,19 @@ pub(super) fn get_inputs_for_dependency<'a>(
         Input::FileSet {
             fileset,
             dependencies: true,
+            semantic,
         } => {
             // For dependency filesets, we apply the same fileset to the dependency
             // and continue recursively with the same pattern
             let self_inputs = vec![Input::FileSet {
                 fileset,
                 dependencies: false,
+                semantic: *semantic,
             }];
             let deps_inputs = vec![Input::FileSet {
                 fileset,
                 dependencies: true,
+                semantic: *semantic,
             }];
 
             Ok(Some(SplitInputs {
@@ -101,6 +105,7 @@ fn split_inputs_into_self_and_deps<'a>(
             Input::FileSet {
                 fileset: "{projectRoot}/**/*",
                 dependencies: false,
+                semantic: false,
             },
             Input::Inputs {
                 input: "default",
@@ -140,7 +145,8 @@ fn split_inputs_into_self_and_deps<'a>(
                 | Input::DepsOutputs { .. }
                 | Input::ExternalDependency(_)
                 | Input::WorkingDirectory(_)
-                | Input::Json { .. } => {
+                | Input::Json { .. }
+                | Input::Git { .. } => {
                     acc.1.push(input);
                 }
                 Input::Projects { .. } => {
@@ -186,6 +192,7 @@ pub(super) fn expand_single_project_inputs<'a>(
                     expanded.push(Input::FileSet {
                         fileset: s,
                         dependencies: false,
+                        semantic: false,
                     });
                 }
             }
@@ -196,11 +203,13 @@ pub(super) fn expand_single_project_inputs<'a>(
             Input::FileSet {
                 fileset,
                 dependencies: false,
+                semantic,
             } => {
                 validate_file_set(fileset)?;
                 expanded.push(Input::FileSet {
                     fileset,
                     dependencies: false,
+                    semantic: *semantic,
                 });
             }
             Input::Runtime(runtime) => expanded.push(Input::Runtime(runtime)),
@@ -218,16 +227,36 @@ pub(super) fn expand_single_project_inputs<'a>(
             Input::WorkingDirectory(mode) => expanded.push(Input::WorkingDirectory(mode)),
             Input::Json {
                 json,
+                format,
                 fields,
                 exclude_fields,
             } => {
                 validate_file_set(json)?;
                 expanded.push(Input::Json {
                     json,
+                    format: *format,
                     fields: *fields,
                     exclude_fields: *exclude_fields,
                 });
             }
+            Input::Git { git, dirty } => {
+                if *git != GIT_HEAD {
+                    validate_file_set(git)?;
+                    // A commit touching everything but some files is rarely
+                    // what a release depends on, so negations aren't supported.
+                    if git.starts_with('!') {
+                        anyhow::bail!(
+                            r#""{git}" is an invalid git input.
+Git inputs take "HEAD" or a fileset to include, and can't exclude files.
+For instance: "{projectRoot}/**/*" or "{workspaceRoot}/package.json".
+"#,
+                            projectRoot = "{projectRoot}",
+                            workspaceRoot = "{workspaceRoot}",
+                        );
+                    }
+                }
+                expanded.push(Input::Git { git, dirty: *dirty });
+            }
             Input::Projects { .. }
             | Input::Inputs {
                 dependencies: true, ..
@@ -288,6 +317,7 @@ pub(super) fn get_named_inputs<'a>(
         vec![Input::FileSet {
             fileset: "{projectRoot}/**/*",
             dependencies: false,
+            semantic: false,
         }],
     );
 
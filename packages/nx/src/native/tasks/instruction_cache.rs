@@ -0,0 +1,184 @@
+use crate::native::db::connection::NxDbConnection;
+use crate::native::tasks::task_hasher::TaskHasher;
+use napi::bindgen_prelude::External;
+use rusqlite::{params, types::Value};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, trace};
+
+/// Hashes of the instructions that are expensive to compute, keyed by the
+/// instruction and everything its hash is derived from.
+pub const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS instruction_hashes (
+    key    TEXT PRIMARY KEY NOT NULL,
+    hash  TEXT NOT NULL,
+    files  TEXT NOT NULL,
+    accessed_at  TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS instruction_hashes_accessed_at_idx ON instruction_hashes (accessed_at);";
+
+/// How long an unused entry is kept, as an SQLite date modifier.
+const RETENTION: &str = "-7 days";
+
+/// Persists the hashes of JSON file sets and semantic file sets across runs,
+/// so that repeated invocations don't parse the same files again. Task
+/// outputs are persisted with `NX_PERSIST_TASK_OUTPUT_HASHES`, and runtime
+/// inputs that set `cacheAcrossRuns`, keyed by their command and environment.
+/// Keys include `INSTRUCTION_HASH_VERSION`, so hashes of an older Nx are
+/// recomputed rather than reused.
+///
+/// Lookups go to the DB; new hashes are buffered and written by `flush`
+/// in a single transaction.
+pub(crate) struct InstructionHashCache {
+    db: Arc<Mutex<NxDbConnection>>,
+    computed: Mutex<Vec<(String, String, Vec<String>)>>,
+    hits: Mutex<Vec<String>>,
+    pruned: AtomicBool,
+}
+
+impl InstructionHashCache {
+    pub(crate) fn new(db: Arc<Mutex<NxDbConnection>>) -> Self {
+        Self {
+            db,
+            computed: Mutex::new(vec![]),
+            hits: Mutex::new(vec![]),
+            pruned: AtomicBool::new(false),
+        }
+    }
+
+    /// The hash and files stored under `key`, or those returned by `compute`.
+    /// The cache is best effort: failing to read it falls back to `compute`.
+    pub(crate) fn get_or_compute(
+        &self,
+        key: String,
+        compute: impl FnOnce() -> anyhow::Result<(String, Vec<String>)>,
+    ) -> anyhow::Result<(String, Vec<String>)> {
+        let cached = self
+            .db
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT hash, files FROM instruction_hashes WHERE key = ?1",
+                [&key],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            )
+            .unwrap_or_else(|e| {
+                debug!("Unable to read the instruction hash cache: {:?}", e);
+                None
+            });
+        if let Some((hash, files)) = cached {
+            if let Ok(files) = serde_json::from_str(&files) {
+                trace!("Instruction hash cache HIT for {}", key);
+                self.hits.lock().unwrap().push(key);
+                return Ok((hash, files));
+            }
+        }
+
+        trace!("Instruction hash cache MISS for {}", key);
+        let (hash, files) = compute()?;
+        self.computed
+            .lock()
+            .unwrap()
+            .push((key, hash.clone(), files.clone()));
+        Ok((hash, files))
+    }
+
+    /// Store the hashes computed since the last flush, and mark the entries
+    /// that were hit as used.
+    pub(crate) fn flush(&self) -> anyhow::Result<()> {
+        let computed = std::mem::take(&mut *self.computed.lock().unwrap())
+            .into_iter()
+            .map(|(key, hash, files)| -> anyhow::Result<_> {
+                Ok((key, hash, serde_json::to_string(&files)?))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let hits = Rc::new(
+            std::mem::take(&mut *self.hits.lock().unwrap())
+                .into_iter()
+                .map(Value::from)
+                .collect::<Vec<Value>>(),
+        );
+
+        let mut db = self.db.lock().unwrap();
+        if !self.pruned.swap(true, Ordering::Relaxed) {
+            db.execute(
+                "DELETE FROM instruction_hashes WHERE accessed_at < datetime('now', ?1)",
+                [RETENTION],
+            )?;
+        }
+        if computed.is_empty() && hits.is_empty() {
+            return Ok(());
+        }
+
+        trace!(
+            "Storing {} instruction hashes, {} were reused",
+            computed.len(),
+            hits.len()
+        );
+        db.transaction(|conn| {
+            let mut stmt = conn.prepare(
+                "INSERT OR REPLACE INTO instruction_hashes (key, hash, files) VALUES (?1, ?2, ?3)",
+            )?;
+            for (key, hash, files) in computed.iter() {
+                stmt.execute(params![key, hash, files])?;
+            }
+            conn.execute(
+                "UPDATE instruction_hashes SET accessed_at = CURRENT_TIMESTAMP WHERE key IN rarray(?1)",
+                [hits.clone()],
+            )?;
+            Ok(())
+        })
+    }
+}
+
+#[napi]
+impl TaskHasher {
+    /// Keep the hashes of JSON file sets and semantic file sets in `db`, so
+    /// that later runs only recompute them when their inputs change. Task
+    /// outputs and runtime inputs are only kept when opted in.
+    #[napi]
+    pub fn cache_instruction_hashes(
+        &mut self,
+        #[napi(ts_arg_type = "ExternalObject<NxDbConnection>")] db: &External<
+            Arc<Mutex<NxDbConnection>>,
+        >,
+    ) {
+        self.instruction_cache = Some(InstructionHashCache::new(Arc::clone(db)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::native::db::initialize::initialize_db;
+    use assert_fs::TempDir;
+    use std::cell::Cell;
+
+    fn compute(calls: &Cell<u32>, hash: &str) -> anyhow::Result<(String, Vec<String>)> {
+        calls.set(calls.get() + 1);
+        Ok((hash.to_string(), vec!["a.json".to_string()]))
+    }
+
+    #[test]
+    fn should_reuse_hashes_across_caches() {
+        let temp = TempDir::new().unwrap();
+        let db = Arc::new(Mutex::new(initialize_db(&temp.join("test.db")).unwrap()));
+        let calls = Cell::new(0);
+
+        let first_run = InstructionHashCache::new(Arc::clone(&db));
+        first_run
+            .get_or_compute("key".into(), || compute(&calls, "123"))
+            .unwrap();
+        first_run.flush().unwrap();
+
+        let second_run = InstructionHashCache::new(Arc::clone(&db));
+        let result = second_run
+            .get_or_compute("key".into(), || compute(&calls, "456"))
+            .unwrap();
+        assert_eq!(result, ("123".to_string(), vec!["a.json".to_string()]));
+        second_run
+            .get_or_compute("other".into(), || compute(&calls, "456"))
+            .unwrap();
+        assert_eq!(calls.get(), 2);
+    }
+}
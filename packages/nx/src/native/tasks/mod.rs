@@ -10,6 +10,10 @@ mod utils;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod details;
 #[cfg(not(target_arch = "wasm32"))]
+pub mod hash_details;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod instruction_cache;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod running_tasks_service;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod task_history;
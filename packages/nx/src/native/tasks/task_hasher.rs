@@ -12,15 +12,20 @@ use crate::native::{
 };
 use crate::native::{
     project_graph::utils::ProjectRootMappings,
-    tasks::hashers::{hash_cwd, hash_env, hash_runtime},
+    tasks::hashers::{
+        GIT_HEAD, hash_cwd, hash_env, hash_git, hash_runtime, matching_env_names, runtime_key,
+    },
 };
 use crate::native::{
     tasks::hashers::{
         CachedTaskOutput, JsonHashResult, ProjectFileIndicesCache, ProjectFileSetCache,
-        WorkspaceFileIndicesCache, WorkspaceFileSetCache, collect_project_file_paths_cached,
-        collect_workspace_file_paths_cached, hash_all_externals, hash_external, hash_json_files,
-        hash_project_config, hash_project_files_cached, hash_task_output,
-        hash_tsconfig_selectively, hash_workspace_files_cached,
+        SemanticFileSetCache, WorkspaceFileIndicesCache, WorkspaceFileSetCache,
+        collect_json_input_file_data, collect_project_file_paths_cached, collect_semantic_files,
+        collect_workspace_file_paths_cached, hash_all_externals, hash_external,
+        hash_project_config, hash_project_files_cached, hash_semantic_files, hash_structured_files,
+        hash_task_output, hash_tsconfig_selectively, hash_workspace_files_cached,
+        instruction_cache_key, json_file_set_key, persist_task_output_hashes,
+        semantic_file_set_key, task_output_cache_key, task_output_key,
     },
     types::FileData,
     workspace::types::ProjectFiles,
@@ -31,6 +36,12 @@ use once_cell::sync::OnceCell;
 use rayon::prelude::*;
 use tracing::{debug, trace, trace_span};
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::native::tasks::{
+    hash_details::HashDetailsRecorder, hash_plan_inspector::HashPlanInspector,
+    instruction_cache::InstructionHashCache,
+};
+
 /// NAPI-compatible struct for returning hash inputs to JavaScript
 #[napi(object)]
 #[derive(Debug, Default, Clone)]
@@ -45,6 +56,8 @@ pub struct HashInputs {
     pub dep_outputs: Vec<String>,
     /// External dependencies
     pub external: Vec<String>,
+    /// Git state: `HEAD`, or the filesets whose last commit is hashed
+    pub git: Vec<String>,
 }
 
 /// Internal builder that uses HashSet for O(1) deduplication during accumulation.
@@ -56,6 +69,7 @@ pub(crate) struct HashInputsBuilder {
     pub(crate) environment: HashSet<String>,
     pub(crate) dep_outputs: HashSet<String>,
     pub(crate) external: HashSet<String>,
+    pub(crate) git: HashSet<String>,
 }
 
 impl HashInputsBuilder {
@@ -66,6 +80,7 @@ impl HashInputsBuilder {
         self.environment.extend(other.environment);
         self.dep_outputs.extend(other.dep_outputs);
         self.external.extend(other.external);
+        self.git.extend(other.git);
     }
 }
 
@@ -79,7 +94,7 @@ impl From<&HashInstruction> for HashInputsBuilder {
     fn from(instruction: &HashInstruction) -> Self {
         match instruction {
             HashInstruction::Runtime(runtime) => HashInputsBuilder {
-                runtime: HashSet::from([runtime.clone()]),
+                runtime: HashSet::from([runtime.command.clone()]),
                 ..Default::default()
             },
             HashInstruction::Environment(env) => HashInputsBuilder {
@@ -94,6 +109,10 @@ impl From<&HashInstruction> for HashInputsBuilder {
                 external: HashSet::from(["AllExternalDependencies".to_string()]),
                 ..Default::default()
             },
+            HashInstruction::Git(git) => HashInputsBuilder {
+                git: HashSet::from([git.path.clone().unwrap_or_else(|| GIT_HEAD.to_string())]),
+                ..Default::default()
+            },
             HashInstruction::ProjectConfiguration(_) | HashInstruction::Cwd(_) => {
                 HashInputsBuilder::default()
             }
@@ -123,6 +142,7 @@ impl From<HashInputsBuilder> for HashInputs {
             environment: to_sorted_vec(builder.environment),
             dep_outputs: to_sorted_vec(builder.dep_outputs),
             external: to_sorted_vec(builder.external),
+            git: to_sorted_vec(builder.git),
         }
     }
 }
@@ -201,6 +221,13 @@ pub struct TaskHasher {
     project_file_indices_cache: ProjectFileIndicesCache,
     // Fold over all externals; identical for every task, so computed once.
     all_externals_hash: OnceCell<String>,
+    // Set through `record_details`; persists the details of computed hashes.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) details_recorder: Option<HashDetailsRecorder>,
+    // Set through `cache_instruction_hashes`; persists expensive instruction
+    // hashes across runs.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) instruction_cache: Option<InstructionHashCache>,
 }
 #[napi]
 impl TaskHasher {
@@ -235,6 +262,10 @@ impl TaskHasher {
             workspace_file_indices_cache: WorkspaceFileIndicesCache::new(),
             project_file_indices_cache: ProjectFileIndicesCache::new(),
             all_externals_hash: OnceCell::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            details_recorder: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            instruction_cache: None,
         }
     }
 
@@ -276,10 +307,12 @@ impl TaskHasher {
         F: Fn(&str) -> &'a HashMap<String, String> + Sync,
     {
         // Per-invocation: these read live disk/exec state (task outputs, shell commands,
-        // json file contents) that can change mid-run, so they must not persist.
+        // json and semantic file contents) that can change mid-run, so they must not persist.
         let task_output_cache = DashMap::new();
         let runtime_cache: DashMap<String, String> = DashMap::new();
+        let git_cache: DashMap<String, String> = DashMap::new();
         let json_file_set_cache: DashMap<String, JsonHashResult> = DashMap::new();
+        let semantic_file_set_cache = SemanticFileSetCache::new();
         // Deduplicates env-dependent hash values (Environment, Runtime)
         // across tasks; see intern_value. Other instruction types share
         // values through per-id slots instead.
@@ -353,7 +386,9 @@ impl TaskHasher {
                     | HashInstruction::TaskOutput(_, _)
                     | HashInstruction::External(_)
                     | HashInstruction::AllExternalDependencies
-                    | HashInstruction::JsonFileSet(_) => Some(&value_slots[id as usize]),
+                    | HashInstruction::JsonFileSet(_)
+                    | HashInstruction::Git(_)
+                    | HashInstruction::SemanticFileSet(_, _) => Some(&value_slots[id as usize]),
                 };
 
                 let cached = if should_collect_inputs {
@@ -377,9 +412,11 @@ impl TaskHasher {
                                 selectively_hash_tsconfig,
                                 task_output_cache: &task_output_cache,
                                 runtime_cache: &runtime_cache,
+                                git_cache: &git_cache,
                                 project_file_set_cache: &self.project_file_set_cache,
                                 workspace_file_set_cache: &self.workspace_file_set_cache,
                                 json_file_set_cache: &json_file_set_cache,
+                                semantic_file_set_cache: &semantic_file_set_cache,
                                 cwd: cwd_path,
                                 collect_inputs: should_collect_inputs,
                             },
@@ -449,6 +486,32 @@ impl TaskHasher {
             assemble_duration
         );
 
+        // Recording is best effort: failing to persist the details must not
+        // fail hashing.
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(recorder) = &self.details_recorder {
+            let inspector = HashPlanInspector::from_parts(
+                Arc::clone(&self.all_workspace_files),
+                Arc::clone(&self.project_file_map),
+                self.workspace_root.clone(),
+            );
+            if let Err(e) = recorder.record(
+                hash_plans,
+                &hashes,
+                &inspector,
+                &self.project_file_indices_cache,
+                &self.all_workspace_files,
+            ) {
+                debug!("Unable to record hash details: {:?}", e);
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(cache) = &self.instruction_cache {
+            if let Err(e) = cache.flush() {
+                debug!("Unable to store instruction hashes: {:?}", e);
+            }
+        }
+
         Ok(TaskHashes(hashes))
     }
 
@@ -464,9 +527,11 @@ impl TaskHasher {
             selectively_hash_tsconfig,
             task_output_cache,
             runtime_cache,
+            git_cache,
             project_file_set_cache,
             workspace_file_set_cache,
             json_file_set_cache,
+            semantic_file_set_cache,
             cwd,
             collect_inputs,
         }: HashInstructionArgs,
@@ -498,8 +563,32 @@ impl TaskHasher {
                 ((*hashed).clone(), inputs)
             }
             HashInstruction::Runtime(runtime) => {
-                let hashed_runtime =
-                    hash_runtime(&self.workspace_root, runtime, js_env, runtime_cache)?;
+                let cache_key = runtime_key(runtime, js_env);
+                let cached = runtime_cache.get(&cache_key).map(|hashed| hashed.clone());
+                let hashed_runtime = match cached {
+                    Some(hashed) => Ok(hashed),
+                    None if runtime.cache_across_runs => self
+                        .with_instruction_cache(
+                            || Ok(instruction_cache_key("runtime", hash(cache_key.as_bytes()))),
+                            || {
+                                hash_runtime(&self.workspace_root, runtime, js_env, runtime_cache)
+                                    .map(|hashed| (hashed, vec![]))
+                            },
+                        )
+                        .map(|(hashed, _)| {
+                            runtime_cache.insert(cache_key, hashed.clone());
+                            hashed
+                        }),
+                    None => hash_runtime(&self.workspace_root, runtime, js_env, runtime_cache),
+                }
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "Unable to hash the runtime input '{}' of task {}: {}",
+                        runtime.command,
+                        task_id,
+                        e
+                    )
+                })?;
                 trace!(parent: &span, "hash_runtime: {:?}", now.elapsed());
                 let inputs = if collect_inputs {
                     instruction.into()
@@ -508,11 +597,31 @@ impl TaskHasher {
                 };
                 (hashed_runtime, inputs)
             }
+            HashInstruction::Git(git) => {
+                let hashed_git = hash_git(&self.workspace_root, git, git_cache).map_err(|e| {
+                    anyhow::anyhow!(
+                        "Unable to hash the git input '{}' of task {}: {}",
+                        git,
+                        task_id,
+                        e
+                    )
+                })?;
+                trace!(parent: &span, "hash_git: {:?}", now.elapsed());
+                let inputs = if collect_inputs {
+                    instruction.into()
+                } else {
+                    empty
+                };
+                (hashed_git, inputs)
+            }
             HashInstruction::Environment(env) => {
                 let hashed_env = hash_env(env, js_env);
                 trace!(parent: &span, "hash_env: {:?}", now.elapsed());
                 let inputs = if collect_inputs {
-                    instruction.into()
+                    HashInputsBuilder {
+                        environment: matching_env_names(env, js_env).into_iter().collect(),
+                        ..Default::default()
+                    }
                 } else {
                     empty
                 };
@@ -548,6 +657,36 @@ impl TaskHasher {
                 };
                 ((*hashed).clone(), inputs)
             }
+            HashInstruction::SemanticFileSet(project_name, file_sets) => {
+                // Keyed on the instruction, like JSON file sets: the project
+                // and file sets are part of its Display string.
+                let cache_key = instruction.to_string();
+                let hashed = semantic_file_set_cache.get_or_try_init(cache_key.clone(), || {
+                    let files = collect_semantic_files(
+                        project_name.as_deref(),
+                        file_sets,
+                        &self.project_file_map,
+                        &self.all_workspace_files,
+                    )?;
+                    self.with_instruction_cache(
+                        || Ok(semantic_file_set_key(&cache_key, &files)),
+                        || {
+                            let hash = hash_semantic_files(&self.workspace_root, &files);
+                            Ok((hash, files.iter().map(|f| f.file.clone()).collect()))
+                        },
+                    )
+                })?;
+                trace!(parent: &span, "hash_semantic_files: {:?}", now.elapsed());
+                let inputs = if collect_inputs {
+                    HashInputsBuilder {
+                        files: hashed.1.iter().cloned().collect(),
+                        ..Default::default()
+                    }
+                } else {
+                    empty
+                };
+                (hashed.0.clone(), inputs)
+            }
             HashInstruction::ProjectConfiguration(project_name) => {
                 let hashed_project_config =
                     hash_project_config(project_name, &self.project_graph.nodes)?;
@@ -608,19 +747,46 @@ impl TaskHasher {
                 (ts_hash, inputs)
             }
             HashInstruction::TaskOutput(glob, outputs) => {
-                let result =
-                    hash_task_output(&self.workspace_root, glob, outputs, task_output_cache)?;
+                let cache_key = task_output_cache_key(glob, outputs);
+                // Clone out of the map before inserting into it.
+                let cached = task_output_cache
+                    .get(&cache_key)
+                    .map(|cached| (cached.hash.clone(), cached.files.clone()));
+                let (hash, files) = match cached {
+                    Some(cached) => cached,
+                    None => {
+                        let compute = || {
+                            hash_task_output(&self.workspace_root, glob, outputs, task_output_cache)
+                                .map(|result| (result.hash, result.files))
+                        };
+                        let (hash, files) = if persist_task_output_hashes() {
+                            self.with_instruction_cache(
+                                || task_output_key(&self.workspace_root, glob, outputs),
+                                compute,
+                            )?
+                        } else {
+                            compute()?
+                        };
+                        task_output_cache
+                            .entry(cache_key)
+                            .or_insert_with(|| CachedTaskOutput {
+                                hash: hash.clone(),
+                                files: files.clone(),
+                            });
+                        (hash, files)
+                    }
+                };
                 trace!(parent: &span, "hash_task_output: {:?}", now.elapsed());
                 let inputs = if collect_inputs {
                     HashInputsBuilder {
-                        dep_outputs: result.files.into_iter().collect(),
+                        dep_outputs: files.into_iter().collect(),
                         ..Default::default()
                     }
                 } else {
-                    drop(result.files);
+                    drop(files);
                     empty
                 };
-                (result.hash, inputs)
+                (hash, inputs)
             }
             HashInstruction::External(external) => {
                 let hashed_external = hash_external(
@@ -661,23 +827,39 @@ impl TaskHasher {
                 // Cache is keyed on the full instruction string so
                 // different fields/excludeFields against the same file
                 // remain distinct. `instruction.to_string()` already
-                // encodes (project_name, json_path, fields, exclude_fields)
-                // via the Display impl — see types.rs.
+                // encodes (project_name, format, json_path, fields,
+                // exclude_fields) via the Display impl — see types.rs.
                 let cache_key = instruction.to_string();
                 // Clone the cached entry and drop the Ref before any
                 // subsequent insert to avoid deadlocking DashMap.
                 let cached_entry = if let Some(entry) = json_file_set_cache.get(&cache_key) {
                     entry.clone()
                 } else {
-                    let result = hash_json_files(
-                        &self.workspace_root,
-                        &json.json_path,
-                        json.project_name.as_deref(),
-                        json.fields.as_deref(),
-                        json.exclude_fields.as_deref(),
-                        &self.project_file_map,
-                        &self.all_workspace_files,
+                    let (hash, files) = self.with_instruction_cache(
+                        || {
+                            let files = collect_json_input_file_data(
+                                &json.json_path,
+                                json.project_name.as_deref(),
+                                &self.project_file_map,
+                                &self.all_workspace_files,
+                            )?;
+                            Ok(json_file_set_key(&cache_key, &files))
+                        },
+                        || {
+                            hash_structured_files(
+                                &self.workspace_root,
+                                &json.json_path,
+                                json.format,
+                                json.project_name.as_deref(),
+                                json.fields.as_deref(),
+                                json.exclude_fields.as_deref(),
+                                &self.project_file_map,
+                                &self.all_workspace_files,
+                            )
+                            .map(|result| (result.hash, result.files))
+                        },
                     )?;
+                    let result = JsonHashResult { hash, files };
                     json_file_set_cache.insert(cache_key, result.clone());
                     result
                 };
@@ -697,6 +879,31 @@ impl TaskHasher {
     }
 }
 
+impl TaskHasher {
+    /// Look the hash up in the persistent instruction cache under `key`,
+    /// before falling back to `compute`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn with_instruction_cache(
+        &self,
+        key: impl FnOnce() -> anyhow::Result<String>,
+        compute: impl FnOnce() -> anyhow::Result<(String, Vec<String>)>,
+    ) -> anyhow::Result<(String, Vec<String>)> {
+        match &self.instruction_cache {
+            Some(cache) => cache.get_or_compute(key()?, compute),
+            None => compute(),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn with_instruction_cache(
+        &self,
+        _key: impl FnOnce() -> anyhow::Result<String>,
+        compute: impl FnOnce() -> anyhow::Result<(String, Vec<String>)>,
+    ) -> anyhow::Result<(String, Vec<String>)> {
+        compute()
+    }
+}
+
 struct HashInstructionArgs<'a> {
     js_env: &'a HashMap<String, String>,
     ts_config_hash: &'a str,
@@ -705,9 +912,11 @@ struct HashInstructionArgs<'a> {
     selectively_hash_tsconfig: bool,
     task_output_cache: &'a DashMap<String, CachedTaskOutput>,
     runtime_cache: &'a DashMap<String, String>,
+    git_cache: &'a DashMap<String, String>,
     project_file_set_cache: &'a ProjectFileSetCache,
     workspace_file_set_cache: &'a WorkspaceFileSetCache,
     json_file_set_cache: &'a DashMap<String, JsonHashResult>,
+    semantic_file_set_cache: &'a SemanticFileSetCache,
     cwd: &'a std::path::Path,
     collect_inputs: bool,
 }
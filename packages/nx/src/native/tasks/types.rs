@@ -3,6 +3,7 @@ use std::sync::Arc;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::{collections::HashMap, fmt, ptr};
 
+use crate::native::types::{RuntimeFailurePolicy, RuntimeInput};
 use dashmap::DashMap;
 use napi::{
     bindgen_prelude::{ToNapiValue, check_status},
@@ -140,21 +141,119 @@ pub enum CwdMode {
     Relative,
 }
 
+/// The format of the files of a `JsonFileSetInput`. Every format is parsed
+/// into the same tree, so field filters and hashing are shared.
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum StructuredFormat {
+    #[default]
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl fmt::Display for StructuredFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StructuredFormat::Json => write!(f, "json"),
+            StructuredFormat::Yaml => write!(f, "yaml"),
+            StructuredFormat::Toml => write!(f, "toml"),
+        }
+    }
+}
+
 /// Payload of `HashInstruction::JsonFileSet`. Boxed in the enum: inlined, its
-/// four fields would nearly double the size of every HashInstruction in every
+/// fields would nearly double the size of every HashInstruction in every
 /// task's plan, whether or not json inputs are used.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub struct JsonFileSetInput {
     pub project_name: Option<String>,
     pub json_path: String,
+    pub format: StructuredFormat,
     pub fields: Option<Vec<String>>,
     pub exclude_fields: Option<Vec<String>>,
 }
 
+/// Payload of `HashInstruction::Runtime`: the command and how it is run.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct RuntimeCommandInput {
+    pub command: String,
+    /// Milliseconds the command may run, `None` for the default.
+    pub timeout: Option<u32>,
+    pub on_failure: RuntimeFailurePolicy,
+    pub minimal_env: bool,
+    /// Keep the hash across runs, keyed by the command and its environment.
+    pub cache_across_runs: bool,
+}
+
+impl RuntimeCommandInput {
+    /// The command run with the default options.
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            timeout: None,
+            on_failure: RuntimeFailurePolicy::default(),
+            minimal_env: false,
+            cache_across_runs: false,
+        }
+    }
+}
+
+impl From<&RuntimeInput> for RuntimeCommandInput {
+    fn from(input: &RuntimeInput) -> Self {
+        Self {
+            command: input.runtime.clone(),
+            timeout: input.timeout,
+            on_failure: input.on_failure.unwrap_or_default(),
+            minimal_env: input.minimal_env.unwrap_or(false),
+            cache_across_runs: input.cache_across_runs.unwrap_or(false),
+        }
+    }
+}
+
+/// The command followed by the options that differ from the defaults, so
+/// that runtime inputs without options keep their keys.
+impl fmt::Display for RuntimeCommandInput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.command)?;
+        if let Some(timeout) = self.timeout {
+            write!(f, "[timeout={timeout}]")?;
+        }
+        if self.on_failure != RuntimeFailurePolicy::default() {
+            write!(f, "[onFailure={:?}]", self.on_failure)?;
+        }
+        if self.minimal_env {
+            write!(f, "[minimalEnv]")?;
+        }
+        if self.cache_across_runs {
+            write!(f, "[cacheAcrossRuns]")?;
+        }
+        Ok(())
+    }
+}
+
+/// Payload of `HashInstruction::Git`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct GitStateInput {
+    /// Workspace-relative glob whose last commit is hashed, `None` for HEAD.
+    pub path: Option<String>,
+    /// Whether uncommitted changes to the files are hashed too.
+    pub dirty: bool,
+}
+
+impl fmt::Display for GitStateInput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.as_deref().unwrap_or("HEAD"))?;
+        if self.dirty {
+            write!(f, "[dirty]")?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub enum HashInstruction {
     WorkspaceFileSet(Vec<String>),
-    Runtime(String),
+    Runtime(RuntimeCommandInput),
     Environment(String),
     Cwd(CwdMode),
     ProjectFileSet(String, Vec<String>),
@@ -164,6 +263,10 @@ pub enum HashInstruction {
     External(String),
     AllExternalDependencies,
     JsonFileSet(Box<JsonFileSetInput>),
+    Git(GitStateInput),
+    /// A file set hashed by the canonical form of its files: the project
+    /// for `{projectRoot}` file sets, `None` for `{workspaceRoot}` ones.
+    SemanticFileSet(Option<String>, Vec<String>),
 }
 
 /// Append-only interner for hash instructions. Plans store `u32` ids into the
@@ -276,8 +379,14 @@ impl fmt::Display for HashInstruction {
                 }
                 HashInstruction::WorkspaceFileSet(file_set) =>
                     format!("workspace:[{}]", file_set.join(",")),
+                HashInstruction::SemanticFileSet(Some(project_name), file_set) => {
+                    format!("semantic:{project_name}:{}", file_set.join(","))
+                }
+                HashInstruction::SemanticFileSet(None, file_set) =>
+                    format!("semantic:workspace:[{}]", file_set.join(",")),
                 HashInstruction::Runtime(runtime) => format!("runtime:{}", runtime),
                 HashInstruction::Environment(env) => format!("env:{}", env),
+                HashInstruction::Git(git) => format!("git:{}", git),
                 HashInstruction::Cwd(mode) => format!("cwd:{}", mode),
                 HashInstruction::TaskOutput(task_output, dep_outputs) => {
                     let dep_outputs = dep_outputs.join(",");
@@ -306,7 +415,10 @@ impl fmt::Display for HashInstruction {
                         .as_ref()
                         .map(|f| format!("![{}]", f.join(",")))
                         .unwrap_or_default();
-                    format!("{prefix}json:{}{fields_str}{exclude_str}", json.json_path)
+                    format!(
+                        "{prefix}{}:{}{fields_str}{exclude_str}",
+                        json.format, json.json_path
+                    )
                 }
             }
         )
@@ -336,4 +448,25 @@ mod tests {
         // variant. Box large payloads instead of growing this (NXC-4604).
         assert!(std::mem::size_of::<HashInstruction>() <= 56);
     }
+
+    #[test]
+    fn runtime_display_only_lists_non_default_options() {
+        let runtime = RuntimeCommandInput::new("node -v");
+        assert_eq!(
+            HashInstruction::Runtime(runtime.clone()).to_string(),
+            "runtime:node -v"
+        );
+
+        let runtime = RuntimeCommandInput {
+            timeout: Some(500),
+            on_failure: RuntimeFailurePolicy::error,
+            minimal_env: true,
+            cache_across_runs: true,
+            ..runtime
+        };
+        assert_eq!(
+            HashInstruction::Runtime(runtime).to_string(),
+            "runtime:node -v[timeout=500][onFailure=error][minimalEnv][cacheAcrossRuns]"
+        );
+    }
 }
@@ -9,12 +9,13 @@ describe('Cache', () => {
   let cache: NxCache;
   let tempFs: TempFs;
   let taskDetails: TaskDetails;
+  let dbConnection: ReturnType<typeof getDbConnection>;
 
   const dbOutputFolder = 'temp-db-cache';
   beforeEach(() => {
     tempFs = new TempFs('cache');
 
-    const dbConnection = getDbConnection({
+    dbConnection = getDbConnection({
       directory: join(__dirname, dbOutputFolder),
       dbName: `temp-db-${randomBytes(4).toString('hex')}`,
     });
@@ -65,6 +66,96 @@ describe('Cache', () => {
     );
   });
 
+  it('should list cache entries with their task details', async () => {
+    tempFs.createFileSync('dist/output.txt', 'output contents 123');
+    cache.put('123', 'output 123', ['dist'], 0);
+    cache.get('123');
+
+    const { entries, total } = cache.listEntries({ project: 'proj' });
+
+    expect(total).toEqual(1);
+    expect(entries).toEqual([
+      expect.objectContaining({
+        hash: '123',
+        project: 'proj',
+        target: 'test',
+        configuration: 'production',
+        code: 0,
+        hits: 1,
+      }),
+    ]);
+    expect(cache.listEntries({ project: 'other' }).entries).toEqual([]);
+  });
+
+  it('should record and remove entries by provenance', async () => {
+    cache = new NxCache(
+      tempFs.tempDir,
+      join(tempFs.tempDir, '.cache'),
+      dbConnection,
+      undefined,
+      undefined,
+      undefined,
+      undefined,
+      { gitBranch: 'main', ciRunId: '1234' }
+    );
+    tempFs.createFileSync('dist/output.txt', 'output contents 123');
+    cache.put('123', 'output 123', ['dist'], 0);
+
+    expect(cache.get('123').provenance).toEqual(
+      expect.objectContaining({
+        gitBranch: 'main',
+        ciRunId: '1234',
+        origin: 'local',
+      })
+    );
+    expect(cache.removeEntries({ gitBranch: 'feature' })).toEqual([]);
+    expect(cache.removeEntries({ ciRunId: '1234' })).toEqual(['123']);
+    expect(cache.get('123')).toBeNull();
+  });
+
+  it('should leave out files over the size limit', async () => {
+    cache = new NxCache(
+      tempFs.tempDir,
+      join(tempFs.tempDir, '.cache'),
+      dbConnection,
+      undefined,
+      undefined,
+      undefined,
+      undefined,
+      undefined,
+      { maxFileSize: 100 }
+    );
+    tempFs.createFileSync('dist/output.txt', 'output contents 123');
+    tempFs.createFileSync('dist/core', 'x'.repeat(1000));
+
+    const { expandedOutputs, skippedFiles, refusedSize } = cache.put(
+      '123',
+      'output 123',
+      ['dist'],
+      0
+    );
+
+    expect(expandedOutputs).toEqual(['dist']);
+    expect(skippedFiles).toEqual([{ path: 'dist/core', size: 1000 }]);
+    expect(refusedSize).toBeUndefined();
+  });
+
+  it('should report and repair inconsistencies', async () => {
+    tempFs.createFileSync('dist/output.txt', 'output contents 123');
+    cache.put('123', 'output 123', ['dist'], 0);
+    rmSync(join(tempFs.tempDir, '.cache', '123'), {
+      recursive: true,
+      force: true,
+    });
+
+    expect(cache.fsck()).toEqual(
+      expect.objectContaining({ missingOutputs: ['123'], repaired: false })
+    );
+    expect(cache.fsck(true).repaired).toEqual(true);
+    expect(cache.get('123')).toBeNull();
+    expect(cache.fsck().missingOutputs).toEqual([]);
+  });
+
   it('should handle storing hashes that already exist in the cache', async () => {
     cache.put('123', 'output 123', ['dist'], 0);
     expect(() => cache.put('123', 'output 123', ['dist'], 0)).not.toThrow();
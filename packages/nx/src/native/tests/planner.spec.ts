@@ -527,6 +527,55 @@ describe('task planner', () => {
     expect(plans).toMatchSnapshot();
   });
 
+  it('should plan semantic filesets separately from the other filesets', async () => {
+    let projectFileMap = {
+      parent: [
+        { file: 'libs/parent/filea.ts', hash: 'a.hash' },
+        { file: 'libs/parent/filea.spec.ts', hash: 'a.spec.hash' },
+      ],
+    };
+    let builder = new ProjectGraphBuilder(undefined, projectFileMap);
+    builder.addNode({
+      name: 'parent',
+      type: 'lib',
+      data: {
+        root: 'libs/parent',
+        targets: {
+          build: {
+            inputs: [
+              '{projectRoot}/**/*',
+              { fileset: '{projectRoot}/**/*.ts', semantic: true },
+              '!{projectRoot}/**/*.spec.ts',
+            ],
+            executor: 'nx:run-commands',
+          },
+        },
+      },
+    });
+    let projectGraph = builder.getUpdatedProjectGraph();
+
+    let taskGraph = createTaskGraph(
+      projectGraph,
+      {},
+      ['parent'],
+      ['build'],
+      undefined,
+      {}
+    );
+    const planner = new HashPlanner(
+      {} as any,
+      transferProjectGraph(transformProjectGraphForRust(projectGraph))
+    );
+
+    const plans = planner.getPlans(Object.keys(taskGraph.tasks), taskGraph);
+    expect(plans['parent:build']).toEqual(
+      expect.arrayContaining([
+        'parent:libs/parent/**/*,!libs/parent/**/*.spec.ts,!libs/parent/**/*.ts',
+        'semantic:parent:libs/parent/**/*.ts,!libs/parent/**/*.spec.ts',
+      ])
+    );
+  });
+
   it('should be able to handle multiple filesets per project', async () => {
     await withEnvironmentVariables(
       { MY_TEST_HASH_ENV: 'MY_TEST_HASH_ENV_VALUE' },
@@ -1,5 +1,7 @@
 use napi::Either;
-use napi::bindgen_prelude::Either9;
+use napi::bindgen_prelude::Either12;
+
+use crate::native::tasks::types::StructuredFormat;
 
 #[napi(object)]
 pub struct InputsInput {
@@ -12,11 +14,41 @@ pub struct InputsInput {
 pub struct FileSetInput {
     pub fileset: String,
     pub dependencies: Option<bool>,
+    /// Hash a canonical form of the files instead of their contents, so that
+    /// formatting and comment changes don't change the hash. Comments that
+    /// tools act on, like `@ts-ignore` or `#__PURE__`, are ignored too.
+    pub semantic: Option<bool>,
 }
 
 #[napi(object)]
+#[derive(Debug)]
 pub struct RuntimeInput {
     pub runtime: String,
+    /// Milliseconds the command may run before hashing fails. Defaults to
+    /// 60 seconds.
+    pub timeout: Option<u32>,
+    /// What a failing command does to the hash. Defaults to `hashExitCode`.
+    pub on_failure: Option<RuntimeFailurePolicy>,
+    /// Run the command with only the environment variables needed to find
+    /// and run it, instead of the environment of the task.
+    pub minimal_env: Option<bool>,
+    /// Keep the hash of the output across runs, keyed by the command and
+    /// the environment it runs with. Defaults to `false`.
+    pub cache_across_runs: Option<bool>,
+}
+
+/// What a runtime input whose command exits with a non-zero code does to the hash.
+#[napi(string_enum)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RuntimeFailurePolicy {
+    /// Hashing fails, naming the task and the input.
+    #[allow(non_camel_case_types)]
+    error,
+    /// The exit code is hashed along with the output, so that a failing
+    /// command never hashes like a succeeding one.
+    #[default]
+    #[allow(non_camel_case_types)]
+    hashExitCode,
 }
 
 #[napi(object)]
@@ -47,7 +79,29 @@ pub struct JsonInput {
     pub exclude_fields: Option<Vec<String>>,
 }
 
-pub(crate) type JsInputs = Either9<
+#[napi(object)]
+pub struct YamlInput {
+    pub yaml: String,
+    pub fields: Option<Vec<String>>,
+    pub exclude_fields: Option<Vec<String>>,
+}
+
+#[napi(object)]
+pub struct TomlInput {
+    pub toml: String,
+    pub fields: Option<Vec<String>>,
+    pub exclude_fields: Option<Vec<String>>,
+}
+
+#[napi(object)]
+pub struct GitInput {
+    /// `HEAD`, or a fileset whose last commit is hashed.
+    pub git: String,
+    /// Also hash whether the files have uncommitted changes.
+    pub dirty: Option<bool>,
+}
+
+pub(crate) type JsInputs = Either12<
     InputsInput,
     String,
     FileSetInput,
@@ -57,12 +111,15 @@ pub(crate) type JsInputs = Either9<
     DepsOutputsInput,
     WorkingDirectoryInput,
     JsonInput,
+    GitInput,
+    YamlInput,
+    TomlInput,
 >;
 
 impl<'a> From<&'a JsInputs> for Input<'a> {
     fn from(value: &'a JsInputs) -> Self {
         match value {
-            Either9::A(inputs) => {
+            Either12::A(inputs) => {
                 if let Some(projects) = &inputs.projects {
                     Input::Projects {
                         input: &inputs.input,
@@ -78,13 +135,14 @@ impl<'a> From<&'a JsInputs> for Input<'a> {
                     }
                 }
             }
-            Either9::B(string) => {
+            Either12::B(string) => {
                 if let Some(rest) = string.strip_prefix('^') {
                     // Check if this is a dependency fileset (starts with {projectRoot} or {workspaceRoot})
                     if rest.starts_with("{projectRoot}") || rest.starts_with("{workspaceRoot}") {
                         Input::FileSet {
                             fileset: rest,
                             dependencies: true,
+                            semantic: false,
                         }
                     } else {
                         // This is a named input reference (existing behavior)
@@ -97,27 +155,45 @@ impl<'a> From<&'a JsInputs> for Input<'a> {
                     Input::String(string)
                 }
             }
-            Either9::C(file_set) => Input::FileSet {
+            Either12::C(file_set) => Input::FileSet {
                 fileset: &file_set.fileset,
                 dependencies: file_set.dependencies.unwrap_or(false),
+                semantic: file_set.semantic.unwrap_or(false),
             },
-            Either9::D(runtime) => Input::Runtime(&runtime.runtime),
-            Either9::E(environment) => Input::Environment(&environment.env),
-            Either9::F(external_dependencies) => {
+            Either12::D(runtime) => Input::Runtime(runtime),
+            Either12::E(environment) => Input::Environment(&environment.env),
+            Either12::F(external_dependencies) => {
                 Input::ExternalDependency(&external_dependencies.external_dependencies)
             }
-            Either9::G(deps_outputs) => Input::DepsOutputs {
+            Either12::G(deps_outputs) => Input::DepsOutputs {
                 transitive: deps_outputs.transitive.unwrap_or(false),
                 dependent_tasks_output_files: &deps_outputs.dependent_tasks_output_files,
             },
-            Either9::H(working_directory) => {
+            Either12::H(working_directory) => {
                 Input::WorkingDirectory(&working_directory.working_directory)
             }
-            Either9::I(json_input) => Input::Json {
+            Either12::I(json_input) => Input::Json {
                 json: &json_input.json,
+                format: StructuredFormat::Json,
                 fields: json_input.fields.as_deref(),
                 exclude_fields: json_input.exclude_fields.as_deref(),
             },
+            Either12::J(git_input) => Input::Git {
+                git: &git_input.git,
+                dirty: git_input.dirty.unwrap_or(false),
+            },
+            Either12::K(yaml_input) => Input::Json {
+                json: &yaml_input.yaml,
+                format: StructuredFormat::Yaml,
+                fields: yaml_input.fields.as_deref(),
+                exclude_fields: yaml_input.exclude_fields.as_deref(),
+            },
+            Either12::L(toml_input) => Input::Json {
+                json: &toml_input.toml,
+                format: StructuredFormat::Toml,
+                fields: toml_input.fields.as_deref(),
+                exclude_fields: toml_input.exclude_fields.as_deref(),
+            },
         }
     }
 }
@@ -132,8 +208,10 @@ pub(crate) enum Input<'a> {
     FileSet {
         fileset: &'a str,
         dependencies: bool,
+        /// Whether the files are hashed by their canonical form.
+        semantic: bool,
     },
-    Runtime(&'a str),
+    Runtime(&'a RuntimeInput),
     Environment(&'a str),
     ExternalDependency(&'a [String]),
     DepsOutputs {
@@ -145,9 +223,15 @@ pub(crate) enum Input<'a> {
         input: &'a str,
     },
     WorkingDirectory(&'a str),
+    /// A JSON, YAML or TOML file set, hashed by its parsed contents.
     Json {
         json: &'a str,
+        format: StructuredFormat,
         fields: Option<&'a [String]>,
         exclude_fields: Option<&'a [String]>,
     },
+    Git {
+        git: &'a str,
+        dirty: bool,
+    },
 }
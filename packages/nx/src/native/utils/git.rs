@@ -1,7 +1,7 @@
 use std::path::{Path, PathBuf};
 
 /// Find the nearest git repository root by walking up the directory tree
-fn find_git_root<P: AsRef<Path>>(start_path: P) -> Option<PathBuf> {
+pub fn find_git_root<P: AsRef<Path>>(start_path: P) -> Option<PathBuf> {
     let mut current_path = start_path.as_ref();
 
     loop {
@@ -1,4 +1,12 @@
-import { formatCacheSize, parseMaxCacheSize } from './cache';
+import {
+  formatCacheSize,
+  parseCacheMaxAge,
+  parseMaxCacheSize,
+  resolveCacheEvictionPolicy,
+  resolveCacheOutputLimits,
+  resolveCacheProvenance,
+  resolveMaxTerminalOutputSize,
+} from './cache';
 
 describe('cache', () => {
   describe('parseMaxCacheSize', () => {
@@ -46,6 +54,166 @@ describe('cache', () => {
     });
   });
 
+  describe('parseCacheMaxAge', () => {
+    it('should parse seconds by default', () => {
+      expect(parseCacheMaxAge('30')).toEqual(30);
+      expect(parseCacheMaxAge(30)).toEqual(30);
+      expect(parseCacheMaxAge('30s')).toEqual(30);
+    });
+
+    it('should parse units', () => {
+      expect(parseCacheMaxAge('5m')).toEqual(5 * 60);
+      expect(parseCacheMaxAge('12h')).toEqual(12 * 60 * 60);
+      expect(parseCacheMaxAge('1.5d')).toEqual(36 * 60 * 60);
+      expect(parseCacheMaxAge('2w')).toEqual(14 * 24 * 60 * 60);
+    });
+
+    it('should error if invalid', () => {
+      expect(() => parseCacheMaxAge('7 days')).toThrow();
+      expect(() => parseCacheMaxAge('-1d')).toThrow();
+    });
+  });
+
+  describe('resolveCacheEvictionPolicy', () => {
+    const env = process.env;
+
+    beforeEach(() => {
+      process.env = { ...env };
+      delete process.env.NX_CACHE_EVICTION_STRATEGY;
+      delete process.env.NX_CACHE_MAX_AGE;
+      delete process.env.NX_CACHE_FAILED_MAX_AGE;
+    });
+
+    afterEach(() => {
+      process.env = env;
+    });
+
+    it('should parse the nx.json configuration', () => {
+      expect(
+        resolveCacheEvictionPolicy({
+          cacheEviction: {
+            strategy: 'lfu',
+            maxAge: '30d',
+            failedMaxAge: '1d',
+            projectQuotas: { app: '1GB' },
+          },
+        })
+      ).toEqual({
+        strategy: 'lfu',
+        maxAge: 30 * 24 * 60 * 60,
+        failedMaxAge: 24 * 60 * 60,
+        projectQuotas: { app: 1024 * 1024 * 1024 },
+      });
+    });
+
+    it('should prefer environment variables', () => {
+      process.env.NX_CACHE_EVICTION_STRATEGY = 'lru';
+      process.env.NX_CACHE_MAX_AGE = '1d';
+
+      expect(
+        resolveCacheEvictionPolicy({
+          cacheEviction: { strategy: 'lfu', maxAge: '30d' },
+        })
+      ).toMatchObject({ strategy: 'lru', maxAge: 24 * 60 * 60 });
+    });
+
+    it('should error on unknown strategies', () => {
+      expect(() =>
+        resolveCacheEvictionPolicy({
+          cacheEviction: { strategy: 'fifo' as any },
+        })
+      ).toThrow();
+    });
+  });
+
+  describe('resolveMaxTerminalOutputSize', () => {
+    const env = process.env;
+
+    beforeEach(() => {
+      process.env = { ...env };
+      delete process.env.NX_CACHE_MAX_TERMINAL_OUTPUT_SIZE;
+    });
+
+    afterEach(() => {
+      process.env = env;
+    });
+
+    it('should keep outputs whole by default', () => {
+      expect(resolveMaxTerminalOutputSize({})).toBeUndefined();
+    });
+
+    it('should prefer the environment variable', () => {
+      expect(
+        resolveMaxTerminalOutputSize({ maxTerminalOutputSize: '1MB' })
+      ).toEqual(1024 * 1024);
+      process.env.NX_CACHE_MAX_TERMINAL_OUTPUT_SIZE = '10KB';
+      expect(
+        resolveMaxTerminalOutputSize({ maxTerminalOutputSize: '1MB' })
+      ).toEqual(10 * 1024);
+    });
+  });
+
+  describe('resolveCacheOutputLimits', () => {
+    const env = process.env;
+
+    beforeEach(() => {
+      process.env = { ...env };
+      delete process.env.NX_CACHE_MAX_FILE_SIZE;
+      delete process.env.NX_CACHE_MAX_ENTRY_SIZE;
+    });
+
+    afterEach(() => {
+      process.env = env;
+    });
+
+    it('should not limit outputs by default', () => {
+      expect(resolveCacheOutputLimits({})).toEqual({
+        maxFileSize: undefined,
+        maxEntrySize: undefined,
+      });
+    });
+
+    it('should prefer the environment variables', () => {
+      process.env.NX_CACHE_MAX_ENTRY_SIZE = '2GB';
+      expect(
+        resolveCacheOutputLimits({
+          maxCacheFileSize: '1MB',
+          maxCacheEntrySize: '1GB',
+        })
+      ).toEqual({
+        maxFileSize: 1024 * 1024,
+        maxEntrySize: 2 * 1024 * 1024 * 1024,
+      });
+    });
+  });
+
+  describe('resolveCacheProvenance', () => {
+    const env = process.env;
+
+    beforeEach(() => {
+      process.env = { ...env };
+      delete process.env.NX_CI_EXECUTION_ID;
+      delete process.env.GITHUB_RUN_ID;
+    });
+
+    afterEach(() => {
+      process.env = env;
+    });
+
+    it('should record the Nx version', () => {
+      expect(resolveCacheProvenance().nxVersion).toEqual(
+        require('../../package.json').version
+      );
+    });
+
+    it('should record the CI run', () => {
+      process.env.GITHUB_RUN_ID = '1234';
+      expect(resolveCacheProvenance().ciRunId).toEqual('1234');
+      process.env.NX_CI_EXECUTION_ID = 'abcd';
+      expect(resolveCacheProvenance().ciRunId).toEqual('abcd');
+    });
+  });
+
   describe('formatCacheSize', () => {
     it('should format bytes', () => {
       expect(formatCacheSize(1)).toEqual('1.00 B');
@@ -4,13 +4,22 @@ import { mkdir, readFile, writeFile } from 'node:fs/promises';
 import { join } from 'path';
 import { performance } from 'perf_hooks';
 import { getCurrentMachineId } from '../utils/machine-id-cache';
-import { NxJsonConfiguration, readNxJson } from '../config/nx-json';
+import {
+  NxCacheEvictionConfiguration,
+  NxJsonConfiguration,
+  readNxJson,
+} from '../config/nx-json';
 import { Task } from '../config/task-graph';
 import {
+  CacheEvictionPolicy,
+  CacheOutputLimits,
+  CacheProvenance,
+  EvictionStrategy,
   HttpRemoteCache,
   IS_WASM,
   CachedResult as NativeCacheResult,
   NxCache,
+  SharedFsRemoteCache,
   getDefaultMaxCacheSize,
 } from '../native';
 import {
@@ -20,10 +29,12 @@ import {
 import { getCloudOptions } from '../nx-cloud/utilities/get-cloud-options';
 import { cacheDir } from '../utils/cache-directory';
 import { getDbConnection } from '../utils/db-connection';
+import { getGitCurrentBranch, getLatestCommitSha } from '../utils/git-utils';
 import { isCI } from '../utils/is-ci';
 import { logger } from '../utils/logger';
 import { isNxCloudUsed } from '../utils/nx-cloud-utils';
 import { output } from '../utils/output';
+import { nxVersion } from '../utils/versions';
 import { workspaceRoot } from '../utils/workspace-root';
 import {
   DefaultTasksRunnerOptions,
@@ -76,12 +87,17 @@ export function getCache(options: DefaultTasksRunnerOptions): DbCache | Cache {
 
 export class DbCache {
   private nxJson = readNxJson();
+  private provenance = resolveCacheProvenance();
   private cache = new NxCache(
     workspaceRoot,
     cacheDir,
     getDbConnection(),
     undefined,
-    resolveMaxCacheSize(this.nxJson)
+    resolveMaxCacheSize(this.nxJson),
+    resolveCacheEvictionPolicy(this.nxJson),
+    resolveMaxTerminalOutputSize(this.nxJson),
+    this.provenance,
+    resolveCacheOutputLimits(this.nxJson)
   );
 
   private remoteCache: RemoteCacheV2 | null;
@@ -171,26 +187,31 @@ export class DbCache {
       }
     }
 
-    // 2. Remote: parallel HTTP retrievals for anything the local SQL missed.
-    //    The RemoteCache interface has no batch endpoint, so this is just
-    //    Promise.all over individual retrieve() calls.
+    // 2. Remote: anything the local SQL missed. Caches with a batch lookup
+    //    get every miss at once; for the rest this is Promise.all over
+    //    individual retrieve() calls.
     if (remoteMisses.length > 0) {
-      await Promise.all(
-        remoteMisses.map(async (task) => {
-          const res = await this.remoteCache.retrieve(
-            task.hash,
+      const remoteResults = this.remoteCache.retrieveBatch
+        ? await this.remoteCache.retrieveBatch(
+            remoteMisses.map((task) => task.hash),
             this.cache.cacheDirectory
+          )
+        : await Promise.all(
+            remoteMisses.map((task) =>
+              this.remoteCache.retrieve(task.hash, this.cache.cacheDirectory)
+            )
           );
-          if (res) {
-            this.applyRemoteCacheResults(task.hash, res, task.outputs);
-            results.set(task.hash, {
-              ...res,
-              terminalOutput: res.terminalOutput ?? '',
-              remote: true,
-            });
-          }
-        })
-      );
+      for (const [i, task] of remoteMisses.entries()) {
+        const res = remoteResults[i];
+        if (res) {
+          this.applyRemoteCacheResults(task.hash, res, task.outputs);
+          results.set(task.hash, {
+            ...res,
+            terminalOutput: res.terminalOutput ?? '',
+            remote: true,
+          });
+        }
+      }
     }
 
     return results;
@@ -215,7 +236,7 @@ export class DbCache {
     code: number
   ) {
     return tryAndRetry(async () => {
-      const expandedOutputs = this.cache.put(
+      const { expandedOutputs, skippedFiles, refusedSize } = this.cache.put(
         task.hash,
         terminalOutput,
         outputs,
@@ -225,12 +246,36 @@ export class DbCache {
       // Notify TaskIOService of actual output files
       getTaskIOService().notifyTaskOutputs(task.id, expandedOutputs);
 
+      if (refusedSize !== undefined && refusedSize !== null) {
+        output.warn({
+          title: `Outputs of ${task.id} were not cached`,
+          bodyLines: [
+            `They take up ${formatCacheSize(refusedSize)}, more than the maximum cache entry size.`,
+            'Check the outputs of the target, or raise `maxCacheEntrySize` in nx.json.',
+          ],
+        });
+        return;
+      }
+      if (skippedFiles.length > 0) {
+        output.warn({
+          title: `Some outputs of ${task.id} were left out of the cache`,
+          bodyLines: [
+            'These files are larger than the maximum cached file size:',
+            ...skippedFiles.map(
+              ({ path, size }) => `- ${path} (${formatCacheSize(size)})`
+            ),
+            'Check the outputs of the target, or raise `maxCacheFileSize` in nx.json.',
+          ],
+        });
+      }
+
       if (this.remoteCache) {
         await this.remoteCache.store(
           task.hash,
           this.cache.cacheDirectory,
           terminalOutput,
-          code
+          code,
+          this.provenance
         );
       }
     });
@@ -304,6 +349,7 @@ export class DbCache {
       (await this.getGcsCache()) ??
       (await this.getAzureCache()) ??
       this.getHttpCache() ??
+      this.getSharedFsCache() ??
       null
     );
   }
@@ -353,6 +399,19 @@ export class DbCache {
     return null;
   }
 
+  private getSharedFsCache(): RemoteCacheV2 | null {
+    if (process.env.NX_SHARED_FS_REMOTE_CACHE_DIRECTORY) {
+      if (IS_WASM) {
+        logger.warn(
+          'The shared filesystem remote cache is not yet supported in the wasm build of Nx.'
+        );
+        return null;
+      }
+      return new SharedFsRemoteCache();
+    }
+    return null;
+  }
+
   private async resolveRemoteCache(pkg: string): Promise<RemoteCacheV2 | null> {
     let getRemoteCache = null;
     try {
@@ -703,6 +762,158 @@ export function resolveMaxCacheSize(nxJson: NxJsonConfiguration): number {
     : getDefaultMaxCacheSize(cacheDir);
 }
 
+/**
+ * Resolves how entries are removed from the local cache from environment
+ * variables and the `cacheEviction` configuration in nx.json.
+ *
+ * @param nxJson The nx.json configuration object
+ */
+export function resolveCacheEvictionPolicy(
+  nxJson: NxJsonConfiguration
+): CacheEvictionPolicy {
+  const config: NxCacheEvictionConfiguration = nxJson.cacheEviction ?? {};
+  const strategy =
+    process.env.NX_CACHE_EVICTION_STRATEGY ?? config.strategy ?? 'lru';
+  if (strategy !== 'lru' && strategy !== 'lfu') {
+    throw new Error(
+      `Invalid cache eviction strategy: ${strategy}. Must be one of: lru, lfu`
+    );
+  }
+  const maxAge = process.env.NX_CACHE_MAX_AGE ?? config.maxAge;
+  const failedMaxAge =
+    process.env.NX_CACHE_FAILED_MAX_AGE ?? config.failedMaxAge;
+  return {
+    strategy: strategy === 'lfu' ? EvictionStrategy.lfu : EvictionStrategy.lru,
+    maxAge: parseCacheMaxAge(maxAge),
+    failedMaxAge: parseCacheMaxAge(failedMaxAge),
+    projectQuotas: config.projectQuotas
+      ? Object.fromEntries(
+          Object.entries(config.projectQuotas).map(([project, quota]) => [
+            project,
+            parseMaxCacheSize(quota),
+          ])
+        )
+      : undefined,
+  };
+}
+
+/**
+ * Resolves the size above which terminal outputs stored in the local cache
+ * are truncated, from the environment or nx.json configuration.
+ *
+ * @param nxJson The nx.json configuration object
+ * @returns The resolved size in bytes, or undefined to keep outputs whole
+ */
+export function resolveMaxTerminalOutputSize(
+  nxJson: NxJsonConfiguration
+): number | undefined {
+  return parseMaxCacheSize(
+    process.env.NX_CACHE_MAX_TERMINAL_OUTPUT_SIZE ??
+      nxJson.maxTerminalOutputSize
+  );
+}
+
+/**
+ * Resolves the size limits of the outputs stored in the local cache, from
+ * the environment or nx.json configuration.
+ *
+ * @param nxJson The nx.json configuration object
+ * @returns The resolved limits in bytes, unset to store outputs of any size
+ */
+export function resolveCacheOutputLimits(
+  nxJson: NxJsonConfiguration
+): CacheOutputLimits {
+  return {
+    maxFileSize: parseMaxCacheSize(
+      process.env.NX_CACHE_MAX_FILE_SIZE ?? nxJson.maxCacheFileSize
+    ),
+    maxEntrySize: parseMaxCacheSize(
+      process.env.NX_CACHE_MAX_ENTRY_SIZE ?? nxJson.maxCacheEntrySize
+    ),
+  };
+}
+
+// The first of these that is set identifies the CI run a task ran in.
+const CI_RUN_ID_ENV_VARS = [
+  'NX_CI_EXECUTION_ID',
+  'GITHUB_RUN_ID',
+  'CIRCLE_WORKFLOW_ID',
+  'BUILD_BUILDID',
+  'CI_PIPELINE_ID',
+  'BUILDKITE_BUILD_ID',
+  'CODEBUILD_BUILD_ID',
+  'BITBUCKET_BUILD_NUMBER',
+  'TRAVIS_BUILD_ID',
+];
+
+// CI providers usually check out a detached HEAD, leaving the branch to be
+// read from their environment.
+const CI_BRANCH_ENV_VARS = [
+  'GITHUB_HEAD_REF',
+  'GITHUB_REF_NAME',
+  'CIRCLE_BRANCH',
+  'BUILD_SOURCEBRANCHNAME',
+  'CI_COMMIT_REF_NAME',
+  'BUILDKITE_BRANCH',
+  'BITBUCKET_BRANCH',
+  'TRAVIS_BRANCH',
+  'BRANCH_NAME',
+];
+
+function firstEnvVar(names: string[]): string | undefined {
+  return names.map((name) => process.env[name]).find((value) => !!value);
+}
+
+/**
+ * Resolves where the entries stored in the cache by this process are
+ * produced. The machine id is filled in by the native cache.
+ *
+ * @returns The git commit and branch, Nx version and CI run of this process
+ */
+export function resolveCacheProvenance(): CacheProvenance {
+  return {
+    gitCommit: getLatestCommitSha(workspaceRoot) ?? undefined,
+    gitBranch:
+      getGitCurrentBranch(workspaceRoot) ?? firstEnvVar(CI_BRANCH_ENV_VARS),
+    nxVersion,
+    ciRunId: firstEnvVar(CI_RUN_ID_ENV_VARS),
+  };
+}
+
+/**
+ * Converts a duration to a number of seconds.
+ *
+ * e.g. '30' -> 30
+ *      '12h' -> 12 * 60 * 60
+ *      '7d' -> 7 * 24 * 60 * 60
+ *
+ * @param maxAge Max age of cache entries as specified in nx.json
+ */
+export function parseCacheMaxAge(
+  maxAge: string | number
+): number | undefined {
+  if (maxAge === null || maxAge === undefined) {
+    return undefined;
+  }
+  const regexResult = maxAge
+    .toString()
+    .match(/^(?<age>\d+(\.\d+)?)\s?(?<unit>[smhdw]?)$/);
+  if (!regexResult) {
+    throw new Error(
+      `Invalid cache max age: ${maxAge}. Must be a number followed by an optional unit (s, m, h, d, w)`
+    );
+  }
+  const seconds = {
+    '': 1,
+    s: 1,
+    m: 60,
+    h: 60 * 60,
+    d: 24 * 60 * 60,
+    w: 7 * 24 * 60 * 60,
+  }[regexResult.groups.unit];
+  return Math.round(parseFloat(regexResult.groups.age) * seconds);
+}
+
 /**
  * Converts a string representation of a max cache size to a number.
  *
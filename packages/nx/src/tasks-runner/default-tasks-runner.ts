@@ -11,7 +11,7 @@ import { DaemonClient } from '../daemon/client/client';
 import { cacheDir } from '../utils/cache-directory';
 import { readFile, writeFile, mkdir, rename, readdir } from 'fs/promises';
 import { join } from 'path';
-import { CachedResult } from '../native';
+import { CachedResult, CacheProvenance } from '../native';
 
 export interface RemoteCache {
   retrieve: (hash: string, cacheDirectory: string) => Promise<boolean>;
@@ -88,8 +88,17 @@ export abstract class RemoteCacheV2 {
     hash: string,
     cacheDirectory: string,
     terminalOutput: string,
-    code: number
+    code: number,
+    provenance?: CacheProvenance
   ): Promise<boolean>;
+  /**
+   * Retrieves many hashes at once, index-aligned with `hashes`. Remote caches
+   * that implement it save a round-trip per task when looking up a batch.
+   */
+  retrieveBatch?(
+    hashes: string[],
+    cacheDirectory: string
+  ): Promise<Array<CachedResult | null | undefined>>;
 }
 
 export interface DefaultTasksRunnerOptions {